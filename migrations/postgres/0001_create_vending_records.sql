@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS vending_records (
+    id TEXT PRIMARY KEY,
+    timestamp TIMESTAMPTZ NOT NULL,
+    meter_number TEXT,
+    address TEXT,
+    community TEXT,
+    customer_name TEXT,
+    token TEXT,
+    tariff DOUBLE PRECISION,
+    amount DOUBLE PRECISION,
+    kwh DOUBLE PRECISION,
+    user_id TEXT,
+    vending_station TEXT,
+    fixed_charge DOUBLE PRECISION,
+    transaction_id TEXT,
+    remaining_credit DOUBLE PRECISION
+);
+
+CREATE INDEX IF NOT EXISTS vending_records_timestamp_idx ON vending_records (timestamp);
+CREATE INDEX IF NOT EXISTS vending_records_meter_timestamp_idx ON vending_records (meter_number, timestamp);
+CREATE INDEX IF NOT EXISTS vending_records_station_timestamp_idx ON vending_records (vending_station, timestamp);
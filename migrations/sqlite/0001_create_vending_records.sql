@@ -0,0 +1,22 @@
+-- Timestamps are stored as UTC milliseconds since the Unix epoch
+CREATE TABLE IF NOT EXISTS vending_records (
+    id TEXT PRIMARY KEY,
+    timestamp_ms INTEGER NOT NULL,
+    meter_number TEXT,
+    address TEXT,
+    community TEXT,
+    customer_name TEXT,
+    token TEXT,
+    tariff REAL,
+    amount REAL,
+    kwh REAL,
+    user_id TEXT,
+    vending_station TEXT,
+    fixed_charge REAL,
+    transaction_id TEXT,
+    remaining_credit REAL
+);
+
+CREATE INDEX IF NOT EXISTS vending_records_timestamp_idx ON vending_records (timestamp_ms);
+CREATE INDEX IF NOT EXISTS vending_records_meter_timestamp_idx ON vending_records (meter_number, timestamp_ms);
+CREATE INDEX IF NOT EXISTS vending_records_station_timestamp_idx ON vending_records (vending_station, timestamp_ms);
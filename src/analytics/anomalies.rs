@@ -0,0 +1,584 @@
+use crate::model::{
+    AnomalyFlag, AnomalyKind, AnomalySeverity, MeterAmountBaseline, StationOpeningHours,
+    VendingRecord,
+};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use std::collections::HashMap;
+
+// Vends needed in a meter's baseline before its amounts are judged against it
+const MIN_BASELINE_PURCHASES: u32 = 5;
+const UNUSUAL_AMOUNT_Z: f64 = 3.0;
+const EXTREME_AMOUNT_Z: f64 = 5.0;
+// Several tokens for one meter within this window suggest a split or duplicated sale
+const REPEATED_VEND_WINDOW_MINUTES: i64 = 60;
+const REPEATED_VEND_THRESHOLD: usize = 3;
+// Relative difference tolerated between amount and kWh * tariff (+ fixed charge)
+pub const TARIFF_TOLERANCE: f64 = 0.02;
+const TARIFF_HIGH_DEVIATION: f64 = 0.10;
+
+/// Inputs shared by every rule of an anomaly scan
+pub struct AnomalyContext<'a> {
+    pub baselines: &'a HashMap<String, MeterAmountBaseline>,
+    pub opening_hours: &'a HashMap<String, StationOpeningHours>,
+    pub detected_at: DateTime<Utc>,
+}
+
+fn flag(
+    record: &VendingRecord,
+    kind: AnomalyKind,
+    severity: AnomalySeverity,
+    details: String,
+    detected_at: DateTime<Utc>,
+) -> AnomalyFlag {
+    AnomalyFlag {
+        record_id: record.id.clone(),
+        kind,
+        severity,
+        timestamp: record.timestamp,
+        meter_number: record.meter_number.clone(),
+        vending_station: record.vending_station.clone(),
+        community: record.community.clone(),
+        user_id: record.user_id.clone(),
+        amount: record.amount,
+        details,
+        detected_at,
+    }
+}
+
+// All meters' baselines pooled into one, for meters with too little history of their own
+fn pooled_baseline(
+    baselines: &HashMap<String, MeterAmountBaseline>,
+) -> Option<MeterAmountBaseline> {
+    let purchase_count: u32 = baselines
+        .values()
+        .map(|baseline| baseline.purchase_count)
+        .sum();
+    if purchase_count < MIN_BASELINE_PURCHASES {
+        return None;
+    }
+
+    let count = purchase_count as f64;
+    let mut sum = 0.0;
+    let mut sum_of_squares = 0.0;
+    for baseline in baselines.values() {
+        let n = baseline.purchase_count as f64;
+        sum += n * baseline.mean_amount;
+        sum_of_squares += n * (baseline.std_dev_amount.powi(2) + baseline.mean_amount.powi(2));
+    }
+    let mean_amount = sum / count;
+    Some(MeterAmountBaseline {
+        meter_number: String::new(),
+        purchase_count,
+        mean_amount,
+        std_dev_amount: (sum_of_squares / count - mean_amount.powi(2))
+            .max(0.0)
+            .sqrt(),
+    })
+}
+
+fn unusual_amount(
+    record: &VendingRecord,
+    context: &AnomalyContext,
+    pooled: Option<&MeterAmountBaseline>,
+) -> Option<AnomalyFlag> {
+    let amount = record.amount?;
+    let meter_baseline = record
+        .meter_number
+        .as_ref()
+        .and_then(|meter_number| context.baselines.get(meter_number))
+        .filter(|baseline| baseline.purchase_count >= MIN_BASELINE_PURCHASES);
+    // Meters with little history, such as a friend's meter topped up once with a large amount,
+    // are judged against all meters instead
+    let (baseline, compared_with) = match meter_baseline {
+        Some(baseline) => (baseline, "the meter's"),
+        None => (pooled?, "all meters'"),
+    };
+
+    // A meter that always buys the same amount has no spread; treat doubling it as unusual
+    let z = if baseline.std_dev_amount > 0.0 {
+        (amount - baseline.mean_amount) / baseline.std_dev_amount
+    } else if amount > 2.0 * baseline.mean_amount {
+        UNUSUAL_AMOUNT_Z
+    } else {
+        0.0
+    };
+    if z < UNUSUAL_AMOUNT_Z {
+        return None;
+    }
+
+    let severity = if z >= EXTREME_AMOUNT_Z {
+        AnomalySeverity::High
+    } else {
+        AnomalySeverity::Medium
+    };
+    Some(flag(
+        record,
+        AnomalyKind::UnusualAmount,
+        severity,
+        format!(
+            "Amount {:.2} is {:.1} standard deviations above {} mean of {:.2} over {} vends",
+            amount, z, compared_with, baseline.mean_amount, baseline.purchase_count
+        ),
+        context.detected_at,
+    ))
+}
+
+fn outside_opening_hours(record: &VendingRecord, context: &AnomalyContext) -> Option<AnomalyFlag> {
+    let hours = context
+        .opening_hours
+        .get(record.vending_station.as_ref()?)?;
+    let timezone = hours.timezone.parse::<chrono_tz::Tz>().ok()?;
+    let opens_at = NaiveTime::parse_from_str(&hours.opens_at, "%H:%M").ok()?;
+    let closes_at = NaiveTime::parse_from_str(&hours.closes_at, "%H:%M").ok()?;
+    let local_time = record.timestamp.with_timezone(&timezone).time();
+
+    let is_open = if opens_at <= closes_at {
+        local_time >= opens_at && local_time < closes_at
+    } else {
+        // Overnight opening hours, e.g. 18:00 to 02:00
+        local_time >= opens_at || local_time < closes_at
+    };
+    if is_open {
+        return None;
+    }
+
+    Some(flag(
+        record,
+        AnomalyKind::OutsideOpeningHours,
+        AnomalySeverity::Medium,
+        format!(
+            "Vended at {} local time; station hours are {} to {} ({})",
+            local_time.format("%H:%M"),
+            hours.opens_at,
+            hours.closes_at,
+            hours.timezone
+        ),
+        context.detected_at,
+    ))
+}
+
+fn tariff_mismatch(record: &VendingRecord, context: &AnomalyContext) -> Option<AnomalyFlag> {
+    let amount = record.amount?;
+    let kwh = record.kwh?;
+    let tariff = record.tariff?;
+    if amount <= 0.0 {
+        return None;
+    }
+
+    // Terminals differ in whether the fixed charge is part of the amount, so accept either
+    let energy_cost = kwh * tariff;
+    let deviation = [
+        energy_cost,
+        energy_cost + record.fixed_charge.unwrap_or(0.0),
+    ]
+    .iter()
+    .map(|expected| (amount - expected).abs() / amount)
+    .fold(f64::INFINITY, f64::min);
+    if deviation <= TARIFF_TOLERANCE {
+        return None;
+    }
+
+    let severity = if deviation >= TARIFF_HIGH_DEVIATION {
+        AnomalySeverity::High
+    } else {
+        AnomalySeverity::Low
+    };
+    Some(flag(
+        record,
+        AnomalyKind::TariffMismatch,
+        severity,
+        format!(
+            "Amount {:.2} does not match {:.3} kWh at tariff {:.4} (fixed charge {:.2}); off by {:.1}%",
+            amount,
+            kwh,
+            tariff,
+            record.fixed_charge.unwrap_or(0.0),
+            deviation * 100.0
+        ),
+        context.detected_at,
+    ))
+}
+
+// Flag every vend that completes a burst of tokens for the same meter
+fn repeated_vends(records: &[VendingRecord], context: &AnomalyContext) -> Vec<AnomalyFlag> {
+    let mut by_meter: HashMap<&str, Vec<&VendingRecord>> = HashMap::new();
+    for record in records {
+        if let Some(meter_number) = record.meter_number.as_deref() {
+            by_meter.entry(meter_number).or_default().push(record);
+        }
+    }
+
+    let window = Duration::minutes(REPEATED_VEND_WINDOW_MINUTES);
+    let mut flags = Vec::new();
+    for vends in by_meter.values_mut() {
+        vends.sort_by_key(|record| record.timestamp);
+        let mut window_start = 0;
+        for (index, record) in vends.iter().enumerate() {
+            while record.timestamp - vends[window_start].timestamp > window {
+                window_start += 1;
+            }
+            let count = index - window_start + 1;
+            if count >= REPEATED_VEND_THRESHOLD {
+                let severity = if count >= 2 * REPEATED_VEND_THRESHOLD {
+                    AnomalySeverity::High
+                } else {
+                    AnomalySeverity::Medium
+                };
+                flags.push(flag(
+                    record,
+                    AnomalyKind::RepeatedVends,
+                    severity,
+                    format!(
+                        "{} tokens for this meter within {} minutes",
+                        count, REPEATED_VEND_WINDOW_MINUTES
+                    ),
+                    context.detected_at,
+                ));
+            }
+        }
+    }
+    flags
+}
+
+/// Run every anomaly rule over the records
+pub fn detect_anomalies(records: &[VendingRecord], context: &AnomalyContext) -> Vec<AnomalyFlag> {
+    let pooled = pooled_baseline(context.baselines);
+    let mut flags: Vec<AnomalyFlag> = records
+        .iter()
+        .flat_map(|record| {
+            [
+                unusual_amount(record, context, pooled.as_ref()),
+                outside_opening_hours(record, context),
+                tariff_mismatch(record, context),
+            ]
+        })
+        .flatten()
+        .collect();
+    flags.extend(repeated_vends(records, context));
+    flags.sort_by_key(|flag| flag.timestamp);
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    // A vend of `amount` at tariff 0.5 with no fixed charge, so the tariff rule stays quiet
+    fn vend(id: &str, timestamp: &str, meter: &str, amount: f64) -> VendingRecord {
+        VendingRecord {
+            id: id.to_string(),
+            timestamp: at(timestamp),
+            meter_number: Some(meter.to_string()),
+            address: None,
+            community: Some("North".to_string()),
+            customer_name: None,
+            token: None,
+            tariff: Some(0.5),
+            amount: Some(amount),
+            kwh: Some(amount * 2.0),
+            user_id: Some("u1".to_string()),
+            vending_station: Some("Central".to_string()),
+            fixed_charge: None,
+            transaction_id: None,
+            remaining_credit: None,
+        }
+    }
+
+    fn baseline(meter: &str, purchase_count: u32, mean: f64, std_dev: f64) -> MeterAmountBaseline {
+        MeterAmountBaseline {
+            meter_number: meter.to_string(),
+            purchase_count,
+            mean_amount: mean,
+            std_dev_amount: std_dev,
+        }
+    }
+
+    fn hours(opens_at: &str, closes_at: &str, timezone: &str) -> StationOpeningHours {
+        StationOpeningHours {
+            vending_station: "Central".to_string(),
+            opens_at: opens_at.to_string(),
+            closes_at: closes_at.to_string(),
+            timezone: timezone.to_string(),
+        }
+    }
+
+    fn detect(
+        records: &[VendingRecord],
+        baselines: Vec<MeterAmountBaseline>,
+        opening_hours: Option<StationOpeningHours>,
+    ) -> Vec<AnomalyFlag> {
+        let baselines = baselines
+            .into_iter()
+            .map(|baseline| (baseline.meter_number.clone(), baseline))
+            .collect();
+        let opening_hours = opening_hours
+            .into_iter()
+            .map(|hours| (hours.vending_station.clone(), hours))
+            .collect();
+        detect_anomalies(
+            records,
+            &AnomalyContext {
+                baselines: &baselines,
+                opening_hours: &opening_hours,
+                detected_at: at("2025-02-01T00:00:00Z"),
+            },
+        )
+    }
+
+    fn severities(flags: &[AnomalyFlag], kind: AnomalyKind) -> Vec<(String, AnomalySeverity)> {
+        flags
+            .iter()
+            .filter(|flag| flag.kind == kind)
+            .map(|flag| (flag.record_id.clone(), flag.severity))
+            .collect()
+    }
+
+    fn amount_severity(
+        amount: f64,
+        baselines: Vec<MeterAmountBaseline>,
+    ) -> Option<AnomalySeverity> {
+        let flags = detect(
+            &[vend("v", "2025-01-10T10:00:00Z", "M-1", amount)],
+            baselines,
+            None,
+        );
+        severities(&flags, AnomalyKind::UnusualAmount)
+            .first()
+            .map(|(_, severity)| *severity)
+    }
+
+    #[test]
+    fn unusual_amount_z_score_boundaries() {
+        let meter = || vec![baseline("M-1", 10, 10.0, 2.0)];
+        assert_eq!(amount_severity(15.9, meter()), None);
+        assert_eq!(
+            amount_severity(16.0, meter()),
+            Some(AnomalySeverity::Medium)
+        );
+        assert_eq!(
+            amount_severity(19.9, meter()),
+            Some(AnomalySeverity::Medium)
+        );
+        assert_eq!(amount_severity(20.0, meter()), Some(AnomalySeverity::High));
+        // Unusually small amounts are not flagged
+        assert_eq!(amount_severity(0.5, meter()), None);
+    }
+
+    #[test]
+    fn unusual_amount_without_spread_flags_more_than_double() {
+        let meter = || vec![baseline("M-1", 8, 10.0, 0.0)];
+        assert_eq!(amount_severity(20.0, meter()), None);
+        assert_eq!(
+            amount_severity(20.5, meter()),
+            Some(AnomalySeverity::Medium)
+        );
+        assert_eq!(
+            amount_severity(500.0, meter()),
+            Some(AnomalySeverity::Medium)
+        );
+    }
+
+    #[test]
+    fn unusual_amount_judges_low_history_meters_against_all_meters() {
+        // Two meters of 10 vends each: all 20 vends have mean 15 and standard deviation 5
+        let others = || {
+            vec![
+                baseline("M-2", 10, 10.0, 0.0),
+                baseline("M-3", 10, 20.0, 0.0),
+            ]
+        };
+        let low_history = || {
+            let mut baselines = others();
+            baselines.push(baseline("M-1", 2, 5.0, 0.0));
+            baselines
+        };
+
+        // Unknown meter, and a meter whose own 2 vends would make 20 look huge
+        assert_eq!(amount_severity(29.0, others()), None);
+        assert_eq!(
+            amount_severity(30.0, others()),
+            Some(AnomalySeverity::Medium)
+        );
+        assert_eq!(amount_severity(29.0, low_history()), None);
+        assert_eq!(
+            amount_severity(45.0, low_history()),
+            Some(AnomalySeverity::High)
+        );
+
+        let flags = detect(
+            &[vend("v", "2025-01-10T10:00:00Z", "M-1", 40.0)],
+            others(),
+            None,
+        );
+        assert!(
+            flags[0]
+                .details
+                .contains("above all meters' mean of 15.00 over 20 vends")
+        );
+    }
+
+    #[test]
+    fn unusual_amount_needs_some_history() {
+        // Too few vends overall to judge anything
+        assert_eq!(amount_severity(1000.0, vec![]), None);
+        assert_eq!(
+            amount_severity(1000.0, vec![baseline("M-2", 4, 10.0, 1.0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn opening_hours_in_station_timezone() {
+        // 08:00 to 17:00 in Lagos is 07:00 to 16:00 UTC
+        let records = [
+            vend("early", "2025-01-10T06:59:00Z", "M-1", 10.0),
+            vend("opening", "2025-01-10T07:00:00Z", "M-2", 10.0),
+            vend("late", "2025-01-10T15:59:00Z", "M-3", 10.0),
+            vend("closing", "2025-01-10T16:00:00Z", "M-4", 10.0),
+        ];
+        let flags = detect(
+            &records,
+            vec![],
+            Some(hours("08:00", "17:00", "Africa/Lagos")),
+        );
+        assert_eq!(
+            severities(&flags, AnomalyKind::OutsideOpeningHours),
+            vec![
+                ("early".to_string(), AnomalySeverity::Medium),
+                ("closing".to_string(), AnomalySeverity::Medium),
+            ]
+        );
+        assert!(flags[0].details.contains("07:59 local time"));
+    }
+
+    #[test]
+    fn overnight_opening_hours_span_midnight() {
+        let records = [
+            vend("before", "2025-01-10T17:59:00Z", "M-1", 10.0),
+            vend("evening", "2025-01-10T18:00:00Z", "M-2", 10.0),
+            vend("midnight", "2025-01-11T00:00:00Z", "M-3", 10.0),
+            vend("night", "2025-01-11T01:59:00Z", "M-4", 10.0),
+            vend("closed", "2025-01-11T02:00:00Z", "M-5", 10.0),
+        ];
+        let flags = detect(&records, vec![], Some(hours("18:00", "02:00", "UTC")));
+        let flagged: Vec<String> = severities(&flags, AnomalyKind::OutsideOpeningHours)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(flagged, vec!["before", "closed"]);
+    }
+
+    #[test]
+    fn stations_without_valid_hours_are_not_checked() {
+        let records = [vend("v", "2025-01-10T03:00:00Z", "M-1", 10.0)];
+        assert!(detect(&records, vec![], None).is_empty());
+        assert!(detect(&records, vec![], Some(hours("8am", "17:00", "UTC"))).is_empty());
+        assert!(
+            detect(
+                &records,
+                vec![],
+                Some(hours("08:00", "17:00", "Mars/Olympus"))
+            )
+            .is_empty()
+        );
+    }
+
+    fn tariff_severity(
+        amount: f64,
+        kwh: f64,
+        fixed_charge: Option<f64>,
+    ) -> Option<AnomalySeverity> {
+        let mut record = vend("v", "2025-01-10T10:00:00Z", "M-1", amount);
+        record.kwh = Some(kwh);
+        record.fixed_charge = fixed_charge;
+        severities(
+            &detect(&[record], vec![], None),
+            AnomalyKind::TariffMismatch,
+        )
+        .first()
+        .map(|(_, severity)| *severity)
+    }
+
+    #[test]
+    fn tariff_check_with_and_without_fixed_charge() {
+        // 200 kWh at 0.5 costs 100
+        assert_eq!(tariff_severity(100.0, 200.0, None), None);
+        assert_eq!(tariff_severity(102.0, 200.0, None), None);
+        assert_eq!(
+            tariff_severity(103.0, 200.0, None),
+            Some(AnomalySeverity::Low)
+        );
+        assert_eq!(
+            tariff_severity(112.0, 200.0, None),
+            Some(AnomalySeverity::High)
+        );
+        // The fixed charge may or may not be part of the amount
+        assert_eq!(tariff_severity(105.0, 200.0, Some(5.0)), None);
+        assert_eq!(tariff_severity(100.0, 200.0, Some(5.0)), None);
+        assert_eq!(
+            tariff_severity(108.0, 200.0, Some(5.0)),
+            Some(AnomalySeverity::Low)
+        );
+        // Refunds and incomplete records are not checked
+        assert_eq!(tariff_severity(-100.0, 100.0, None), None);
+        let mut record = vend("v", "2025-01-10T10:00:00Z", "M-1", 100.0);
+        record.tariff = None;
+        assert!(detect(&[record], vec![], None).is_empty());
+    }
+
+    fn repeated(minutes: &[i64]) -> Vec<(String, AnomalySeverity)> {
+        let start = at("2025-01-10T10:00:00Z");
+        let records: Vec<VendingRecord> = minutes
+            .iter()
+            .enumerate()
+            .map(|(index, minute)| {
+                let mut record = vend(&format!("v{}", index), "2025-01-10T10:00:00Z", "M-1", 10.0);
+                record.timestamp = start + Duration::minutes(*minute);
+                record
+            })
+            .collect();
+        severities(&detect(&records, vec![], None), AnomalyKind::RepeatedVends)
+    }
+
+    #[test]
+    fn repeated_vends_flag_each_vend_completing_a_burst() {
+        assert!(repeated(&[0, 10]).is_empty());
+        assert_eq!(
+            repeated(&[0, 10, 20]),
+            vec![("v2".to_string(), AnomalySeverity::Medium)]
+        );
+        // Exactly 60 minutes apart still counts as within the window
+        assert_eq!(repeated(&[0, 30, 60]).len(), 1);
+        assert!(repeated(&[0, 30, 61]).is_empty());
+        // The window slides, so the count drops once early vends fall out of it
+        assert!(repeated(&[0, 50, 110]).is_empty());
+        assert_eq!(
+            repeated(&[0, 1, 2, 3, 4, 5]),
+            vec![
+                ("v2".to_string(), AnomalySeverity::Medium),
+                ("v3".to_string(), AnomalySeverity::Medium),
+                ("v4".to_string(), AnomalySeverity::Medium),
+                ("v5".to_string(), AnomalySeverity::High),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_vends_are_counted_per_meter() {
+        let mut records = vec![
+            vend("a", "2025-01-10T10:00:00Z", "M-1", 10.0),
+            vend("b", "2025-01-10T10:05:00Z", "M-2", 10.0),
+            vend("c", "2025-01-10T10:10:00Z", "M-1", 10.0),
+        ];
+        assert!(detect(&records, vec![], None).is_empty());
+        records.push(vend("d", "2025-01-10T10:15:00Z", "M-1", 10.0));
+        assert_eq!(
+            severities(&detect(&records, vec![], None), AnomalyKind::RepeatedVends),
+            vec![("d".to_string(), AnomalySeverity::Medium)]
+        );
+    }
+}
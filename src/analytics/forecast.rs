@@ -0,0 +1,208 @@
+// Smoothing parameter values tried when fitting; 0.05 to 0.95 in steps of 0.1
+const PARAMETER_GRID: [f64; 10] = [0.05, 0.15, 0.25, 0.35, 0.45, 0.55, 0.65, 0.75, 0.85, 0.95];
+
+/// Point forecasts and prediction intervals from an additive Holt-Winters model
+#[derive(Debug)]
+pub struct HoltWintersForecast {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub rmse: f64, // Root mean squared one-step-ahead error over the fitted history
+    pub points: Vec<f64>,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+struct Fit {
+    sse: f64,
+    fitted_steps: usize,
+    level: f64,
+    trend: f64,
+    seasonals: Vec<f64>,
+}
+
+impl Fit {
+    // Forecast `h` steps past the `n` observations the state was fitted on
+    fn forecast(&self, n: usize, h: usize) -> f64 {
+        self.level + h as f64 * self.trend + self.seasonals[(n + h - 1) % self.seasonals.len()]
+    }
+}
+
+// Weight of a one-step error in the error j steps later. The recursions use the classical
+// smoothing parameters; in ETS(A,A,A) terms the seasonal one is (1 - alpha) * gamma.
+fn error_weight(alpha: f64, beta: f64, gamma: f64, season: usize, j: usize) -> f64 {
+    let seasonal = if j.is_multiple_of(season) {
+        (1.0 - alpha) * gamma
+    } else {
+        0.0
+    };
+    alpha * (1.0 + j as f64 * beta) + seasonal
+}
+
+// Run the additive Holt-Winters recursions over the series with fixed parameters
+fn fit(series: &[f64], season: usize, alpha: f64, beta: f64, gamma: f64) -> Fit {
+    // Initial state from the first two seasons
+    let first_mean = series[..season].iter().sum::<f64>() / season as f64;
+    let second_mean = series[season..2 * season].iter().sum::<f64>() / season as f64;
+    let mut level = first_mean;
+    let mut trend = (second_mean - first_mean) / season as f64;
+    let mut seasonals: Vec<f64> = series[..season].iter().map(|y| y - first_mean).collect();
+
+    let mut sse = 0.0;
+    let mut fitted_steps = 0;
+    for (t, &y) in series.iter().enumerate() {
+        let index = t % season;
+        let error = y - (level + trend + seasonals[index]);
+        // The first season seeded the state, so its errors say nothing about the fit
+        if t >= season {
+            sse += error * error;
+            fitted_steps += 1;
+        }
+
+        let previous_level = level;
+        level = alpha * (y - seasonals[index]) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous_level) + (1.0 - beta) * trend;
+        seasonals[index] = gamma * (y - level) + (1.0 - gamma) * seasonals[index];
+    }
+
+    Fit {
+        sse,
+        fitted_steps,
+        level,
+        trend,
+        seasonals,
+    }
+}
+
+/// Fit an additive Holt-Winters model by grid search and forecast `horizon` steps ahead.
+///
+/// `z` is the standard normal quantile of the prediction interval (1.96 for 95%).
+/// Returns `None` when the series is shorter than two seasons.
+pub fn holt_winters_forecast(
+    series: &[f64],
+    season: usize,
+    horizon: usize,
+    z: f64,
+) -> Option<HoltWintersForecast> {
+    if season == 0 || series.len() < 2 * season {
+        return None;
+    }
+
+    let mut best: Option<(f64, f64, f64, Fit)> = None;
+    for &alpha in &PARAMETER_GRID {
+        for &beta in &PARAMETER_GRID {
+            for &gamma in &PARAMETER_GRID {
+                let candidate = fit(series, season, alpha, beta, gamma);
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, _, _, best_fit)| candidate.sse < best_fit.sse)
+                {
+                    best = Some((alpha, beta, gamma, candidate));
+                }
+            }
+        }
+    }
+    let (alpha, beta, gamma, fitted) = best?;
+
+    let sigma_squared = fitted.sse / fitted.fitted_steps.max(1) as f64;
+    let n = series.len();
+
+    let mut points = Vec::with_capacity(horizon);
+    let mut lower = Vec::with_capacity(horizon);
+    let mut upper = Vec::with_capacity(horizon);
+    // Variance grows with the horizon as in the ETS(A,A,A) state space form:
+    // var_h = sigma^2 * (1 + sum_{j<h} c_j^2)
+    let mut cumulative = 0.0;
+    for h in 1..=horizon {
+        let point = fitted.forecast(n, h);
+        let half_width = z * (sigma_squared * (1.0 + cumulative)).sqrt();
+        points.push(point);
+        lower.push(point - half_width);
+        upper.push(point + half_width);
+
+        let c = error_weight(alpha, beta, gamma, season, h);
+        cumulative += c * c;
+    }
+
+    Some(HoltWintersForecast {
+        alpha,
+        beta,
+        gamma,
+        rmse: sigma_squared.sqrt(),
+        points,
+        lower,
+        upper,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEASON: usize = 7;
+
+    fn weekly_series(days: usize) -> Vec<f64> {
+        (0..days)
+            .map(|t| 100.0 + 0.5 * t as f64 + [5.0, -3.0, 2.0, 8.0, -6.0, -4.0, -2.0][t % SEASON])
+            .map(|y| y + ((y * 12.9898).sin() * 43758.5453).fract() * 4.0) // Fixed pseudo-noise
+            .collect()
+    }
+
+    #[test]
+    fn error_weights_match_the_ets_reference_values() {
+        // alpha = 0.5, beta* = 0.2, gamma* = 0.3, m = 4, so gamma = (1 - 0.5) * 0.3 = 0.15
+        // (Hyndman et al., Forecasting with Exponential Smoothing, table 6.1, class 1)
+        let weights: Vec<f64> = (1..=5).map(|j| error_weight(0.5, 0.2, 0.3, 4, j)).collect();
+        let expected = [0.6, 0.7, 0.8, 1.05, 1.0];
+        for (weight, expected) in weights.iter().zip(expected) {
+            assert!((weight - expected).abs() < 1e-12, "{weights:?}");
+        }
+    }
+
+    #[test]
+    fn error_weights_match_how_a_shock_moves_later_forecasts() {
+        let series = weekly_series(60);
+        let (alpha, beta, gamma) = (0.35, 0.15, 0.45);
+        let n = series.len();
+
+        // Observe the next value exactly as forecast, and once with a unit error
+        let expected = fit(&series, SEASON, alpha, beta, gamma).forecast(n, 1);
+        let mut on_forecast = series.clone();
+        on_forecast.push(expected);
+        let mut shocked = series.clone();
+        shocked.push(expected + 1.0);
+        let on_forecast = fit(&on_forecast, SEASON, alpha, beta, gamma);
+        let shocked = fit(&shocked, SEASON, alpha, beta, gamma);
+
+        for j in 1..=3 * SEASON {
+            let moved = shocked.forecast(n + 1, j) - on_forecast.forecast(n + 1, j);
+            let weight = error_weight(alpha, beta, gamma, SEASON, j);
+            assert!(
+                (moved - weight).abs() < 1e-9,
+                "j = {j}: {moved} vs {weight}"
+            );
+        }
+    }
+
+    #[test]
+    fn intervals_widen_by_the_accumulated_error_weights() {
+        let series = weekly_series(84);
+        let z = 1.96;
+        let forecast = holt_winters_forecast(&series, SEASON, 14, z).expect("two seasons of data");
+
+        let mut cumulative: f64 = 0.0;
+        for h in 1..=14 {
+            let expected = z * forecast.rmse * (1.0 + cumulative).sqrt();
+            let half_width = (forecast.upper[h - 1] - forecast.lower[h - 1]) / 2.0;
+            assert!((half_width - expected).abs() < 1e-9, "h = {h}");
+            let c = error_weight(forecast.alpha, forecast.beta, forecast.gamma, SEASON, h);
+            cumulative += c * c;
+        }
+        assert!(forecast.upper[13] - forecast.lower[13] > forecast.upper[0] - forecast.lower[0]);
+    }
+
+    #[test]
+    fn too_short_series_has_no_forecast() {
+        assert!(holt_winters_forecast(&weekly_series(13), SEASON, 7, 1.96).is_none());
+    }
+}
@@ -0,0 +1,9 @@
+mod anomalies;
+mod forecast;
+mod transaction_gaps;
+
+pub use anomalies::{AnomalyContext, TARIFF_TOLERANCE, detect_anomalies};
+pub use forecast::{HoltWintersForecast, holt_winters_forecast};
+pub use transaction_gaps::{
+    DEFAULT_TRANSACTION_ID_PATTERN, analyze_transaction_gaps, compile_transaction_id_pattern,
+};
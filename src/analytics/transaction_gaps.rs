@@ -0,0 +1,331 @@
+use crate::model::{
+    MissingRange, OutOfOrderVend, SequenceGapReport, SequenceReset, TransactionIdRule,
+    TransactionSequenceEntry, UnparsedTransactionIds,
+};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+
+/// Rule used for stations without their own: the trailing digits are the sequence number
+pub const DEFAULT_TRANSACTION_ID_PATTERN: &str = r"(?P<seq>\d+)$";
+const UNPARSED_SAMPLE_SIZE: usize = 5;
+// Highest number a counter restarts from
+const RESET_SEQUENCE_MAX: u64 = 1;
+
+/// Compile a transaction ID rule, requiring the `seq` capture group
+pub fn compile_transaction_id_pattern(pattern: &str) -> Result<Regex, String> {
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+    if !regex.capture_names().any(|name| name == Some("seq")) {
+        return Err("Pattern must contain a named group `seq` for the sequence number".to_string());
+    }
+    Ok(regex)
+}
+
+struct ParsedVend<'a> {
+    sequence: u64,
+    entry: &'a TransactionSequenceEntry,
+}
+
+// Split vends, in time order, where the counter restarted. A restart is a vend numbered 0 or 1
+// right after a vend at least two numbers higher, e.g. after a terminal reset or rollover.
+fn split_at_resets(mut vends: Vec<ParsedVend>) -> (Vec<Vec<ParsedVend>>, Vec<SequenceReset>) {
+    vends.sort_by_key(|vend| (vend.entry.timestamp, vend.sequence));
+
+    let mut runs = Vec::new();
+    let mut resets = Vec::new();
+    let mut run: Vec<ParsedVend> = Vec::new();
+    for vend in vends {
+        if let Some(previous) = run.last()
+            && vend.sequence <= RESET_SEQUENCE_MAX
+            && previous.sequence > vend.sequence + 1
+        {
+            resets.push(SequenceReset {
+                transaction_id: vend.entry.transaction_id.clone(),
+                timestamp: vend.entry.timestamp,
+                previous_transaction_id: previous.entry.transaction_id.clone(),
+                previous_sequence: previous.sequence,
+            });
+            runs.push(std::mem::take(&mut run));
+        }
+        run.push(vend);
+    }
+    if !run.is_empty() {
+        runs.push(run);
+    }
+    (runs, resets)
+}
+
+fn sequence_report(
+    vending_station: &str,
+    terminal: Option<String>,
+    pattern: &str,
+    vends: Vec<ParsedVend>,
+) -> SequenceGapReport {
+    let received = vends.len() as u32;
+    let (mut runs, resets) = split_at_resets(vends);
+
+    let mut missing_ranges = Vec::new();
+    let mut out_of_order = Vec::new();
+    let mut duplicate_count = 0;
+    for run in &mut runs {
+        run.sort_by_key(|vend| (vend.sequence, vend.entry.timestamp));
+        for pair in run.windows(2) {
+            let (previous, current) = (&pair[0], &pair[1]);
+            match current.sequence - previous.sequence {
+                0 => duplicate_count += 1,
+                1 => {}
+                step => missing_ranges.push(MissingRange {
+                    from: previous.sequence + 1,
+                    to: current.sequence - 1,
+                    count: step - 1,
+                }),
+            }
+            if current.entry.timestamp < previous.entry.timestamp {
+                out_of_order.push(OutOfOrderVend {
+                    transaction_id: current.entry.transaction_id.clone(),
+                    timestamp: current.entry.timestamp,
+                    previous_transaction_id: previous.entry.transaction_id.clone(),
+                    previous_timestamp: previous.entry.timestamp,
+                });
+            }
+        }
+    }
+
+    SequenceGapReport {
+        vending_station: vending_station.to_string(),
+        terminal,
+        pattern: pattern.to_string(),
+        first_sequence: runs
+            .first()
+            .and_then(|run| run.first())
+            .map(|vend| vend.sequence)
+            .unwrap_or(0),
+        last_sequence: runs
+            .last()
+            .and_then(|run| run.last())
+            .map(|vend| vend.sequence)
+            .unwrap_or(0),
+        received,
+        missing_count: missing_ranges.iter().map(|range| range.count).sum(),
+        duplicate_count,
+        missing_ranges,
+        out_of_order,
+        resets,
+    }
+}
+
+/// Parse transaction IDs with each station's rule and report gaps per station and terminal
+pub fn analyze_transaction_gaps(
+    entries: &[TransactionSequenceEntry],
+    rules: &HashMap<String, TransactionIdRule>,
+) -> (Vec<SequenceGapReport>, Vec<UnparsedTransactionIds>) {
+    let mut compiled: HashMap<&str, (&str, Option<Regex>)> = HashMap::new();
+    let mut sequences: BTreeMap<(&str, Option<String>), Vec<ParsedVend>> = BTreeMap::new();
+    let mut unparsed: BTreeMap<&str, UnparsedTransactionIds> = BTreeMap::new();
+
+    for entry in entries {
+        let station = entry.vending_station.as_str();
+        let (_, regex) = compiled.entry(station).or_insert_with(|| {
+            let pattern = rules
+                .get(station)
+                .map(|rule| rule.pattern.as_str())
+                .unwrap_or(DEFAULT_TRANSACTION_ID_PATTERN);
+            (pattern, compile_transaction_id_pattern(pattern).ok())
+        });
+
+        let parsed = regex.as_ref().and_then(|regex| {
+            let captures = regex.captures(&entry.transaction_id)?;
+            let sequence = captures.name("seq")?.as_str().parse::<u64>().ok()?;
+            let terminal = captures.name("terminal").map(|m| m.as_str().to_string());
+            Some((sequence, terminal))
+        });
+
+        match parsed {
+            Some((sequence, terminal)) => sequences
+                .entry((station, terminal))
+                .or_default()
+                .push(ParsedVend { sequence, entry }),
+            None => {
+                let unparsed = unparsed
+                    .entry(station)
+                    .or_insert_with(|| UnparsedTransactionIds {
+                        vending_station: station.to_string(),
+                        count: 0,
+                        samples: Vec::new(),
+                    });
+                unparsed.count += 1;
+                if unparsed.samples.len() < UNPARSED_SAMPLE_SIZE {
+                    unparsed.samples.push(entry.transaction_id.clone());
+                }
+            }
+        }
+    }
+
+    let reports = sequences
+        .into_iter()
+        .map(|((station, terminal), vends)| {
+            let pattern = compiled
+                .get(station)
+                .map(|(pattern, _)| *pattern)
+                .unwrap_or(DEFAULT_TRANSACTION_ID_PATTERN);
+            sequence_report(station, terminal, pattern, vends)
+        })
+        .collect();
+
+    (reports, unparsed.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_704_067_200, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    // Vends at one station, given as (transaction ID, minutes after the start)
+    fn entries(station: &str, vends: &[(&str, i64)]) -> Vec<TransactionSequenceEntry> {
+        vends
+            .iter()
+            .map(|(transaction_id, minutes)| TransactionSequenceEntry {
+                record_id: format!("rec-{}", transaction_id),
+                vending_station: station.to_string(),
+                transaction_id: transaction_id.to_string(),
+                timestamp: at(*minutes),
+            })
+            .collect()
+    }
+
+    fn analyze(entries: &[TransactionSequenceEntry]) -> Vec<SequenceGapReport> {
+        analyze_transaction_gaps(entries, &HashMap::new()).0
+    }
+
+    #[test]
+    fn contiguous_sequence_has_no_gaps() {
+        let reports = analyze(&entries("A", &[("T-1", 0), ("T-2", 1), ("T-3", 2)]));
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!((report.first_sequence, report.last_sequence), (1, 3));
+        assert_eq!(report.received, 3);
+        assert_eq!(report.missing_count, 0);
+        assert!(report.missing_ranges.is_empty());
+        assert!(report.out_of_order.is_empty());
+        assert!(report.resets.is_empty());
+    }
+
+    #[test]
+    fn missing_numbers_are_reported_as_ranges() {
+        let reports = analyze(&entries(
+            "A",
+            &[("T-10", 0), ("T-11", 1), ("T-15", 2), ("T-17", 3)],
+        ));
+        let report = &reports[0];
+        let ranges: Vec<_> = report
+            .missing_ranges
+            .iter()
+            .map(|range| (range.from, range.to, range.count))
+            .collect();
+        assert_eq!(ranges, [(12, 14, 3), (16, 16, 1)]);
+        assert_eq!(report.missing_count, 4);
+    }
+
+    #[test]
+    fn duplicates_and_out_of_order_vends_are_counted() {
+        let reports = analyze(&entries(
+            "A",
+            &[("T-1", 0), ("T-3", 1), ("T-2", 2), ("T-3", 3)],
+        ));
+        let report = &reports[0];
+        assert_eq!(report.duplicate_count, 1);
+        assert_eq!(report.missing_count, 0);
+        assert_eq!(report.out_of_order.len(), 1);
+        // T-3 was vended before T-2
+        assert_eq!(report.out_of_order[0].transaction_id, "T-3");
+        assert_eq!(report.out_of_order[0].previous_transaction_id, "T-2");
+        assert!(report.resets.is_empty());
+    }
+
+    #[test]
+    fn counter_reset_starts_a_new_run() {
+        let reports = analyze(&entries(
+            "A",
+            &[("T-98", 0), ("T-99", 1), ("T-1", 2), ("T-2", 3), ("T-4", 4)],
+        ));
+        let report = &reports[0];
+        assert_eq!(report.resets.len(), 1);
+        let reset = &report.resets[0];
+        assert_eq!(reset.transaction_id, "T-1");
+        assert_eq!(reset.previous_transaction_id, "T-99");
+        assert_eq!(reset.previous_sequence, 99);
+        assert_eq!(reset.timestamp, at(2));
+
+        // Only the gap inside the second run; nothing between 2 and 98 or out of order
+        assert_eq!(report.missing_count, 1);
+        assert_eq!(report.missing_ranges[0].from, 3);
+        assert!(report.out_of_order.is_empty());
+        assert_eq!(report.duplicate_count, 0);
+        assert_eq!((report.first_sequence, report.last_sequence), (98, 4));
+        assert_eq!(report.received, 5);
+    }
+
+    #[test]
+    fn rollover_to_zero_is_a_reset() {
+        let reports = analyze(&entries(
+            "A",
+            &[("T-9998", 0), ("T-9999", 1), ("T-0000", 2)],
+        ));
+        assert_eq!(reports[0].resets.len(), 1);
+        assert_eq!(reports[0].missing_count, 0);
+    }
+
+    #[test]
+    fn small_step_back_is_out_of_order_not_a_reset() {
+        let reports = analyze(&entries("A", &[("T-2", 0), ("T-1", 1), ("T-3", 2)]));
+        let report = &reports[0];
+        assert!(report.resets.is_empty());
+        assert_eq!(report.out_of_order.len(), 1);
+    }
+
+    #[test]
+    fn station_rules_split_terminals_and_collect_unparsed_ids() {
+        let rules = HashMap::from([(
+            "A".to_string(),
+            TransactionIdRule {
+                vending_station: "A".to_string(),
+                pattern: r"^(?P<terminal>[A-Z]+)-(?P<seq>\d+)$".to_string(),
+            },
+        )]);
+        let mut vends = entries(
+            "A",
+            &[("X-1", 0), ("X-3", 1), ("Y-7", 2), ("Y-8", 3), ("bad", 4)],
+        );
+        vends.extend(entries("B", &[("B5", 5), ("B6", 6)]));
+
+        let (reports, unparsed) = analyze_transaction_gaps(&vends, &rules);
+        let summary: Vec<_> = reports
+            .iter()
+            .map(|report| {
+                (
+                    report.vending_station.as_str(),
+                    report.terminal.as_deref(),
+                    report.missing_count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [("A", Some("X"), 1), ("A", Some("Y"), 0), ("B", None, 0)]
+        );
+        assert_eq!(reports[2].pattern, DEFAULT_TRANSACTION_ID_PATTERN);
+        assert_eq!(unparsed.len(), 1);
+        assert_eq!(unparsed[0].count, 1);
+        assert_eq!(unparsed[0].samples, ["bad"]);
+    }
+
+    #[test]
+    fn patterns_need_a_seq_group() {
+        assert!(compile_transaction_id_pattern(r"(?P<seq>\d+)$").is_ok());
+        assert!(compile_transaction_id_pattern(r"(\d+)$").is_err());
+        assert!(compile_transaction_id_pattern(r"(?P<seq>\d+").is_err());
+    }
+}
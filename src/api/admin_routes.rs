@@ -0,0 +1,428 @@
+use actix_web::{HttpResponse, web};
+use chrono::{NaiveDate, Utc};
+use mongodb::Database;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::vending_records_routes::{ApiResponse, resolve_date_range, resolve_timezone};
+use crate::analytics::{DEFAULT_TRANSACTION_ID_PATTERN, compile_transaction_id_pattern};
+use crate::auth::{RequestContext, Role, ScopedVendingRecords, generate_api_key, hash_api_key};
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::model::{ApiKey, IssuedApiKey, StationOpeningHours, TransactionIdRule};
+use crate::repositories::{
+    ApiKeyRepository, DailyRollupRepository, MongoDbDailyRollupRepository,
+    MongoDbStationHoursRepository, MongoDbTransactionIdRuleRepository, StationHoursRepository,
+    TransactionIdRuleRepository,
+};
+
+// Days before today whose raw totals every incremental refresh compares with their rollups,
+// to catch late-synced vends that carry no change time
+pub const ROLLUP_LOOKBACK_DAYS: u32 = 7;
+const DEFAULT_DATA_QUALITY_SAMPLES: u32 = 10;
+const MAX_DATA_QUALITY_SAMPLES: u32 = 50;
+
+#[derive(Deserialize)]
+pub struct RollupRefreshQuery {
+    pub start_date: Option<String>, // YYYY-MM-DD; with end_date, forces a rebuild of those days
+    pub end_date: Option<String>,   // YYYY-MM-DD
+}
+
+#[derive(Deserialize)]
+pub struct DataQualityQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub samples: Option<u32>, // Sample document ids per problem (1-50, default 10)
+}
+
+#[derive(Deserialize)]
+pub struct OpeningHoursRequest {
+    pub opens_at: String,  // HH:MM local time
+    pub closes_at: String, // HH:MM local time
+    pub timezone: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TransactionIdRuleRequest {
+    pub pattern: String, // Regex with a `seq` group and an optional `terminal` group
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyRequest {
+    pub name: String, // Who or what uses the key, e.g. "finance dashboard"
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub stations: Vec<String>, // Required with station_manager or cashier, unless communities is set
+    #[serde(default)]
+    pub communities: Vec<String>,
+}
+
+fn parse_day(date_str: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(date_str, "%Y-%m-%d").map_err(|_| {
+        AppError::Validation(format!(
+            "Invalid date format: '{}'. Use YYYY-MM-DD",
+            date_str
+        ))
+    })
+}
+
+/// Get how far the daily rollups have been refreshed
+pub async fn get_rollup_status(
+    db: web::Data<Database>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let repo = MongoDbDailyRollupRepository::from_database(&db, &config.collections);
+
+    let status = repo.get_rollup_status().await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: match &status.refreshed_through {
+            Some(date) => format!("Daily rollups are complete through {}", date),
+            None => "Daily rollups have not been built yet".to_string(),
+        },
+        data: Some(status),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Refresh the daily rollups incrementally, or rebuild an explicit range of days
+pub async fn refresh_rollups(
+    db: web::Data<Database>,
+    query: web::Query<RollupRefreshQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let today = Utc::now().date_naive();
+    let repo = MongoDbDailyRollupRepository::from_database(&db, &config.collections);
+
+    let result = match (&query.start_date, &query.end_date) {
+        (None, None) => repo.refresh_incremental(today, ROLLUP_LOOKBACK_DAYS).await,
+        (Some(start_date), Some(end_date)) => {
+            let start_day = parse_day(start_date)?;
+            let end_day = parse_day(end_date)?;
+            if start_day > end_day {
+                return Err(AppError::Validation(
+                    "start_date must not be after end_date".to_string(),
+                ));
+            }
+            repo.refresh_range(start_day, end_day, today).await
+        }
+        _ => {
+            return Err(AppError::Validation(
+                "Provide both start_date and end_date to rebuild a range, or neither for an incremental refresh".to_string(),
+            ));
+        }
+    };
+
+    let refresh = result?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Refreshed {} days of rollups", refresh.days_refreshed),
+        data: Some(refresh),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// List the configured opening hours of every station
+pub async fn get_opening_hours(
+    db: web::Data<Database>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let repo = MongoDbStationHoursRepository::from_collection(
+        db.collection::<StationOpeningHours>(&config.collections.station_opening_hours),
+    );
+
+    let hours = repo.get_opening_hours().await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Retrieved opening hours for {} stations", hours.len()),
+        data: Some(hours),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Set a station's opening hours, used by the anomaly scan
+pub async fn set_opening_hours(
+    db: web::Data<Database>,
+    path: web::Path<String>,
+    body: web::Json<OpeningHoursRequest>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let vending_station = path.into_inner();
+    let request = body.into_inner();
+
+    for time in [&request.opens_at, &request.closes_at] {
+        if chrono::NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+            return Err(AppError::Validation(format!(
+                "Invalid time format: '{}'. Use HH:MM",
+                time
+            )));
+        }
+    }
+    let timezone = resolve_timezone(&request.timezone)?;
+
+    let repo = MongoDbStationHoursRepository::from_collection(
+        db.collection::<StationOpeningHours>(&config.collections.station_opening_hours),
+    );
+    let opening_hours = StationOpeningHours {
+        vending_station,
+        opens_at: request.opens_at,
+        closes_at: request.closes_at,
+        timezone,
+    };
+
+    let hours = repo.save_opening_hours(opening_hours).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Saved opening hours for {}", hours.vending_station),
+        data: Some(hours),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Get the transaction ID parsing rules configured per station
+pub async fn get_transaction_id_rules(
+    db: web::Data<Database>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let repo = MongoDbTransactionIdRuleRepository::from_collection(
+        db.collection::<TransactionIdRule>(&config.collections.transaction_id_rules),
+    );
+
+    let rules = repo.get_rules().await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved transaction ID rules for {} stations (default: {})",
+            rules.len(),
+            DEFAULT_TRANSACTION_ID_PATTERN
+        ),
+        data: Some(rules),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Set how a station's transaction IDs are parsed, used by the transaction gap report
+pub async fn set_transaction_id_rule(
+    db: web::Data<Database>,
+    path: web::Path<String>,
+    body: web::Json<TransactionIdRuleRequest>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let vending_station = path.into_inner();
+    let pattern = body.into_inner().pattern;
+    compile_transaction_id_pattern(&pattern).map_err(AppError::Validation)?;
+
+    let repo = MongoDbTransactionIdRuleRepository::from_collection(
+        db.collection::<TransactionIdRule>(&config.collections.transaction_id_rules),
+    );
+    let rule = TransactionIdRule {
+        vending_station,
+        pattern,
+    };
+
+    let rule = repo.save_rule(rule).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Saved transaction ID rule for {}", rule.vending_station),
+        data: Some(rule),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Count vending record documents per data quality problem, with sample ids for cleanup
+pub async fn get_data_quality_report(
+    repo: ScopedVendingRecords,
+    query: web::Query<DataQualityQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+    let samples = query.samples.unwrap_or(DEFAULT_DATA_QUALITY_SAMPLES);
+    if samples == 0 || samples > MAX_DATA_QUALITY_SAMPLES {
+        return Err(AppError::Validation(format!(
+            "Invalid samples value: {}. Must be between 1 and {}",
+            samples, MAX_DATA_QUALITY_SAMPLES
+        )));
+    }
+
+    let report = repo
+        .get_data_quality_report(start_date, end_date, Utc::now(), samples)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Checked {} documents and found {} kinds of data quality problems",
+            report.documents_scanned,
+            report.issues.len()
+        ),
+        data: Some(report),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// List API keys without their secrets, revoked ones included
+pub async fn get_api_keys(repo: web::Data<Arc<dyn ApiKeyRepository>>) -> AppResult<HttpResponse> {
+    let keys = repo.get_api_keys().await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Retrieved {} API keys", keys.len()),
+        data: Some(keys),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Create an API key; the key is only returned in this response
+pub async fn create_api_key(
+    repo: web::Data<Arc<dyn ApiKeyRepository>>,
+    body: web::Json<ApiKeyRequest>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let request = body.into_inner();
+    let name = request.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::Validation("name must not be empty".to_string()));
+    }
+    if request.roles.is_empty() {
+        return Err(AppError::Validation(
+            "roles must name at least one role".to_string(),
+        ));
+    }
+    let roles = request
+        .roles
+        .iter()
+        .map(|role| role.parse::<Role>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::Validation)?;
+    // A key with only scoped roles and nothing to scope it to could never see a vend
+    if roles.iter().all(|role| role.is_scoped())
+        && request.stations.is_empty()
+        && request.communities.is_empty()
+    {
+        return Err(AppError::Validation(
+            "station_manager and cashier keys need stations or communities".to_string(),
+        ));
+    }
+
+    let (key, prefix) = generate_api_key();
+    let api_key = repo
+        .create_api_key(ApiKey {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            prefix,
+            key_hash: hash_api_key(&key),
+            roles: roles.iter().map(|role| role.as_str().to_string()).collect(),
+            stations: request.stations,
+            communities: request.communities,
+            created_at: Utc::now(),
+            rotated_at: None,
+            revoked_at: None,
+        })
+        .await?;
+
+    println!(
+        "🔑 API key '{}' ({}) with roles [{}] created by {}",
+        api_key.name,
+        api_key.id,
+        api_key.roles.join(", "),
+        context
+    );
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Created API key '{}'. Store the key now; it cannot be shown again",
+            api_key.name
+        ),
+        data: Some(IssuedApiKey { api_key, key }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Replace a key's secret, invalidating the old one immediately
+pub async fn rotate_api_key(
+    repo: web::Data<Arc<dyn ApiKeyRepository>>,
+    path: web::Path<String>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let id = path.into_inner();
+    let (key, prefix) = generate_api_key();
+    let api_key = repo
+        .rotate_api_key(&id, &hash_api_key(&key), &prefix, Utc::now())
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No active API key with id '{}'", id)))?;
+
+    println!(
+        "🔑 API key '{}' ({}) rotated by {}",
+        api_key.name, api_key.id, context
+    );
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Rotated API key '{}'. Store the new key now; it cannot be shown again",
+            api_key.name
+        ),
+        data: Some(IssuedApiKey { api_key, key }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Revoke a key; it stays listed with its revocation time
+pub async fn revoke_api_key(
+    repo: web::Data<Arc<dyn ApiKeyRepository>>,
+    path: web::Path<String>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let id = path.into_inner();
+    let api_key = repo
+        .revoke_api_key(&id, Utc::now())
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No active API key with id '{}'", id)))?;
+
+    println!(
+        "🔑 API key '{}' ({}) revoked by {}",
+        api_key.name, api_key.id, context
+    );
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Revoked API key '{}'", api_key.name),
+        data: Some(api_key),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Configure admin routes
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/admin")
+            .route("/rollups", web::get().to(get_rollup_status))
+            .route("/rollups/refresh", web::post().to(refresh_rollups))
+            .route("/data-quality", web::get().to(get_data_quality_report))
+            .route("/stations/opening-hours", web::get().to(get_opening_hours))
+            .route(
+                "/stations/{vending_station}/opening-hours",
+                web::put().to(set_opening_hours),
+            )
+            .route(
+                "/stations/transaction-id-rules",
+                web::get().to(get_transaction_id_rules),
+            )
+            .route(
+                "/stations/{vending_station}/transaction-id-rule",
+                web::put().to(set_transaction_id_rule),
+            )
+            .route("/api-keys", web::get().to(get_api_keys))
+            .route("/api-keys", web::post().to(create_api_key))
+            .route("/api-keys/{id}/rotate", web::post().to(rotate_api_key))
+            .route("/api-keys/{id}", web::delete().to(revoke_api_key)),
+    );
+}
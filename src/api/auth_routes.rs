@@ -0,0 +1,41 @@
+use actix_web::{HttpResponse, web};
+use serde::Serialize;
+
+use super::vending_records_routes::ApiResponse;
+use crate::auth::{Permission, RequestContext};
+use crate::error::AppResult;
+use crate::model::AccessScope;
+
+/// The caller with what their roles allow
+#[derive(Serialize)]
+struct Identity {
+    #[serde(flatten)]
+    context: RequestContext,
+    permissions: Vec<Permission>,
+    scope: AccessScope,
+}
+
+/// Show who the server takes the caller to be, to check tokens and keys
+pub async fn get_current_identity(context: RequestContext) -> AppResult<HttpResponse> {
+    let permissions = Permission::ALL
+        .into_iter()
+        .filter(|permission| context.has_permission(*permission))
+        .collect();
+    let scope = context.access_scope();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Authenticated as {}", context),
+        data: Some(Identity {
+            context,
+            permissions,
+            scope,
+        }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/api/auth").route("/whoami", web::get().to(get_current_identity)));
+}
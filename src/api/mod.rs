@@ -1,6 +1,16 @@
+mod admin_routes;
+mod auth_routes;
 mod health_routes;
+mod vending_analytics_routes;
 mod vending_records_routes;
+mod vending_reports_routes;
 
+pub use admin_routes::ROLLUP_LOOKBACK_DAYS;
+pub use admin_routes::configure_routes as configure_admin_routes;
+pub use auth_routes::configure_routes as configure_auth_routes;
 pub use health_routes::configure_routes as configure_health_routes;
+pub use vending_analytics_routes::configure_routes as configure_analytics_routes;
+pub use vending_records_routes::ApiResponse;
 pub use vending_records_routes::configure_routes as configure_vending_routes;
 pub use vending_records_routes::configure_routes as configure_vending_summary_routes;
+pub use vending_reports_routes::configure_routes as configure_report_routes;
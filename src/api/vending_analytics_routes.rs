@@ -0,0 +1,419 @@
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use super::vending_records_routes::{
+    ApiResponse, DateRangeQuery, resolve_date_range, resolve_timezone,
+};
+use crate::analytics::{HoltWintersForecast, holt_winters_forecast};
+use crate::auth::ScopedVendingRecords;
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::model::{
+    ForecastPoint, HorizonTotal, LeaderboardDimension, LeaderboardMetric, RevenueForecast,
+    SeriesForecast, StationForecast, SummarySeriesOptions,
+};
+
+const DEFAULT_HISTOGRAM_BUCKETS: u32 = 10;
+const MAX_HISTOGRAM_BUCKETS: u32 = 50;
+const DEFAULT_LEADERBOARD_LIMIT: u32 = 10;
+const MAX_LEADERBOARD_LIMIT: u32 = 100;
+const DEFAULT_FORECAST_HORIZON: u32 = 90;
+const MAX_FORECAST_HORIZON: u32 = 90;
+const DEFAULT_FORECAST_HISTORY_DAYS: u32 = 365;
+const MIN_FORECAST_HISTORY_DAYS: u32 = 28;
+const FORECAST_CHECKPOINTS: [u32; 3] = [30, 60, 90];
+// Weekly seasonality: vending follows the day of the week
+const FORECAST_SEASON_DAYS: usize = 7;
+
+#[derive(Deserialize)]
+pub struct StatisticsQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub buckets: Option<u32>, // Number of histogram buckets (1-50, default 10)
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub by: LeaderboardDimension, // meter, customer, community or station
+    pub metric: Option<LeaderboardMetric>, // amount (default), kwh or transactions
+    pub limit: Option<u32>,       // Number of entries (1-100, default 10)
+}
+
+#[derive(Deserialize)]
+pub struct ForecastQuery {
+    pub horizon: Option<u32>,      // Days to forecast (1-90, default 90)
+    pub history_days: Option<u32>, // Complete days of history to fit on (default 365, at most max_range_days)
+    pub confidence: Option<u32>,   // Prediction interval coverage: 80, 90, 95 (default) or 99
+}
+
+#[derive(Deserialize)]
+pub struct HeatmapQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub timezone: Option<String>, // IANA timezone for local days and hours (default UTC)
+    pub vending_station: Option<String>, // Restrict to one station
+    pub community: Option<String>, // Restrict to one community
+}
+
+fn confidence_z(confidence: u32) -> Option<f64> {
+    match confidence {
+        80 => Some(1.2816),
+        90 => Some(1.6449),
+        95 => Some(1.9600),
+        99 => Some(2.5758),
+        _ => None,
+    }
+}
+
+fn series_forecast(
+    fit: HoltWintersForecast,
+    first_day: NaiveDate,
+    floor_at_zero: bool,
+) -> SeriesForecast {
+    let clamp = |value: f64| if floor_at_zero { value.max(0.0) } else { value };
+    let points = fit
+        .points
+        .iter()
+        .zip(fit.lower.iter().zip(fit.upper.iter()))
+        .enumerate()
+        .map(|(offset, (point, (lower, upper)))| ForecastPoint {
+            date: (first_day + Days::new(offset as u64))
+                .format("%Y-%m-%d")
+                .to_string(),
+            forecast: clamp(*point),
+            lower: clamp(*lower),
+            upper: clamp(*upper),
+        })
+        .collect();
+
+    SeriesForecast {
+        alpha: fit.alpha,
+        beta: fit.beta,
+        gamma: fit.gamma,
+        rmse: fit.rmse,
+        points,
+    }
+}
+
+// Forecast one station's daily amount and kWh; None when its history is too short
+fn station_forecast(
+    vending_station: String,
+    amounts: &[f64],
+    kwhs: &[f64],
+    first_day: NaiveDate,
+    horizon: u32,
+    z: f64,
+) -> Option<StationForecast> {
+    // A station that opened during the history window is only fitted from its first vend
+    let opened = amounts
+        .iter()
+        .zip(kwhs)
+        .position(|(amount, kwh)| *amount != 0.0 || *kwh != 0.0)?;
+    let amount_fit = holt_winters_forecast(
+        &amounts[opened..],
+        FORECAST_SEASON_DAYS,
+        horizon as usize,
+        z,
+    )?;
+    let kwh_fit =
+        holt_winters_forecast(&kwhs[opened..], FORECAST_SEASON_DAYS, horizon as usize, z)?;
+
+    let amount = series_forecast(amount_fit, first_day, true);
+    let kwh = series_forecast(kwh_fit, first_day, true);
+    let horizon_totals = FORECAST_CHECKPOINTS
+        .iter()
+        .filter(|days| **days <= horizon)
+        .map(|days| HorizonTotal {
+            days: *days,
+            amount: amount.points[..*days as usize]
+                .iter()
+                .map(|point| point.forecast)
+                .sum(),
+            kwh: kwh.points[..*days as usize]
+                .iter()
+                .map(|point| point.forecast)
+                .sum(),
+        })
+        .collect();
+
+    Some(StationForecast {
+        vending_station,
+        amount,
+        kwh,
+        horizon_totals,
+    })
+}
+
+/// Get amount and kWh distribution statistics with per-station and per-community breakdowns
+pub async fn get_vending_statistics(
+    repo: ScopedVendingRecords,
+    query: web::Query<StatisticsQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+
+    let buckets = query.buckets.unwrap_or(DEFAULT_HISTOGRAM_BUCKETS);
+    if buckets == 0 || buckets > MAX_HISTOGRAM_BUCKETS {
+        return Err(AppError::Validation(format!(
+            "Invalid buckets value: {}. Must be between 1 and {}",
+            buckets, MAX_HISTOGRAM_BUCKETS
+        )));
+    }
+
+    let statistics = repo
+        .get_vending_statistics(start_date, end_date, buckets)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved vending statistics for period {} to {} ({} transactions with amount)",
+            statistics.period_start, statistics.period_end, statistics.amount.stats.count
+        ),
+        data: Some(statistics),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Get the top N meters, customers, communities or stations over a date range
+pub async fn get_leaderboard(
+    repo: ScopedVendingRecords,
+    query: web::Query<LeaderboardQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LEADERBOARD_LIMIT);
+    if limit == 0 || limit > MAX_LEADERBOARD_LIMIT {
+        return Err(AppError::Validation(format!(
+            "Invalid limit value: {}. Must be between 1 and {}",
+            limit, MAX_LEADERBOARD_LIMIT
+        )));
+    }
+    let metric = query.metric.unwrap_or(LeaderboardMetric::Amount);
+
+    let leaderboard = repo
+        .get_leaderboard(start_date, end_date, query.by, metric, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved top {} entries for period {} to {}",
+            leaderboard.entries.len(),
+            leaderboard.period_start,
+            leaderboard.period_end
+        ),
+        data: Some(leaderboard),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Forecast daily revenue and kWh per station with a weekly Holt-Winters model
+pub async fn get_revenue_forecast(
+    repo: ScopedVendingRecords,
+    query: web::Query<ForecastQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let horizon = query.horizon.unwrap_or(DEFAULT_FORECAST_HORIZON);
+    if horizon == 0 || horizon > MAX_FORECAST_HORIZON {
+        return Err(AppError::Validation(format!(
+            "Invalid horizon value: {}. Must be between 1 and {}",
+            horizon, MAX_FORECAST_HORIZON
+        )));
+    }
+    let max_history_days = config.queries.max_range_days;
+    let history_days = query
+        .history_days
+        .unwrap_or(DEFAULT_FORECAST_HISTORY_DAYS.min(max_history_days));
+    if history_days < MIN_FORECAST_HISTORY_DAYS {
+        return Err(AppError::Validation(format!(
+            "Invalid history_days value: {}. At least {} days are needed",
+            history_days, MIN_FORECAST_HISTORY_DAYS
+        )));
+    }
+    if history_days > max_history_days {
+        return Err(AppError::Validation(format!(
+            "Invalid history_days value: {}. Must be at most {} days",
+            history_days, max_history_days
+        )));
+    }
+    let confidence = query.confidence.unwrap_or(95);
+    let z = confidence_z(confidence).ok_or_else(|| {
+        AppError::Validation(format!(
+            "Invalid confidence value: {}. Use 80, 90, 95 or 99",
+            confidence
+        ))
+    })?;
+
+    // Fit on complete days only, ending yesterday
+    let today = Utc::now().date_naive();
+    let history_end = today - Days::new(1);
+    let history_start = today - Days::new(history_days as u64);
+    let start_date = DateTime::<Utc>::from_naive_utc_and_offset(
+        history_start
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time"),
+        Utc,
+    );
+    let end_date = DateTime::<Utc>::from_naive_utc_and_offset(
+        history_end
+            .and_hms_milli_opt(23, 59, 59, 999)
+            .expect("end of day is a valid time"),
+        Utc,
+    );
+
+    let summary = repo
+        .get_vending_summary(start_date, end_date, SummarySeriesOptions::default())
+        .await?;
+
+    // Dense daily series with zeros on days without vends
+    let days = history_days as usize;
+    let day_index = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .and_then(|day| usize::try_from((day - history_start).num_days()).ok())
+            .filter(|index| *index < days)
+    };
+    let mut overall_amounts = vec![0.0; days];
+    let mut overall_kwhs = vec![0.0; days];
+    let mut station_series = BTreeMap::new();
+    for station in &summary.vending_station_summaries {
+        let mut amounts = vec![0.0; days];
+        let mut kwhs = vec![0.0; days];
+        for daily in &station.daily_summaries {
+            if let Some(index) = day_index(&daily.date) {
+                amounts[index] += daily.total_amount;
+                kwhs[index] += daily.total_kwh;
+                overall_amounts[index] += daily.total_amount;
+                overall_kwhs[index] += daily.total_kwh;
+            }
+        }
+        station_series.insert(station.vending_station.clone(), (amounts, kwhs));
+    }
+
+    let Some(overall) = station_forecast(
+        "All stations".to_string(),
+        &overall_amounts,
+        &overall_kwhs,
+        today,
+        horizon,
+        z,
+    ) else {
+        return Err(AppError::InsufficientData(
+            "Not enough vending history to fit a forecast; at least two weeks of vends are needed"
+                .to_string(),
+        ));
+    };
+
+    let mut vending_station_forecasts = Vec::new();
+    let mut skipped_stations = Vec::new();
+    for (vending_station, (amounts, kwhs)) in station_series {
+        match station_forecast(vending_station.clone(), &amounts, &kwhs, today, horizon, z) {
+            Some(forecast) => vending_station_forecasts.push(forecast),
+            None => skipped_stations.push(vending_station),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Forecast {} days for {} vending stations",
+            horizon,
+            vending_station_forecasts.len()
+        ),
+        data: Some(RevenueForecast {
+            history_start: history_start.format("%Y-%m-%d").to_string(),
+            history_end: history_end.format("%Y-%m-%d").to_string(),
+            horizon_days: horizon,
+            confidence_level: confidence,
+            overall,
+            vending_station_forecasts,
+            skipped_stations,
+        }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Get transaction count and amount by day of week and hour of day
+pub async fn get_heatmap(
+    repo: ScopedVendingRecords,
+    query: web::Query<HeatmapQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+    let timezone = resolve_timezone(&query.timezone)?;
+
+    let heatmap = repo
+        .get_heatmap(
+            start_date,
+            end_date,
+            &timezone,
+            query.vending_station.as_deref(),
+            query.community.as_deref(),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved heatmap of {} transactions for period {} to {} ({})",
+            heatmap.transaction_counts.iter().flatten().sum::<u32>(),
+            heatmap.period_start,
+            heatmap.period_end,
+            heatmap.timezone
+        ),
+        data: Some(heatmap),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Get ARPU, kWh per customer, active and new customers and revenue growth per community
+pub async fn get_kpis(
+    repo: ScopedVendingRecords,
+    query: web::Query<DateRangeQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+    if start_date >= end_date {
+        return Err(AppError::Validation(
+            "start_date must be before end_date".to_string(),
+        ));
+    }
+
+    let kpis = repo.get_kpis(start_date, end_date).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved KPIs for period {} to {} ({} active customers in {} communities)",
+            kpis.period_start,
+            kpis.period_end,
+            kpis.overall.active_customers,
+            kpis.communities.len()
+        ),
+        data: Some(kpis),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Configure vending analytics routes
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/analytics")
+            .route("/statistics", web::get().to(get_vending_statistics))
+            .route("/leaderboard", web::get().to(get_leaderboard))
+            .route("/forecast", web::get().to(get_revenue_forecast))
+            .route("/heatmap", web::get().to(get_heatmap))
+            .route("/kpis", web::get().to(get_kpis)),
+    );
+}
@@ -1,10 +1,11 @@
-use actix_web::{HttpResponse, Result, web};
+use actix_web::{HttpResponse, web};
 use chrono::{DateTime, NaiveDate, Utc};
-use mongodb::Database;
 use serde::{Deserialize, Serialize};
 
-use crate::model::VendingRecord;
-use crate::repositories::{MongoDbVendingRecordRepository, VendingRecordRepository};
+use crate::auth::ScopedVendingRecords;
+use crate::config::{AppConfig, QueryLimits};
+use crate::error::{AppError, AppResult};
+use crate::model::{DecodeReport, SummarySeriesOptions};
 
 #[derive(Deserialize)]
 pub struct DateRangeQuery {
@@ -12,11 +13,23 @@ pub struct DateRangeQuery {
     pub end_date: Option<String>,   // ISO 8601 format: "2023-12-31T23:59:59Z"
 }
 
+#[derive(Deserialize)]
+pub struct SummaryQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub cumulative: Option<bool>, // Add running amount/kWh totals since period start
+    pub moving_averages: Option<bool>, // Add 7- and 30-day moving averages of amount/kWh
+}
+
 #[derive(Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub message: String,
     pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<&'static str>, // Set on failures, see AppError::code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<DecodeReport>, // Set when malformed documents were skipped or partially read
 }
 
 /// Parse flexible date formats (YYYY-MM-DD or ISO 8601 datetime)
@@ -47,93 +60,123 @@ fn parse_flexible_date(date_str: &str, is_end_date: bool) -> Result<DateTime<Utc
     ))
 }
 
-/// Get vending records with optional date range filtering
-pub async fn get_vending_records(
-    db: web::Data<Database>,
-    query: web::Query<DateRangeQuery>,
-) -> Result<HttpResponse> {
-    // Parse date range or use defaults
-    let start_date = match &query.start_date {
-        Some(date_str) => {
-            parse_flexible_date(date_str, false).map_err(actix_web::error::ErrorBadRequest)?
-        }
-        None => Utc::now() - chrono::Duration::days(30), // Default: last 30 days
+/// Resolve optional start/end query parameters, defaulting to the configured window ending now
+pub(crate) fn resolve_date_range(
+    start_date: &Option<String>,
+    end_date: &Option<String>,
+    limits: &QueryLimits,
+) -> AppResult<(DateTime<Utc>, DateTime<Utc>)> {
+    let end_date = match end_date {
+        Some(date_str) => parse_flexible_date(date_str, true).map_err(AppError::Validation)?,
+        None => Utc::now(), // Default: now
     };
 
-    let end_date = match &query.end_date {
-        Some(date_str) => {
-            parse_flexible_date(date_str, true).map_err(actix_web::error::ErrorBadRequest)?
-        }
-        None => Utc::now(), // Default: now
+    let start_date = match start_date {
+        Some(date_str) => parse_flexible_date(date_str, false).map_err(AppError::Validation)?,
+        // Default: the configured window before the end date
+        None => end_date - chrono::Duration::days(limits.default_window_days as i64),
     };
 
-    // Create repository
-    let collection = db.collection::<VendingRecord>("vending_records");
-    let repo = MongoDbVendingRecordRepository::from_collection(collection);
+    if end_date < start_date {
+        return Err(AppError::Validation(
+            "start_date must not be after end_date".to_string(),
+        ));
+    }
 
-    // Get records
-    match repo.get_vending_records(start_date, end_date).await {
-        Ok(records) => Ok(HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            message: format!("Retrieved {} vending records", records.len()),
-            data: Some(records),
-        })),
-        Err(e) => {
-            eprintln!("Error fetching vending records: {}", e);
-            eprintln!("Error details: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                message: "Failed to fetch vending records due to data format issues. Check server logs for details.".to_string(),
-                data: None,
-            }))
-        }
+    if end_date - start_date > chrono::Duration::days(limits.max_range_days as i64) {
+        return Err(AppError::Validation(format!(
+            "Date range from {} to {} is longer than the maximum of {} days",
+            start_date.format("%Y-%m-%d"),
+            end_date.format("%Y-%m-%d"),
+            limits.max_range_days
+        )));
+    }
+
+    Ok((start_date, end_date))
+}
+
+/// Validate an optional IANA timezone name, defaulting to UTC
+pub(crate) fn resolve_timezone(timezone: &Option<String>) -> AppResult<String> {
+    match timezone {
+        Some(name) => name
+            .parse::<chrono_tz::Tz>()
+            .map(|tz| tz.name().to_string())
+            .map_err(|_| {
+                AppError::Validation(format!(
+                    "Invalid timezone: '{}'. Use an IANA name such as 'Africa/Monrovia'",
+                    name
+                ))
+            }),
+        None => Ok("UTC".to_string()),
     }
 }
 
-/// Get vending summary with aggregated statistics
-pub async fn get_vending_summary(
-    db: web::Data<Database>,
+/// Describe how many records were returned and how many malformed documents were left out
+pub(crate) fn decoded_records_message(returned: usize, report: &DecodeReport) -> String {
+    let mut message = format!("Retrieved {} vending records", returned);
+    if report.skipped_documents > 0 || report.partially_decoded > 0 {
+        message.push_str(&format!(
+            " ({} malformed documents skipped, {} partially decoded)",
+            report.skipped_documents, report.partially_decoded
+        ));
+    }
+    message
+}
+
+/// Only report decoding problems when there were any
+pub(crate) fn decode_metadata(report: DecodeReport) -> Option<DecodeReport> {
+    (report.skipped_documents > 0 || report.partially_decoded > 0).then_some(report)
+}
+
+/// Get vending records with optional date range filtering
+pub async fn get_vending_records(
+    repo: ScopedVendingRecords,
     query: web::Query<DateRangeQuery>,
-) -> Result<HttpResponse> {
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
     // Parse date range or use defaults
-    let start_date = match &query.start_date {
-        Some(date_str) => {
-            parse_flexible_date(date_str, false).map_err(actix_web::error::ErrorBadRequest)?
-        }
-        None => Utc::now() - chrono::Duration::days(30), // Default: last 30 days
-    };
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
 
-    let end_date = match &query.end_date {
-        Some(date_str) => {
-            parse_flexible_date(date_str, true).map_err(actix_web::error::ErrorBadRequest)?
-        }
-        None => Utc::now(), // Default: now
-    };
+    // Get records
+    let batch = repo.get_vending_records(start_date, end_date).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: decoded_records_message(batch.records.len(), &batch.decode_report),
+        data: Some(batch.records),
+        error_code: None,
+        metadata: decode_metadata(batch.decode_report),
+    }))
+}
 
-    // Create repository
-    let collection = db.collection::<VendingRecord>("vending_records");
-    let repo = MongoDbVendingRecordRepository::from_collection(collection);
+/// Get vending summary with aggregated statistics
+pub async fn get_vending_summary(
+    repo: ScopedVendingRecords,
+    query: web::Query<SummaryQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    // Parse date range or use defaults
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+    let series = SummarySeriesOptions {
+        cumulative: query.cumulative.unwrap_or(false),
+        moving_averages: query.moving_averages.unwrap_or(false),
+    };
 
     // Get summary
-    match repo.get_vending_summary(start_date, end_date).await {
-        Ok(summary) => Ok(HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            message: format!(
-                "Retrieved vending summary for period {} to {} ({} total transactions)",
-                summary.period_start, summary.period_end, summary.total_transactions
-            ),
-            data: Some(summary),
-        })),
-        Err(e) => {
-            eprintln!("Error fetching vending summary: {}", e);
-            eprintln!("Error details: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                message: "Failed to fetch vending summary due to data processing issues. Check server logs for details.".to_string(),
-                data: None,
-            }))
-        }
-    }
+    let summary = repo
+        .get_vending_summary(start_date, end_date, series)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved vending summary for period {} to {} ({} total transactions)",
+            summary.period_start, summary.period_end, summary.total_transactions
+        ),
+        data: Some(summary),
+        error_code: None,
+        metadata: None,
+    }))
 }
 
 /// Configure vending records routes
@@ -144,3 +187,272 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
             .route("/summary", web::get().to(get_vending_summary)),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthMethod, RequestContext};
+    use crate::error::query_config;
+    use crate::model::VendingRecord;
+    use crate::repositories::{InMemoryVendingRecordRepository, VendingRecordRepository};
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::test::{TestRequest, call_service, init_service, read_body_json};
+    use actix_web::{App, HttpMessage, http::StatusCode};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    fn record(
+        id: &str,
+        timestamp: &str,
+        station: &str,
+        community: &str,
+        amount: f64,
+    ) -> VendingRecord {
+        VendingRecord {
+            id: id.to_string(),
+            timestamp: timestamp.parse().unwrap(),
+            meter_number: Some(format!("M-{}", id)),
+            address: None,
+            community: Some(community.to_string()),
+            customer_name: None,
+            token: None,
+            tariff: Some(0.5),
+            amount: Some(amount),
+            kwh: Some(amount * 2.0),
+            user_id: Some("u1".to_string()),
+            vending_station: Some(station.to_string()),
+            fixed_charge: None,
+            transaction_id: None,
+            remaining_credit: None,
+        }
+    }
+
+    fn fixture() -> Vec<VendingRecord> {
+        vec![
+            record("a1", "2025-01-01T08:00:00Z", "Central", "North", 10.0),
+            record("a2", "2025-01-02T09:00:00Z", "Harbour", "South", 5.0),
+            record("a3", "2025-01-03T10:00:00Z", "Central", "North", 20.0),
+            record("a4", "2025-02-01T10:00:00Z", "Central", "North", 99.0),
+        ]
+    }
+
+    fn scoped(roles: &[&str], stations: &[&str]) -> RequestContext {
+        RequestContext {
+            subject: "user-1".to_string(),
+            name: None,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            stations: stations.iter().map(|station| station.to_string()).collect(),
+            communities: Vec::new(),
+            auth_method: AuthMethod::Jwt,
+        }
+    }
+
+    // Call the vending record routes as the given caller and return the status and JSON body
+    async fn get(context: RequestContext, uri: &str) -> (StatusCode, Value) {
+        let repo: Arc<dyn VendingRecordRepository> =
+            Arc::new(InMemoryVendingRecordRepository::new(fixture()));
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(repo))
+                .app_data(web::Data::new(AppConfig::default()))
+                .app_data(query_config())
+                // Stands in for the authentication middleware
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(context.clone());
+                    srv.call(req)
+                })
+                .configure(configure_routes),
+        )
+        .await;
+        let response: ServiceResponse =
+            call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        let status = response.status();
+        (status, read_body_json(response).await)
+    }
+
+    fn ids(body: &Value) -> Vec<&str> {
+        body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| record["_id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn records_are_limited_to_the_date_range() {
+        let (status, body) = get(
+            RequestContext::anonymous(),
+            "/api/vending-records?start_date=2025-01-01&end_date=2025-01-03",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["success"], true);
+        assert_eq!(ids(&body), ["a1", "a2", "a3"]);
+
+        // A date-only end date covers the whole day; a timestamp is exact
+        let (_, body) = get(
+            RequestContext::anonymous(),
+            "/api/vending-records?start_date=2025-01-01&end_date=2025-01-03T09:59:59Z",
+        )
+        .await;
+        assert_eq!(ids(&body), ["a1", "a2"]);
+    }
+
+    #[actix_web::test]
+    async fn invalid_date_ranges_are_rejected() {
+        for (uri, message) in [
+            (
+                "/api/vending-records?start_date=2025-01-03&end_date=2025-01-01",
+                "start_date must not be after end_date",
+            ),
+            (
+                "/api/vending-records?start_date=2023-01-01&end_date=2025-01-01",
+                "longer than the maximum of 366 days",
+            ),
+            (
+                "/api/vending-records?start_date=01/02/2025",
+                "Invalid date format: '01/02/2025'",
+            ),
+            (
+                "/api/vending-records/summary?start_date=2025-01-01&end_date=yesterday",
+                "Invalid date format: 'yesterday'",
+            ),
+        ] {
+            let (status, body) = get(RequestContext::anonymous(), uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(body["success"], false);
+            assert_eq!(body["error_code"], "VALIDATION_ERROR");
+            assert_eq!(body["data"], Value::Null);
+            assert!(
+                body["message"].as_str().unwrap().contains(message),
+                "{}: {}",
+                uri,
+                body["message"]
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn malformed_query_parameters_are_validation_errors() {
+        let (status, body) = get(
+            RequestContext::anonymous(),
+            "/api/vending-records/summary?start_date=2025-01-01&end_date=2025-01-03&cumulative=maybe",
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error_code"], "VALIDATION_ERROR");
+    }
+
+    #[actix_web::test]
+    async fn summary_totals_each_station_by_day() {
+        let (status, body) = get(
+            RequestContext::anonymous(),
+            "/api/vending-records/summary?start_date=2025-01-01&end_date=2025-01-03",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let summary = &body["data"];
+        assert_eq!(summary["total_transactions"], 3);
+        assert_eq!(summary["total_amount"], 35.0);
+        assert_eq!(summary["total_kwh"], 70.0);
+        assert_eq!(summary["period_start"], "2025-01-01");
+        assert_eq!(summary["period_end"], "2025-01-03");
+
+        let stations = summary["vending_station_summaries"].as_array().unwrap();
+        let central = stations
+            .iter()
+            .find(|station| station["vending_station"] == "Central")
+            .unwrap();
+        assert_eq!(central["total_transactions"], 2);
+        assert_eq!(central["total_amount"], 30.0);
+        let days: Vec<_> = central["daily_summaries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|day| {
+                (
+                    day["date"].as_str().unwrap(),
+                    day["total_amount"].as_f64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(days, [("2025-01-01", 10.0), ("2025-01-03", 20.0)]);
+
+        // Series fields are only added on request
+        let first_day = &central["daily_summaries"][0];
+        assert!(first_day.get("cumulative_amount").is_none());
+        assert!(first_day.get("amount_avg_7d").is_none());
+    }
+
+    #[actix_web::test]
+    async fn summary_adds_running_totals_and_moving_averages_on_request() {
+        let (status, body) = get(
+            RequestContext::anonymous(),
+            "/api/vending-records/summary?start_date=2025-01-01&end_date=2025-01-03&cumulative=true&moving_averages=true",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let central = body["data"]["vending_station_summaries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|station| station["vending_station"] == "Central")
+            .unwrap()
+            .clone();
+        let days = central["daily_summaries"].as_array().unwrap();
+
+        assert_eq!(days[0]["cumulative_amount"], 10.0);
+        assert_eq!(days[1]["cumulative_amount"], 30.0);
+        assert_eq!(days[1]["cumulative_kwh"], 60.0);
+        // Calendar-day averages, shortened at the start: (10 + 0 + 20) / 3 on the third day
+        assert_eq!(days[0]["amount_avg_7d"], 10.0);
+        assert_eq!(days[1]["amount_avg_7d"], 10.0);
+        assert_eq!(days[1]["amount_avg_30d"], 10.0);
+    }
+
+    #[actix_web::test]
+    async fn scoped_callers_only_see_their_stations() {
+        let manager = scoped(&["station_manager"], &["Central"]);
+        let (status, body) = get(
+            manager.clone(),
+            "/api/vending-records?start_date=2025-01-01&end_date=2025-01-03",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&body), ["a1", "a3"]);
+
+        let (_, body) = get(
+            manager,
+            "/api/vending-records/summary?start_date=2025-01-01&end_date=2025-01-03",
+        )
+        .await;
+        let summary = &body["data"];
+        assert_eq!(summary["total_transactions"], 2);
+        assert_eq!(summary["total_amount"], 30.0);
+        let stations: Vec<_> = summary["vending_station_summaries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|station| station["vending_station"].as_str().unwrap())
+            .collect();
+        assert_eq!(stations, ["Central"]);
+    }
+
+    #[actix_web::test]
+    async fn scoped_caller_without_stations_sees_nothing_and_unscoped_roles_see_all() {
+        let (_, body) = get(
+            scoped(&["cashier"], &[]),
+            "/api/vending-records?start_date=2025-01-01&end_date=2025-01-03",
+        )
+        .await;
+        assert!(ids(&body).is_empty());
+
+        let (_, body) = get(
+            scoped(&["station_manager", "finance"], &["Central"]),
+            "/api/vending-records?start_date=2025-01-01&end_date=2025-01-03",
+        )
+        .await;
+        assert_eq!(ids(&body), ["a1", "a2", "a3"]);
+    }
+}
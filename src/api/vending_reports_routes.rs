@@ -0,0 +1,705 @@
+use actix_web::{HttpResponse, web};
+use chrono::{Duration, Utc};
+use mongodb::Database;
+use serde::Deserialize;
+
+use super::vending_records_routes::{
+    ApiResponse, DateRangeQuery, decode_metadata, resolve_date_range, resolve_timezone,
+};
+use crate::analytics::{AnomalyContext, analyze_transaction_gaps, detect_anomalies};
+use crate::auth::{RequestContext, ScopedVendingRecords};
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::model::{
+    AnomalyFlag, AnomalyKind, AnomalyScanResult, AnomalySeverity, CashCount, CreditRunoutReport,
+    InactiveMeterReport, MeterBehaviour, MeterBehaviourReport, OperatorReconciliationReport,
+    TransactionGapReport,
+};
+use crate::repositories::{
+    AnomalyFlagRepository, CashCountRepository, MongoDbAnomalyFlagRepository,
+    MongoDbCashCountRepository, MongoDbStationHoursRepository, MongoDbTransactionIdRuleRepository,
+    StationHoursRepository, TransactionIdRuleRepository,
+};
+
+const DEFAULT_INACTIVE_DAYS: u32 = 30;
+const DEFAULT_HISTORY_DAYS: u32 = 365;
+const DEFAULT_MIN_PURCHASES: u32 = 3;
+const DEFAULT_BEHAVIOUR_WINDOW_DAYS: u32 = 90;
+const MAX_BEHAVIOUR_WINDOW_DAYS: u32 = 365;
+const DEFAULT_BEHAVIOUR_LIMIT: usize = 100;
+const MAX_BEHAVIOUR_LIMIT: usize = 1000;
+const DEFAULT_RUNOUT_WITHIN_DAYS: u32 = 7;
+const DEFAULT_RUNOUT_HISTORY_DAYS: u32 = 180;
+const DEFAULT_NEAR_DUPLICATE_SECONDS: u32 = 10;
+const MAX_NEAR_DUPLICATE_SECONDS: u32 = 3600;
+// History before a scanned period that defines each meter's normal purchase amounts
+const ANOMALY_BASELINE_DAYS: i64 = 180;
+
+#[derive(Deserialize)]
+pub struct InactiveMetersQuery {
+    pub inactive_days: Option<u32>, // Days without a vend (default 30)
+    pub history_days: Option<u32>, // Purchase history considered (default 365, at most max_range_days)
+    pub min_purchases: Option<u32>, // Purchases required to count as a regular buyer (default 3)
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MeterBehaviourSort {
+    Trend,
+    AverageTicket,
+    Interval,
+    Regularity,
+    Purchases,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct MeterBehaviourQuery {
+    pub window_days: Option<u32>, // Length of the recent and prior windows (default 90)
+    pub sort: Option<MeterBehaviourSort>, // trend (default), average_ticket, interval, regularity or purchases
+    pub order: Option<SortOrder>,         // asc (default) or desc
+    pub limit: Option<usize>,             // Maximum number of meters returned (1-1000, default 100)
+}
+
+#[derive(Deserialize)]
+pub struct CreditRunoutQuery {
+    pub within_days: Option<u32>,  // Run-out horizon in days (default 7)
+    pub history_days: Option<u32>, // Purchase history used for consumption rates (default 180, at most max_range_days)
+}
+
+#[derive(Deserialize)]
+pub struct OperatorReconciliationQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub timezone: Option<String>, // IANA timezone used to split days (default UTC)
+    pub user_id: Option<String>,  // Restrict the report to one operator
+}
+
+#[derive(Deserialize)]
+pub struct RecordCashCountRequest {
+    pub user_id: String,
+    pub date: String,            // YYYY-MM-DD
+    pub vending_station: String, // Station whose till was counted
+    pub counted_amount: f64,
+    pub notes: Option<String>,
+    pub timezone: Option<String>, // IANA timezone the date is local to (default UTC)
+}
+
+#[derive(Deserialize)]
+pub struct AnomalyQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub min_severity: Option<AnomalySeverity>, // low, medium or high
+    pub kind: Option<AnomalyKind>, // unusual_amount, outside_opening_hours, repeated_vends or tariff_mismatch
+}
+
+#[derive(Deserialize)]
+pub struct IntegrityQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub window_seconds: Option<u32>, // Same meter and amount within this many seconds (default 10)
+}
+
+fn behaviour_sort_value(meter: &MeterBehaviour, sort: MeterBehaviourSort) -> Option<f64> {
+    match sort {
+        MeterBehaviourSort::Trend => meter.amount_trend_percent,
+        MeterBehaviourSort::AverageTicket => Some(meter.average_ticket),
+        MeterBehaviourSort::Interval => meter.average_days_between_purchases,
+        MeterBehaviourSort::Regularity => meter.regularity_cv,
+        MeterBehaviourSort::Purchases => Some(meter.purchase_count as f64),
+    }
+}
+
+fn behaviour_window(window_days: Option<u32>) -> AppResult<u32> {
+    let window_days = window_days.unwrap_or(DEFAULT_BEHAVIOUR_WINDOW_DAYS);
+    if window_days == 0 || window_days > MAX_BEHAVIOUR_WINDOW_DAYS {
+        return Err(AppError::Validation(format!(
+            "Invalid window_days value: {}. Must be between 1 and {}",
+            window_days, MAX_BEHAVIOUR_WINDOW_DAYS
+        )));
+    }
+    Ok(window_days)
+}
+
+fn behaviour_report(
+    as_of: chrono::DateTime<Utc>,
+    window_days: u32,
+    meters: Vec<MeterBehaviour>,
+) -> MeterBehaviourReport {
+    let recent_start = as_of - Duration::days(window_days as i64);
+    let prior_start = recent_start - Duration::days(window_days as i64);
+    MeterBehaviourReport {
+        as_of,
+        window_days,
+        prior_period_start: prior_start.format("%Y-%m-%d").to_string(),
+        recent_period_start: recent_start.format("%Y-%m-%d").to_string(),
+        meters,
+    }
+}
+
+/// List meters that bought regularly but have not vended for the given number of days
+pub async fn get_inactive_meters(
+    repo: ScopedVendingRecords,
+    query: web::Query<InactiveMetersQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let inactive_days = query.inactive_days.unwrap_or(DEFAULT_INACTIVE_DAYS);
+    let history_days = query.history_days.unwrap_or(DEFAULT_HISTORY_DAYS);
+    let min_purchases = query.min_purchases.unwrap_or(DEFAULT_MIN_PURCHASES);
+
+    if inactive_days == 0 || history_days <= inactive_days {
+        return Err(AppError::Validation(format!(
+            "Invalid window: inactive_days ({}) must be positive and smaller than history_days ({})",
+            inactive_days, history_days
+        )));
+    }
+    if history_days > config.queries.max_range_days {
+        return Err(AppError::Validation(format!(
+            "Invalid history_days value: {}. Must be at most {} days",
+            history_days, config.queries.max_range_days
+        )));
+    }
+    if min_purchases < 2 {
+        return Err(AppError::Validation(
+            "Invalid min_purchases value: at least 2 purchases are needed to infer an interval"
+                .to_string(),
+        ));
+    }
+
+    let as_of = Utc::now();
+    let history_start = as_of - Duration::days(history_days as i64);
+    let inactive_since = as_of - Duration::days(inactive_days as i64);
+
+    let meters = repo
+        .get_inactive_meters(history_start, inactive_since, min_purchases)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Found {} meters without a vend in the last {} days",
+            meters.len(),
+            inactive_days
+        ),
+        data: Some(InactiveMeterReport {
+            as_of,
+            inactive_days,
+            history_start: history_start.format("%Y-%m-%d").to_string(),
+            min_purchases,
+            meters,
+        }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Sortable report of per-meter purchase behaviour and trend
+pub async fn get_meter_behaviour_report(
+    repo: ScopedVendingRecords,
+    query: web::Query<MeterBehaviourQuery>,
+) -> AppResult<HttpResponse> {
+    let window_days = behaviour_window(query.window_days)?;
+    let sort = query.sort.unwrap_or(MeterBehaviourSort::Trend);
+    let order = query.order.unwrap_or(SortOrder::Asc);
+    let limit = query.limit.unwrap_or(DEFAULT_BEHAVIOUR_LIMIT);
+    if limit == 0 || limit > MAX_BEHAVIOUR_LIMIT {
+        return Err(AppError::Validation(format!(
+            "Invalid limit value: {}. Must be between 1 and {}",
+            limit, MAX_BEHAVIOUR_LIMIT
+        )));
+    }
+    let as_of = Utc::now();
+
+    let mut meters = repo.get_meter_behaviour(as_of, window_days, None).await?;
+
+    // Meters without a value for the sort key always go last
+    meters.sort_by(
+        |a, b| match (behaviour_sort_value(a, sort), behaviour_sort_value(b, sort)) {
+            (Some(a), Some(b)) => match order {
+                SortOrder::Asc => a.total_cmp(&b),
+                SortOrder::Desc => b.total_cmp(&a),
+            },
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        },
+    );
+    let total = meters.len();
+    meters.truncate(limit);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved behaviour for {} of {} meters",
+            meters.len(),
+            total
+        ),
+        data: Some(behaviour_report(as_of, window_days, meters)),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Purchase behaviour and trend for a single meter
+pub async fn get_meter_behaviour(
+    repo: ScopedVendingRecords,
+    path: web::Path<String>,
+    query: web::Query<MeterBehaviourQuery>,
+) -> AppResult<HttpResponse> {
+    let meter_number = path.into_inner();
+    let window_days = behaviour_window(query.window_days)?;
+    let as_of = Utc::now();
+
+    let meters = repo
+        .get_meter_behaviour(as_of, window_days, Some(&meter_number))
+        .await?;
+    if meters.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No vends found for meter {} in the last {} days",
+            meter_number,
+            window_days * 2
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Retrieved behaviour for meter {}", meter_number),
+        data: Some(behaviour_report(as_of, window_days, meters)),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// List meters whose credit is expected to run out within the given number of days
+pub async fn get_credit_runout(
+    repo: ScopedVendingRecords,
+    query: web::Query<CreditRunoutQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let within_days = query.within_days.unwrap_or(DEFAULT_RUNOUT_WITHIN_DAYS);
+    let history_days = query.history_days.unwrap_or(DEFAULT_RUNOUT_HISTORY_DAYS);
+    if history_days == 0 || history_days > config.queries.max_range_days {
+        return Err(AppError::Validation(format!(
+            "Invalid history_days value: {}. Must be between 1 and {} days",
+            history_days, config.queries.max_range_days
+        )));
+    }
+
+    let as_of = Utc::now();
+    let history_start = as_of - Duration::days(history_days as i64);
+
+    let meters = repo.get_credit_runout(history_start, as_of).await?;
+
+    // Meters that ran out more than within_days ago belong to the inactive meters report
+    let meters: Vec<_> = meters
+        .into_iter()
+        .filter(|meter| {
+            meter.days_until_runout <= within_days as f64
+                && meter.days_until_runout > -(within_days as f64)
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Found {} meters expected to run out of credit within {} days",
+            meters.len(),
+            within_days
+        ),
+        data: Some(CreditRunoutReport {
+            as_of,
+            within_days,
+            history_start: history_start.format("%Y-%m-%d").to_string(),
+            meters,
+        }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Per-operator daily cash-up report with counted cash and variance
+pub async fn get_operator_reconciliation(
+    db: web::Data<Database>,
+    repo: ScopedVendingRecords,
+    query: web::Query<OperatorReconciliationQuery>,
+    config: web::Data<AppConfig>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+    let timezone = resolve_timezone(&query.timezone)?;
+    let period_start = start_date.format("%Y-%m-%d").to_string();
+    let period_end = end_date.format("%Y-%m-%d").to_string();
+
+    // Create repositories
+    let cash_count_repo = MongoDbCashCountRepository::from_collection(
+        db.collection::<CashCount>(&config.collections.cash_counts),
+    );
+
+    let shifts = repo
+        .get_operator_shifts(start_date, end_date, &timezone, query.user_id.as_deref())
+        .await?;
+
+    // Shift dates are local days in `timezone`, which can fall either side of the UTC period
+    let shift_dates = shifts.iter().map(|shift| shift.date.as_str());
+    let cash_counts = match (shift_dates.clone().min(), shift_dates.max()) {
+        (Some(first_day), Some(last_day)) => {
+            let scope = context.access_scope();
+            cash_count_repo
+                .get_cash_counts(first_day, last_day, scope.vending_stations.as_deref())
+                .await?
+        }
+        _ => Vec::new(),
+    };
+
+    let shifts: Vec<_> = shifts
+        .into_iter()
+        .map(|mut shift| {
+            // Counts recorded before stations were stored apply to the operator's whole day
+            let counts: Vec<f64> = cash_counts
+                .iter()
+                .filter(|count| count.user_id == shift.user_id && count.date == shift.date)
+                .filter(|count| {
+                    count.vending_station.is_empty()
+                        || shift.vending_stations.contains(&count.vending_station)
+                })
+                .map(|count| count.counted_amount)
+                .collect();
+            if !counts.is_empty() {
+                let counted_cash: f64 = counts.iter().sum();
+                shift.counted_cash = Some(counted_cash);
+                shift.variance = Some(counted_cash - shift.expected_cash);
+            }
+            shift
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Retrieved {} operator shifts for period {} to {}",
+            shifts.len(),
+            period_start,
+            period_end
+        ),
+        data: Some(OperatorReconciliationReport {
+            period_start,
+            period_end,
+            timezone,
+            shifts,
+        }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Record the cash counted for an operator's day at a station
+pub async fn record_cash_count(
+    db: web::Data<Database>,
+    repo: ScopedVendingRecords,
+    body: web::Json<RecordCashCountRequest>,
+    config: web::Data<AppConfig>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let request = body.into_inner();
+
+    if request.user_id.trim().is_empty() {
+        return Err(AppError::Validation("user_id is required".to_string()));
+    }
+    if request.vending_station.trim().is_empty() {
+        return Err(AppError::Validation(
+            "vending_station is required".to_string(),
+        ));
+    }
+    let Ok(day) = chrono::NaiveDate::parse_from_str(&request.date, "%Y-%m-%d") else {
+        return Err(AppError::Validation(format!(
+            "Invalid date format: '{}'. Use YYYY-MM-DD",
+            request.date
+        )));
+    };
+    if !request.counted_amount.is_finite() || request.counted_amount < 0.0 {
+        return Err(AppError::Validation(
+            "counted_amount must be a non-negative number".to_string(),
+        ));
+    }
+    let timezone = resolve_timezone(&request.timezone)?;
+
+    // Scoped callers may only count tills where the operator vended within their scope that day
+    if !context.access_scope().is_unrestricted() {
+        // A local day lies within a day either side of the UTC one
+        let midnight = day.and_time(chrono::NaiveTime::MIN).and_utc();
+        let shifts = repo
+            .get_operator_shifts(
+                midnight - Duration::days(1),
+                midnight + Duration::days(2),
+                &timezone,
+                Some(&request.user_id),
+            )
+            .await?;
+        let in_scope = shifts.iter().any(|shift| {
+            shift.user_id == request.user_id
+                && shift.date == request.date
+                && shift.vending_stations.contains(&request.vending_station)
+        });
+        if !in_scope {
+            return Err(AppError::Forbidden(format!(
+                "No vends by {} at {} on {} within your stations and communities",
+                request.user_id, request.vending_station, request.date
+            )));
+        }
+    }
+
+    // Create repository
+    let cash_count_repo = MongoDbCashCountRepository::from_collection(
+        db.collection::<CashCount>(&config.collections.cash_counts),
+    );
+
+    let cash_count = CashCount {
+        user_id: request.user_id,
+        date: request.date,
+        vending_station: request.vending_station,
+        counted_amount: request.counted_amount,
+        notes: request.notes,
+        recorded_by: context.subject,
+        recorded_at: Utc::now(),
+    };
+
+    let cash_count = cash_count_repo.save_cash_count(cash_count).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Recorded cash count for {} on {}",
+            cash_count.user_id, cash_count.date
+        ),
+        data: Some(cash_count),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Scan vends in a date range for anomalies and store the resulting flags
+pub async fn scan_anomalies(
+    db: web::Data<Database>,
+    repo: ScopedVendingRecords,
+    query: web::Query<AnomalyQuery>,
+    config: web::Data<AppConfig>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+
+    // Create repositories
+    let station_hours_repo = MongoDbStationHoursRepository::from_collection(
+        db.collection(&config.collections.station_opening_hours),
+    );
+    let flag_repo = MongoDbAnomalyFlagRepository::from_collection(
+        db.collection::<AnomalyFlag>(&config.collections.anomaly_flags),
+    );
+
+    let baseline_start = start_date - Duration::days(ANOMALY_BASELINE_DAYS);
+    let batch = repo.get_vending_records(start_date, end_date).await?;
+    let records = batch.records;
+    let baselines = repo
+        .get_meter_amount_baselines(baseline_start, start_date)
+        .await?;
+    let opening_hours = station_hours_repo.get_opening_hours().await?;
+
+    let baselines = baselines
+        .into_iter()
+        .map(|baseline| (baseline.meter_number.clone(), baseline))
+        .collect();
+    let opening_hours = opening_hours
+        .into_iter()
+        .map(|hours| (hours.vending_station.clone(), hours))
+        .collect();
+    let detected_at = Utc::now();
+    let flags = detect_anomalies(
+        &records,
+        &AnomalyContext {
+            baselines: &baselines,
+            opening_hours: &opening_hours,
+            detected_at,
+        },
+    );
+
+    // The rescan replaces every earlier flag on the vends it covered
+    let saved = flag_repo
+        .replace_flags(
+            start_date,
+            end_date,
+            &context.access_scope(),
+            detected_at,
+            &flags,
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Scanned {} vends and raised {} anomaly flags",
+            records.len(),
+            saved
+        ),
+        data: Some(AnomalyScanResult {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            records_scanned: records.len() as u32,
+            flags_raised: saved as u32,
+            high_severity: flags
+                .iter()
+                .filter(|flag| flag.severity == AnomalySeverity::High)
+                .count() as u32,
+        }),
+        error_code: None,
+        metadata: decode_metadata(batch.decode_report),
+    }))
+}
+
+/// List stored anomaly flags on vends within a date range
+pub async fn get_anomalies(
+    db: web::Data<Database>,
+    query: web::Query<AnomalyQuery>,
+    config: web::Data<AppConfig>,
+    context: RequestContext,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+
+    // Create repository
+    let flag_repo = MongoDbAnomalyFlagRepository::from_collection(
+        db.collection::<AnomalyFlag>(&config.collections.anomaly_flags),
+    );
+
+    let flags = flag_repo
+        .get_flags(
+            start_date,
+            end_date,
+            &context.access_scope(),
+            query.min_severity,
+            query.kind,
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Retrieved {} anomaly flags", flags.len()),
+        data: Some(flags),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Data integrity report of duplicate tokens, transaction IDs and near-duplicate vends
+pub async fn get_integrity_report(
+    repo: ScopedVendingRecords,
+    query: web::Query<IntegrityQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+    let window_seconds = query
+        .window_seconds
+        .unwrap_or(DEFAULT_NEAR_DUPLICATE_SECONDS);
+    if window_seconds == 0 || window_seconds > MAX_NEAR_DUPLICATE_SECONDS {
+        return Err(AppError::Validation(format!(
+            "Invalid window_seconds value: {}. Must be between 1 and {}",
+            window_seconds, MAX_NEAR_DUPLICATE_SECONDS
+        )));
+    }
+
+    let report = repo
+        .get_integrity_report(start_date, end_date, window_seconds)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Found {} duplicate tokens, {} duplicate transaction IDs and {} near-duplicate vend groups",
+            report.duplicate_tokens.len(),
+            report.duplicate_transaction_ids.len(),
+            report.near_duplicate_vends.len()
+        ),
+        data: Some(report),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Per-station transaction ID sequences with missing ranges and out-of-order vends
+pub async fn get_transaction_gaps(
+    db: web::Data<Database>,
+    repo: ScopedVendingRecords,
+    query: web::Query<DateRangeQuery>,
+    config: web::Data<AppConfig>,
+) -> AppResult<HttpResponse> {
+    let (start_date, end_date) =
+        resolve_date_range(&query.start_date, &query.end_date, &config.queries)?;
+
+    // Create repositories
+    let rule_repo = MongoDbTransactionIdRuleRepository::from_collection(
+        db.collection(&config.collections.transaction_id_rules),
+    );
+
+    let entries = repo.get_transaction_sequence(start_date, end_date).await?;
+    let rules = rule_repo.get_rules().await?;
+
+    let rules = rules
+        .into_iter()
+        .map(|rule| (rule.vending_station.clone(), rule))
+        .collect();
+    let (sequences, unparsed) = analyze_transaction_gaps(&entries, &rules);
+    let missing: u64 = sequences
+        .iter()
+        .map(|sequence| sequence.missing_count)
+        .sum();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!(
+            "Analyzed {} transaction IDs across {} sequences: {} missing, {} unparsable",
+            entries.len(),
+            sequences.len(),
+            missing,
+            unparsed.iter().map(|station| station.count).sum::<u32>()
+        ),
+        data: Some(TransactionGapReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            sequences,
+            unparsed,
+        }),
+        error_code: None,
+        metadata: None,
+    }))
+}
+
+/// Configure vending report routes
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/reports")
+            .route("/inactive-meters", web::get().to(get_inactive_meters))
+            .route(
+                "/meter-behaviour",
+                web::get().to(get_meter_behaviour_report),
+            )
+            .route(
+                "/meter-behaviour/{meter_number}",
+                web::get().to(get_meter_behaviour),
+            )
+            .route("/credit-runout", web::get().to(get_credit_runout))
+            .route(
+                "/operator-reconciliation",
+                web::get().to(get_operator_reconciliation),
+            )
+            .route(
+                "/operator-reconciliation/cash-counts",
+                web::post().to(record_cash_count),
+            )
+            .route("/anomalies", web::get().to(get_anomalies))
+            .route("/anomalies/scan", web::post().to(scan_anomalies))
+            .route("/integrity", web::get().to(get_integrity_report))
+            .route("/transaction-gaps", web::get().to(get_transaction_gaps)),
+    );
+}
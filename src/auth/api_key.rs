@@ -0,0 +1,20 @@
+use sha2::{Digest, Sha256};
+
+const KEY_PREFIX: &str = "jep_";
+// Random bytes in a key; 32 bytes leave nothing to guess, so a plain SHA-256 is safe to store
+const KEY_BYTES: usize = 32;
+// Characters kept in listings to tell keys apart, e.g. "jep_3f9a1c0b"
+const DISPLAY_PREFIX_LEN: usize = KEY_PREFIX.len() + 8;
+
+/// Create a new key, returning the key and the prefix shown in listings
+pub fn generate_api_key() -> (String, String) {
+    let bytes: [u8; KEY_BYTES] = rand::random();
+    let key = format!("{}{}", KEY_PREFIX, hex::encode(bytes));
+    let prefix = key[..DISPLAY_PREFIX_LEN].to_string();
+    (key, prefix)
+}
+
+/// Hex SHA-256 of a key, the only form in which keys are stored
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
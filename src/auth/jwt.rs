@@ -0,0 +1,576 @@
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    errors::ErrorKind,
+    jwk::{AlgorithmParameters, Jwk, KeyAlgorithm, PublicKeyUse},
+};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use super::{AuthMethod, RequestContext};
+use crate::config::JwtConfig;
+use crate::error::AppError;
+
+struct VerificationKey {
+    kid: Option<String>,
+    key: DecodingKey,
+    algorithms: Vec<Algorithm>, // Accepted for this key, so a token can't pick a weaker one
+}
+
+/// Verifies bearer tokens against keys loaded at startup, without calling an identity provider
+pub struct JwtVerifier {
+    keys: Vec<VerificationKey>,
+    issuer: String,
+    audience: String,
+    leeway_secs: u64,
+    roles_claim: String,
+    stations_claim: String,
+    communities_claim: String,
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+// Algorithms a JWK may be used with when it does not name one itself
+fn jwk_algorithms(parameters: &AlgorithmParameters) -> Vec<Algorithm> {
+    match parameters {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(_) => vec![Algorithm::ES256, Algorithm::ES384],
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        AlgorithmParameters::OctetKey(_) => {
+            vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512]
+        }
+    }
+}
+
+// Verification algorithm named by a JWK's `alg`, or None for encryption algorithms
+fn signing_algorithm(algorithm: KeyAlgorithm) -> Option<Algorithm> {
+    match algorithm {
+        KeyAlgorithm::HS256 => Some(Algorithm::HS256),
+        KeyAlgorithm::HS384 => Some(Algorithm::HS384),
+        KeyAlgorithm::HS512 => Some(Algorithm::HS512),
+        KeyAlgorithm::ES256 => Some(Algorithm::ES256),
+        KeyAlgorithm::ES384 => Some(Algorithm::ES384),
+        KeyAlgorithm::RS256 => Some(Algorithm::RS256),
+        KeyAlgorithm::RS384 => Some(Algorithm::RS384),
+        KeyAlgorithm::RS512 => Some(Algorithm::RS512),
+        KeyAlgorithm::PS256 => Some(Algorithm::PS256),
+        KeyAlgorithm::PS384 => Some(Algorithm::PS384),
+        KeyAlgorithm::PS512 => Some(Algorithm::PS512),
+        KeyAlgorithm::EdDSA => Some(Algorithm::EdDSA),
+        KeyAlgorithm::RSA1_5 | KeyAlgorithm::RSA_OAEP | KeyAlgorithm::RSA_OAEP_256 => None,
+    }
+}
+
+// A verification key for one JWKS entry, or why the entry cannot verify token signatures
+fn verification_key(entry: Value) -> Result<VerificationKey, String> {
+    let key_use = entry.get("use").cloned();
+    let jwk: Jwk = serde_json::from_value(entry).map_err(|e| e.to_string())?;
+    if let Some(public_key_use) = &jwk.common.public_key_use
+        && *public_key_use != PublicKeyUse::Signature
+    {
+        return Err(format!(
+            "not a signature key (\"use\": {})",
+            key_use.unwrap_or(Value::Null)
+        ));
+    }
+    let algorithms = match jwk.common.key_algorithm {
+        Some(algorithm) => vec![
+            signing_algorithm(algorithm)
+                .ok_or_else(|| format!("{} is not a signature algorithm", algorithm))?,
+        ],
+        None => jwk_algorithms(&jwk.algorithm),
+    };
+    let key = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+    Ok(VerificationKey {
+        kid: jwk.common.key_id,
+        key,
+        algorithms,
+    })
+}
+
+// Keys that cannot verify signatures (encryption keys, unknown algorithms) are skipped with a
+// warning, so an issuer publishing new key types does not stop the server from starting
+fn load_jwks(path: &Path) -> Result<Vec<VerificationKey>, String> {
+    #[derive(serde::Deserialize)]
+    struct RawJwkSet {
+        keys: Vec<Value>,
+    }
+
+    let jwks: RawJwkSet = serde_json::from_slice(&read_file(path)?)
+        .map_err(|e| format!("Invalid JWKS in {}: {}", path.display(), e))?;
+
+    let mut keys = Vec::new();
+    for entry in jwks.keys {
+        let kid = entry.get("kid").cloned().unwrap_or(Value::Null);
+        match verification_key(entry) {
+            Ok(key) => keys.push(key),
+            Err(reason) => eprintln!(
+                "⚠️  Skipping JWKS key {} in {}: {}",
+                kid,
+                path.display(),
+                reason
+            ),
+        }
+    }
+    if keys.is_empty() {
+        return Err(format!(
+            "JWKS in {} contains no usable signature keys",
+            path.display()
+        ));
+    }
+    Ok(keys)
+}
+
+fn load_key_file(path: &Path, algorithm: Algorithm) -> Result<VerificationKey, String> {
+    let contents = read_file(path)?;
+    let key = match algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            // Tolerate the trailing newline most editors add to a secret file
+            DecodingKey::from_secret(contents.trim_ascii_end())
+        }
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(&contents)
+            .map_err(|e| format!("Invalid EC public key in {}: {}", path.display(), e))?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(&contents)
+            .map_err(|e| format!("Invalid Ed25519 public key in {}: {}", path.display(), e))?,
+        _ => DecodingKey::from_rsa_pem(&contents)
+            .map_err(|e| format!("Invalid RSA public key in {}: {}", path.display(), e))?,
+    };
+    Ok(VerificationKey {
+        kid: None,
+        key,
+        algorithms: vec![algorithm],
+    })
+}
+
+// Claims may hold a list of strings or a single space-separated string
+fn string_list(claims: &Map<String, Value>, claim: &str) -> Result<Vec<String>, AppError> {
+    match claims.get(claim) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(values)) => Ok(values.split_whitespace().map(String::from).collect()),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| {
+                value.as_str().map(String::from).ok_or_else(|| {
+                    AppError::Unauthorized(format!("Token claim '{}' must hold strings", claim))
+                })
+            })
+            .collect(),
+        Some(_) => Err(AppError::Unauthorized(format!(
+            "Token claim '{}' must be a string or a list of strings",
+            claim
+        ))),
+    }
+}
+
+impl JwtVerifier {
+    /// Load the configured JWKS file or key file; errors are reported at startup
+    pub fn from_config(config: &JwtConfig) -> Result<Self, String> {
+        let keys = match (&config.jwks_file, &config.key_file, &config.algorithm) {
+            (Some(jwks_file), _, _) => load_jwks(jwks_file)?,
+            (None, Some(key_file), Some(algorithm)) => {
+                let algorithm = algorithm
+                    .parse::<Algorithm>()
+                    .map_err(|_| format!("Unsupported JWT algorithm '{}'", algorithm))?;
+                vec![load_key_file(key_file, algorithm)?]
+            }
+            _ => return Err("auth.jwt needs jwks_file, or key_file with algorithm".to_string()),
+        };
+
+        Ok(Self {
+            keys,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            leeway_secs: config.leeway_secs,
+            roles_claim: config.roles_claim.clone(),
+            stations_claim: config.stations_claim.clone(),
+            communities_claim: config.communities_claim.clone(),
+        })
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    // Tokens pick a JWKS key by `kid`; a lone key without a kid (key_file) is used for any token
+    fn key_for(&self, kid: Option<&str>) -> Result<&VerificationKey, AppError> {
+        let key = match (kid, self.keys.as_slice()) {
+            (_, [key]) if key.kid.is_none() => Some(key),
+            (Some(kid), keys) => keys.iter().find(|key| key.kid.as_deref() == Some(kid)),
+            (None, [key]) => Some(key),
+            (None, _) => None,
+        };
+        key.ok_or_else(|| {
+            AppError::Unauthorized("Token was not signed by a trusted key".to_string())
+        })
+    }
+
+    /// Check signature, expiry, issuer and audience, and map the claims into a request context
+    pub fn verify(&self, token: &str) -> Result<RequestContext, AppError> {
+        let header = decode_header(token)
+            .map_err(|_| AppError::Unauthorized("Malformed bearer token".to_string()))?;
+        let key = self.key_for(header.kid.as_deref())?;
+        if !key.algorithms.contains(&header.alg) {
+            return Err(AppError::Unauthorized(format!(
+                "Token algorithm {:?} is not accepted for this key",
+                header.alg
+            )));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = key.algorithms.clone();
+        validation.leeway = self.leeway_secs;
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<Map<String, Value>>(token, &key.key, &validation)
+            .map_err(|e| {
+                AppError::Unauthorized(match e.kind() {
+                    ErrorKind::ExpiredSignature => "Token has expired".to_string(),
+                    ErrorKind::ImmatureSignature => "Token is not valid yet".to_string(),
+                    ErrorKind::InvalidIssuer => {
+                        "Token was issued by an untrusted issuer".to_string()
+                    }
+                    ErrorKind::InvalidAudience => "Token is not meant for this API".to_string(),
+                    ErrorKind::MissingRequiredClaim(claim) => {
+                        format!("Token is missing the '{}' claim", claim)
+                    }
+                    ErrorKind::InvalidSignature => "Token signature is invalid".to_string(),
+                    _ => "Invalid bearer token".to_string(),
+                })
+            })?
+            .claims;
+
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .filter(|subject| !subject.is_empty())
+            .ok_or_else(|| AppError::Unauthorized("Token 'sub' must be a string".to_string()))?;
+
+        Ok(RequestContext {
+            subject: subject.to_string(),
+            name: None,
+            roles: string_list(&claims, &self.roles_claim)?,
+            stations: string_list(&claims, &self.stations_claim)?,
+            communities: string_list(&claims, &self.communities_claim)?,
+            auth_method: AuthMethod::Jwt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    const SECRET: &[u8] = b"jwks-signing-secret-0123456789abcdef";
+    const OTHER_SECRET: &[u8] = b"jwks-other-secret-0123456789abcdef";
+
+    // A key file written for one test and removed when it ends
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(contents: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("jep-jwt-{}", uuid::Uuid::new_v4()));
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn config() -> JwtConfig {
+        JwtConfig {
+            enabled: true,
+            issuer: "https://id.example.com".to_string(),
+            audience: "jep-api".to_string(),
+            ..JwtConfig::default()
+        }
+    }
+
+    fn key_file_verifier() -> JwtVerifier {
+        // The trailing newline is ignored
+        let file = TempFile::new(b"jwks-signing-secret-0123456789abcdef\n");
+        JwtVerifier::from_config(&JwtConfig {
+            key_file: Some(file.0.clone()),
+            algorithm: Some("HS256".to_string()),
+            ..config()
+        })
+        .unwrap()
+    }
+
+    fn jwks(keys: Value) -> TempFile {
+        TempFile::new(json!({ "keys": keys }).to_string().as_bytes())
+    }
+
+    fn jwks_verifier(keys: Value) -> Result<JwtVerifier, String> {
+        let file = jwks(keys);
+        JwtVerifier::from_config(&JwtConfig {
+            jwks_file: Some(file.0.clone()),
+            ..config()
+        })
+    }
+
+    fn signing_key(kid: &str) -> Value {
+        json!({
+            "kty": "oct",
+            "kid": kid,
+            "use": "sig",
+            "alg": "HS256",
+            "k": "andrcy1zaWduaW5nLXNlY3JldC0wMTIzNDU2Nzg5YWJjZGVm"
+        })
+    }
+
+    fn claims() -> Value {
+        json!({
+            "sub": "user-7",
+            "iss": "https://id.example.com",
+            "aud": "jep-api",
+            "exp": Utc::now().timestamp() + 600,
+            "roles": ["station_manager"],
+            "stations": "Central Harbour",
+            "communities": ["North"]
+        })
+    }
+
+    fn token(kid: Option<&str>, secret: &[u8], claims: &Value) -> String {
+        let header = Header {
+            kid: kid.map(str::to_string),
+            ..Header::new(Algorithm::HS256)
+        };
+        encode(&header, claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn rejection(verifier: &JwtVerifier, token: &str) -> String {
+        match verifier.verify(token) {
+            Err(AppError::Unauthorized(message)) => message,
+            other => panic!("expected Unauthorized, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_claims_into_request_context() {
+        let context = key_file_verifier()
+            .verify(&token(None, SECRET, &claims()))
+            .unwrap();
+
+        assert_eq!(context.subject, "user-7");
+        assert_eq!(context.name, None);
+        assert_eq!(context.roles, vec!["station_manager"]);
+        // A space-separated claim is split like a list
+        assert_eq!(context.stations, vec!["Central", "Harbour"]);
+        assert_eq!(context.communities, vec!["North"]);
+        assert_eq!(context.auth_method, AuthMethod::Jwt);
+    }
+
+    #[test]
+    fn reads_claim_names_from_config() {
+        let file = TempFile::new(SECRET);
+        let verifier = JwtVerifier::from_config(&JwtConfig {
+            key_file: Some(file.0.clone()),
+            algorithm: Some("HS256".to_string()),
+            roles_claim: "jep_roles".to_string(),
+            ..config()
+        })
+        .unwrap();
+        let mut claims = claims();
+        claims["jep_roles"] = json!(["finance"]);
+
+        let context = verifier.verify(&token(None, SECRET, &claims)).unwrap();
+        assert_eq!(context.roles, vec!["finance"]);
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let message = rejection(&key_file_verifier(), &token(None, OTHER_SECRET, &claims()));
+        assert_eq!(message, "Token signature is invalid");
+    }
+
+    #[test]
+    fn rejects_expired_token_beyond_leeway() {
+        let verifier = key_file_verifier();
+        let mut claims = claims();
+
+        // Within the default 60 seconds of leeway
+        claims["exp"] = json!(Utc::now().timestamp() - 10);
+        assert!(verifier.verify(&token(None, SECRET, &claims)).is_ok());
+
+        claims["exp"] = json!(Utc::now().timestamp() - 3600);
+        assert_eq!(
+            rejection(&verifier, &token(None, SECRET, &claims)),
+            "Token has expired"
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_issuer_and_audience() {
+        let verifier = key_file_verifier();
+
+        let mut claims = claims();
+        claims["iss"] = json!("https://elsewhere.example.com");
+        assert_eq!(
+            rejection(&verifier, &token(None, SECRET, &claims)),
+            "Token was issued by an untrusted issuer"
+        );
+
+        let mut claims = self::claims();
+        claims["aud"] = json!("another-api");
+        assert_eq!(
+            rejection(&verifier, &token(None, SECRET, &claims)),
+            "Token is not meant for this API"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_or_empty_subject() {
+        let verifier = key_file_verifier();
+
+        let mut claims = claims();
+        claims.as_object_mut().unwrap().remove("sub");
+        assert_eq!(
+            rejection(&verifier, &token(None, SECRET, &claims)),
+            "Token is missing the 'sub' claim"
+        );
+
+        claims["sub"] = json!("");
+        assert_eq!(
+            rejection(&verifier, &token(None, SECRET, &claims)),
+            "Token 'sub' must be a string"
+        );
+    }
+
+    #[test]
+    fn rejects_claims_that_are_not_strings() {
+        let mut claims = claims();
+        claims["stations"] = json!([1, 2]);
+        assert_eq!(
+            rejection(&key_file_verifier(), &token(None, SECRET, &claims)),
+            "Token claim 'stations' must hold strings"
+        );
+    }
+
+    #[test]
+    fn rejects_algorithm_not_accepted_for_key() {
+        let header = Header::new(Algorithm::HS384);
+        let token = encode(&header, &claims(), &EncodingKey::from_secret(SECRET)).unwrap();
+        assert!(rejection(&key_file_verifier(), &token).contains("HS384"));
+    }
+
+    #[test]
+    fn kid_less_key_file_accepts_any_kid() {
+        let verifier = key_file_verifier();
+        assert!(verifier.verify(&token(None, SECRET, &claims())).is_ok());
+        assert!(
+            verifier
+                .verify(&token(Some("rotated-2025"), SECRET, &claims()))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn jwks_picks_key_by_kid() {
+        let mut other = signing_key("other");
+        other["k"] = json!("andrcy1vdGhlci1zZWNyZXQtMDEyMzQ1Njc4OWFiY2RlZg");
+        let verifier = jwks_verifier(json!([signing_key("main"), other])).unwrap();
+        assert_eq!(verifier.key_count(), 2);
+
+        assert!(
+            verifier
+                .verify(&token(Some("main"), SECRET, &claims()))
+                .is_ok()
+        );
+        assert!(
+            verifier
+                .verify(&token(Some("other"), OTHER_SECRET, &claims()))
+                .is_ok()
+        );
+        // Signed by one key but naming the other
+        assert_eq!(
+            rejection(&verifier, &token(Some("other"), SECRET, &claims())),
+            "Token signature is invalid"
+        );
+        // Several keys and no kid to choose between them
+        assert_eq!(
+            rejection(&verifier, &token(None, SECRET, &claims())),
+            "Token was not signed by a trusted key"
+        );
+        assert_eq!(
+            rejection(&verifier, &token(Some("unknown"), SECRET, &claims())),
+            "Token was not signed by a trusted key"
+        );
+    }
+
+    #[test]
+    fn jwks_skips_encryption_keys() {
+        let encryption_key = json!({
+            "kty": "oct",
+            "kid": "enc",
+            "use": "enc",
+            "k": "ZW5jcnlwdGlvbi1zZWNyZXQtMDEyMzQ1Njc4OWFiY2RlZg"
+        });
+        let mut unknown_algorithm = signing_key("future");
+        unknown_algorithm["alg"] = json!("HS1024");
+        let verifier = jwks_verifier(json!([
+            signing_key("main"),
+            encryption_key.clone(),
+            unknown_algorithm
+        ]))
+        .unwrap();
+
+        assert_eq!(verifier.key_count(), 1);
+        assert_eq!(
+            rejection(
+                &verifier,
+                &token(
+                    Some("enc"),
+                    b"encryption-secret-0123456789abcdef",
+                    &claims()
+                )
+            ),
+            "Token was not signed by a trusted key"
+        );
+
+        let error = jwks_verifier(json!([encryption_key])).err().unwrap();
+        assert!(
+            error.contains("contains no usable signature keys"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn verification_key_explains_rejections() {
+        let mut encryption_key = signing_key("enc");
+        encryption_key["use"] = json!("enc");
+        assert_eq!(
+            verification_key(encryption_key).err().unwrap(),
+            "not a signature key (\"use\": \"enc\")"
+        );
+
+        let mut rsa_oaep = signing_key("oaep");
+        rsa_oaep.as_object_mut().unwrap().remove("use");
+        rsa_oaep["alg"] = json!("RSA-OAEP");
+        assert!(
+            verification_key(rsa_oaep)
+                .err()
+                .unwrap()
+                .contains("is not a signature algorithm")
+        );
+    }
+}
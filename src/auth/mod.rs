@@ -0,0 +1,233 @@
+mod api_key;
+mod jwt;
+mod roles;
+
+pub use api_key::{generate_api_key, hash_api_key};
+pub use jwt::JwtVerifier;
+pub use roles::{Permission, Role, required_permission};
+
+use actix_web::{FromRequest, HttpMessage, HttpRequest, dev::Payload, http::Method, web};
+use serde::Serialize;
+use std::future::{Ready, ready};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::model::AccessScope;
+use crate::repositories::VendingRecordRepository;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    ApiKey,
+    BootstrapKey,
+    Jwt,
+    None, // Authentication is disabled
+}
+
+/// Who is calling and what they may see, set by the authentication middleware. Handlers take it
+/// as an extractor argument.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestContext {
+    pub subject: String,          // JWT `sub`, or the id of the API key
+    pub name: Option<String>,     // API key name
+    pub roles: Vec<String>,       // Role names as granted; unknown names are ignored
+    pub stations: Vec<String>,    // Vending stations a scoped role is limited to
+    pub communities: Vec<String>, // Communities a scoped role is limited to
+    pub auth_method: AuthMethod,
+}
+
+impl RequestContext {
+    /// Context for requests when authentication is disabled
+    pub fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".to_string(),
+            name: None,
+            roles: Vec::new(),
+            stations: Vec::new(),
+            communities: Vec::new(),
+            auth_method: AuthMethod::None,
+        }
+    }
+
+    fn known_roles(&self) -> impl Iterator<Item = Role> + '_ {
+        self.roles.iter().filter_map(|role| role.parse().ok())
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.auth_method == AuthMethod::None
+            || self
+                .known_roles()
+                .any(|role| role.permissions().contains(&permission))
+    }
+
+    /// Vending records the caller may see. Any unscoped role (admin, finance, auditor) sees
+    /// everything; station managers and cashiers only see their stations and communities.
+    pub fn access_scope(&self) -> AccessScope {
+        if self.auth_method == AuthMethod::None || self.known_roles().any(|role| !role.is_scoped())
+        {
+            return AccessScope::default();
+        }
+        let listed = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        match (listed(&self.stations), listed(&self.communities)) {
+            // A scoped role without any stations or communities sees nothing
+            (None, None) => AccessScope {
+                vending_stations: Some(Vec::new()),
+                communities: None,
+            },
+            (vending_stations, communities) => AccessScope {
+                vending_stations,
+                communities,
+            },
+        }
+    }
+
+    /// Reject the request with 403 unless one of the caller's roles allows it
+    pub fn authorize(&self, method: &Method, path: &str) -> Result<(), AppError> {
+        match required_permission(method, path) {
+            Some(permission) if !self.has_permission(permission) => {
+                Err(AppError::Forbidden(format!(
+                    "{} {} is not allowed for roles [{}]",
+                    method,
+                    path,
+                    self.roles.join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "'{}' ({})", name, self.subject),
+            None => write!(f, "{}", self.subject),
+        }
+    }
+}
+
+impl FromRequest for RequestContext {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let context = req.extensions().get::<RequestContext>().cloned();
+        ready(Ok(context.unwrap_or_else(RequestContext::anonymous)))
+    }
+}
+
+/// The vending record repository restricted to the caller's access scope. Handlers take it
+/// instead of the shared repository so every query only covers what the caller may see.
+pub struct ScopedVendingRecords(Arc<dyn VendingRecordRepository>);
+
+impl Deref for ScopedVendingRecords {
+    type Target = dyn VendingRecordRepository;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl FromRequest for ScopedVendingRecords {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Some(repo) = req.app_data::<web::Data<Arc<dyn VendingRecordRepository>>>() else {
+            return ready(Err(AppError::Database(
+                "Vending record repository is not configured".to_string(),
+            )));
+        };
+        let scope = match RequestContext::from_request(req, payload).into_inner() {
+            Ok(context) => context.access_scope(),
+            Err(error) => return ready(Err(error)),
+        };
+        if scope.is_unrestricted() {
+            return ready(Ok(Self(repo.get_ref().clone())));
+        }
+        ready(Ok(Self(repo.with_scope(scope))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(roles: &[&str], stations: &[&str], communities: &[&str]) -> RequestContext {
+        let list = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        RequestContext {
+            subject: "user-1".to_string(),
+            name: None,
+            roles: list(roles),
+            stations: list(stations),
+            communities: list(communities),
+            auth_method: AuthMethod::Jwt,
+        }
+    }
+
+    #[test]
+    fn scoped_roles_see_their_stations_and_communities() {
+        let scope = context(&["station_manager"], &["Central"], &["North"]).access_scope();
+        assert_eq!(scope.vending_stations, Some(vec!["Central".to_string()]));
+        assert_eq!(scope.communities, Some(vec!["North".to_string()]));
+    }
+
+    #[test]
+    fn scoped_role_without_stations_or_communities_sees_nothing() {
+        let scope = context(&["cashier"], &[], &[]).access_scope();
+        assert!(!scope.allows(Some("Central"), Some("North")));
+    }
+
+    #[test]
+    fn any_unscoped_role_lifts_the_scope() {
+        let scope = context(&["cashier", "auditor"], &["Central"], &[]).access_scope();
+        assert!(scope.is_unrestricted());
+        assert!(RequestContext::anonymous().access_scope().is_unrestricted());
+    }
+
+    #[test]
+    fn unknown_roles_grant_nothing() {
+        let context = context(&["superuser"], &[], &[]);
+        assert!(!context.access_scope().is_unrestricted());
+        assert!(
+            context
+                .authorize(&Method::GET, "/api/vending-records")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn authorize_checks_the_route_permission() {
+        let auditor = context(&["auditor"], &[], &[]);
+        assert!(
+            auditor
+                .authorize(&Method::GET, "/api/admin/rollups")
+                .is_ok()
+        );
+        assert!(matches!(
+            auditor.authorize(&Method::POST, "/api/admin/rollups/refresh"),
+            Err(AppError::Forbidden(_))
+        ));
+
+        let manager = context(&["station_manager"], &["Central"], &[]);
+        assert!(
+            manager
+                .authorize(
+                    &Method::POST,
+                    "/api/reports/operator-reconciliation/cash-counts"
+                )
+                .is_ok()
+        );
+        assert!(
+            manager
+                .authorize(&Method::GET, "/api/admin/rollups")
+                .is_err()
+        );
+        assert!(
+            RequestContext::anonymous()
+                .authorize(&Method::DELETE, "/api/admin/api-keys/k1")
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use actix_web::http::Method;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Roles granted through the `roles` token claim or stored on an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Finance,
+    Auditor,
+    StationManager,
+    Cashier,
+}
+
+/// What a request needs to be allowed, derived from its method and route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ReadVending,     // Vending records and summary
+    ReadReports,     // Analytics and reports
+    ReadAdmin,       // Admin views: rollups, data quality, station settings, API keys
+    WriteCashCounts, // Record counted cash for a shift
+    RunAnomalyScan,  // Scan vends and store anomaly flags
+    Administer,      // Every other change
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::ReadVending,
+        Permission::ReadReports,
+        Permission::ReadAdmin,
+        Permission::WriteCashCounts,
+        Permission::RunAnomalyScan,
+        Permission::Administer,
+    ];
+}
+
+impl Role {
+    pub const ALL: [Role; 5] = [
+        Role::Admin,
+        Role::Finance,
+        Role::Auditor,
+        Role::StationManager,
+        Role::Cashier,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Finance => "finance",
+            Role::Auditor => "auditor",
+            Role::StationManager => "station_manager",
+            Role::Cashier => "cashier",
+        }
+    }
+
+    pub fn permissions(self) -> &'static [Permission] {
+        match self {
+            Role::Admin => &Permission::ALL,
+            Role::Finance => &[
+                Permission::ReadVending,
+                Permission::ReadReports,
+                Permission::WriteCashCounts,
+                Permission::RunAnomalyScan,
+            ],
+            // Sees everything, changes nothing
+            Role::Auditor => &[
+                Permission::ReadVending,
+                Permission::ReadReports,
+                Permission::ReadAdmin,
+            ],
+            Role::StationManager => &[
+                Permission::ReadVending,
+                Permission::ReadReports,
+                Permission::WriteCashCounts,
+            ],
+            Role::Cashier => &[Permission::ReadVending, Permission::WriteCashCounts],
+        }
+    }
+
+    /// Whether the role only sees the caller's own vending stations and communities
+    pub fn is_scoped(self) -> bool {
+        matches!(self, Role::StationManager | Role::Cashier)
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == value)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown role '{}'. Use one of: {}",
+                    value,
+                    Role::ALL.map(Role::as_str).join(", ")
+                )
+            })
+    }
+}
+
+/// Permission needed for a request to /api/*, or None for routes every caller may use.
+/// Routes not listed here need Administer, so new endpoints are closed until they are added.
+pub fn required_permission(method: &Method, path: &str) -> Option<Permission> {
+    let read = method == Method::GET || method == Method::HEAD;
+    match path {
+        _ if path.starts_with("/api/auth/") => None,
+        _ if read && path.starts_with("/api/vending-records") => Some(Permission::ReadVending),
+        _ if read && (path.starts_with("/api/analytics/") || path.starts_with("/api/reports/")) => {
+            Some(Permission::ReadReports)
+        }
+        "/api/reports/operator-reconciliation/cash-counts" if method == Method::POST => {
+            Some(Permission::WriteCashCounts)
+        }
+        "/api/reports/anomalies/scan" if method == Method::POST => Some(Permission::RunAnomalyScan),
+        _ if read && path.starts_with("/api/admin/") => Some(Permission::ReadAdmin),
+        _ => Some(Permission::Administer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_route_needs_its_permission() {
+        use Permission::*;
+        let routes = [
+            (Method::GET, "/api/auth/whoami", None),
+            (Method::GET, "/api/vending-records", Some(ReadVending)),
+            (Method::HEAD, "/api/vending-records", Some(ReadVending)),
+            (
+                Method::GET,
+                "/api/vending-records/summary",
+                Some(ReadVending),
+            ),
+            (Method::GET, "/api/analytics/statistics", Some(ReadReports)),
+            (Method::GET, "/api/analytics/leaderboard", Some(ReadReports)),
+            (Method::GET, "/api/analytics/forecast", Some(ReadReports)),
+            (Method::GET, "/api/analytics/heatmap", Some(ReadReports)),
+            (Method::GET, "/api/analytics/kpis", Some(ReadReports)),
+            (
+                Method::GET,
+                "/api/reports/inactive-meters",
+                Some(ReadReports),
+            ),
+            (
+                Method::GET,
+                "/api/reports/meter-behaviour",
+                Some(ReadReports),
+            ),
+            (
+                Method::GET,
+                "/api/reports/meter-behaviour/M1",
+                Some(ReadReports),
+            ),
+            (Method::GET, "/api/reports/credit-runout", Some(ReadReports)),
+            (
+                Method::GET,
+                "/api/reports/operator-reconciliation",
+                Some(ReadReports),
+            ),
+            (
+                Method::POST,
+                "/api/reports/operator-reconciliation/cash-counts",
+                Some(WriteCashCounts),
+            ),
+            (Method::GET, "/api/reports/anomalies", Some(ReadReports)),
+            (
+                Method::POST,
+                "/api/reports/anomalies/scan",
+                Some(RunAnomalyScan),
+            ),
+            (Method::GET, "/api/reports/integrity", Some(ReadReports)),
+            (
+                Method::GET,
+                "/api/reports/transaction-gaps",
+                Some(ReadReports),
+            ),
+            (Method::GET, "/api/admin/rollups", Some(ReadAdmin)),
+            (Method::POST, "/api/admin/rollups/refresh", Some(Administer)),
+            (Method::GET, "/api/admin/data-quality", Some(ReadAdmin)),
+            (
+                Method::GET,
+                "/api/admin/stations/opening-hours",
+                Some(ReadAdmin),
+            ),
+            (
+                Method::PUT,
+                "/api/admin/stations/A/opening-hours",
+                Some(Administer),
+            ),
+            (
+                Method::GET,
+                "/api/admin/stations/transaction-id-rules",
+                Some(ReadAdmin),
+            ),
+            (
+                Method::PUT,
+                "/api/admin/stations/A/transaction-id-rule",
+                Some(Administer),
+            ),
+            (Method::GET, "/api/admin/api-keys", Some(ReadAdmin)),
+            (Method::POST, "/api/admin/api-keys", Some(Administer)),
+            (
+                Method::POST,
+                "/api/admin/api-keys/k1/rotate",
+                Some(Administer),
+            ),
+            (Method::DELETE, "/api/admin/api-keys/k1", Some(Administer)),
+        ];
+
+        for (method, path, expected) in routes {
+            assert_eq!(
+                required_permission(&method, path),
+                expected,
+                "{} {}",
+                method,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn writes_outside_their_rules_and_unknown_routes_need_administer() {
+        for (method, path) in [
+            (Method::POST, "/api/vending-records"),
+            (Method::DELETE, "/api/reports/anomalies"),
+            (
+                Method::PUT,
+                "/api/reports/operator-reconciliation/cash-counts",
+            ),
+            (Method::POST, "/api/analytics/kpis"),
+            (Method::GET, "/api/something-new"),
+        ] {
+            assert_eq!(
+                required_permission(&method, path),
+                Some(Permission::Administer),
+                "{} {}",
+                method,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn role_permissions() {
+        use Permission::*;
+        assert_eq!(Role::Admin.permissions(), Permission::ALL);
+        assert!(!Role::Auditor.permissions().contains(&Administer));
+        assert!(!Role::Auditor.permissions().contains(&WriteCashCounts));
+        assert!(!Role::Finance.permissions().contains(&ReadAdmin));
+        assert!(
+            Role::StationManager
+                .permissions()
+                .contains(&WriteCashCounts)
+        );
+        assert!(!Role::StationManager.permissions().contains(&RunAnomalyScan));
+        assert_eq!(Role::Cashier.permissions(), [ReadVending, WriteCashCounts]);
+    }
+
+    #[test]
+    fn only_station_roles_are_scoped() {
+        let scoped: Vec<Role> = Role::ALL.into_iter().filter(|r| r.is_scoped()).collect();
+        assert_eq!(scoped, [Role::StationManager, Role::Cashier]);
+    }
+
+    #[test]
+    fn roles_parse_from_their_names() {
+        for role in Role::ALL {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+        }
+        let error = "superuser".parse::<Role>().unwrap_err();
+        assert!(error.contains("superuser") && error.contains("station_manager"));
+    }
+}
@@ -8,6 +8,22 @@ pub struct DatabaseConnection {
 
 impl DatabaseConnection {
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let connection = Self::new_unverified().await?;
+
+        // Test the connection by pinging the database
+        connection
+            .client
+            .database("admin")
+            .run_command(mongodb::bson::doc! {"ping": 1})
+            .await?;
+
+        println!("✅ Successfully connected to MongoDB");
+
+        Ok(connection)
+    }
+
+    /// Set up the client without contacting the server, which is only reached on first use
+    pub async fn new_unverified() -> Result<Self, Box<dyn std::error::Error>> {
         // Get MongoDB connection string from environment variable
         let connection_string = env::var("MONGODB_URI").map_err(
             |_| "MONGODB_URI env variable is required. Please set it in your .env file.",
@@ -21,14 +37,6 @@ impl DatabaseConnection {
         // Create MongoDB client
         let client = Client::with_uri_str(&connection_string).await?;
 
-        // Test the connection by pinging the database
-        client
-            .database("admin")
-            .run_command(mongodb::bson::doc! {"ping": 1})
-            .await?;
-
-        println!("✅ Successfully connected to MongoDB");
-
         let database = client.database(&database_name);
 
         Ok(DatabaseConnection { client, database })
@@ -0,0 +1,173 @@
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use mongodb::error::ErrorKind;
+
+use crate::api::ApiResponse;
+
+// MongoDB server code for an operation that exceeded its maxTimeMS
+const MAX_TIME_MS_EXPIRED: i32 = 50;
+
+/// Errors surfaced by repositories and handlers, each mapped to an HTTP status and error code
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    InsufficientData(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    #[error("Failed to decode stored data: {0}")]
+    Deserialization(String),
+    #[error("Database operation timed out: {0}")]
+    Timeout(String),
+    #[error("Database unavailable: {0}")]
+    DatabaseUnavailable(String),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    /// Machine-readable code returned in the `error_code` field of the response
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation(_) => "VALIDATION_ERROR",
+            AppError::Unauthorized(_) => "UNAUTHORIZED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::InsufficientData(_) => "INSUFFICIENT_HISTORY",
+            AppError::TooManyRequests(_) => "RATE_LIMITED",
+            AppError::Deserialization(_) => "DESERIALIZATION_ERROR",
+            AppError::Timeout(_) => "TIMEOUT",
+            AppError::DatabaseUnavailable(_) => "DATABASE_UNAVAILABLE",
+            AppError::Database(_) => "DATABASE_ERROR",
+        }
+    }
+
+    // Client errors explain themselves; server errors point to the logs instead of leaking details
+    fn public_message(&self) -> String {
+        match self {
+            AppError::Validation(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::InsufficientData(message)
+            | AppError::TooManyRequests(message) => message.clone(),
+            AppError::Deserialization(_) => {
+                "Failed to read stored data due to data format issues. Check server logs for details.".to_string()
+            }
+            AppError::Timeout(_) => {
+                "The database did not respond in time. Try a shorter date range or try again later.".to_string()
+            }
+            AppError::DatabaseUnavailable(_) => {
+                "The database is currently unavailable. Try again later.".to_string()
+            }
+            AppError::Database(_) => {
+                "Failed to process the request due to a database error. Check server logs for details.".to_string()
+            }
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for AppError {
+    fn from(error: mongodb::error::Error) -> Self {
+        match error.kind.as_ref() {
+            ErrorKind::BsonDeserialization(_) => AppError::Deserialization(error.to_string()),
+            ErrorKind::Io(io_error) if io_error.kind() == std::io::ErrorKind::TimedOut => {
+                AppError::Timeout(error.to_string())
+            }
+            ErrorKind::Command(command_error) if command_error.code == MAX_TIME_MS_EXPIRED => {
+                AppError::Timeout(error.to_string())
+            }
+            ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. }
+            | ErrorKind::Io(_) => AppError::DatabaseUnavailable(error.to_string()),
+            _ => AppError::Database(error.to_string()),
+        }
+    }
+}
+
+impl From<mongodb::bson::de::Error> for AppError {
+    fn from(error: mongodb::bson::de::Error) -> Self {
+        AppError::Deserialization(error.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for AppError {
+    fn from(error: mongodb::bson::ser::Error) -> Self {
+        AppError::Database(error.to_string())
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::ColumnDecode { .. } | sqlx::Error::Decode(_) => {
+                AppError::Deserialization(error.to_string())
+            }
+            sqlx::Error::PoolTimedOut => AppError::Timeout(error.to_string()),
+            sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolClosed => {
+                AppError::DatabaseUnavailable(error.to_string())
+            }
+            _ => AppError::Database(error.to_string()),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::InsufficientData(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Deserialization(_) | AppError::Database(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::DatabaseUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            eprintln!("Error handling request: {}", self);
+            eprintln!("Error details: {:?}", self);
+        }
+        HttpResponse::build(status).json(ApiResponse::<()> {
+            success: false,
+            message: self.public_message(),
+            data: None,
+            error_code: Some(self.code()),
+            metadata: None,
+        })
+    }
+}
+
+/// Query string errors (unknown enum values, non-numeric limits) as validation errors
+pub fn query_config() -> actix_web::web::QueryConfig {
+    actix_web::web::QueryConfig::default()
+        .error_handler(|err, _| AppError::Validation(err.to_string()).into())
+}
+
+/// Malformed request bodies as validation errors
+pub fn json_config() -> actix_web::web::JsonConfig {
+    actix_web::web::JsonConfig::default()
+        .error_handler(|err, _| AppError::Validation(err.to_string()).into())
+}
+
+/// Malformed path segments as validation errors
+pub fn path_config() -> actix_web::web::PathConfig {
+    actix_web::web::PathConfig::default()
+        .error_handler(|err, _| AppError::Validation(err.to_string()).into())
+}
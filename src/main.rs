@@ -1,11 +1,93 @@
+mod analytics;
 mod api;
+mod auth;
+mod config;
 mod database;
+mod error;
+mod middleware;
 mod model;
 mod repositories;
 
-use actix_web::{App, HttpServer, middleware::Logger, web};
-use api::{configure_health_routes, configure_vending_routes, configure_vending_summary_routes};
+use actix_web::{
+    App, HttpServer,
+    middleware::{Logger, from_fn},
+    web,
+};
+use api::{
+    ROLLUP_LOOKBACK_DAYS, configure_admin_routes, configure_analytics_routes,
+    configure_auth_routes, configure_health_routes, configure_report_routes,
+    configure_vending_routes, configure_vending_summary_routes,
+};
+use auth::JwtVerifier;
+use config::{AppConfig, CollectionNames, VendingRecordBackend, VendingRecordsConfig};
 use database::DatabaseConnection;
+use middleware::{RateLimiter, authenticate, cors_middleware, rate_limit};
+use repositories::{
+    ApiKeyRepository, DailyRollupRepository, InMemoryVendingRecordRepository,
+    MongoDbApiKeyRepository, MongoDbDailyRollupRepository, MongoDbVendingRecordRepository,
+    SqlVendingRecordRepository, VendingRecordRepository,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often the daily rollups are brought up to date in the background
+const ROLLUP_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Keep the daily rollups current so summaries only aggregate raw records for recent days
+fn spawn_rollup_refresh(database: mongodb::Database, collections: CollectionNames) {
+    tokio::spawn(async move {
+        let repo = MongoDbDailyRollupRepository::from_database(&database, &collections);
+        let mut interval = tokio::time::interval(ROLLUP_REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let today = chrono::Utc::now().date_naive();
+            match repo.refresh_incremental(today, ROLLUP_LOOKBACK_DAYS).await {
+                Ok(refresh) => println!(
+                    "🔄 Refreshed {} days of daily rollups ({} rollup documents)",
+                    refresh.days_refreshed, refresh.rollup_documents
+                ),
+                Err(e) => eprintln!("❌ Failed to refresh daily rollups: {}", e),
+            }
+        }
+    });
+}
+
+async fn vending_record_repository(
+    settings: &VendingRecordsConfig,
+    database: &mongodb::Database,
+    collections: &CollectionNames,
+) -> Result<Arc<dyn VendingRecordRepository>, Box<dyn std::error::Error>> {
+    // Presence of the URL for SQL backends is checked when the config is validated
+    let sql_url = || settings.database_url.as_deref().unwrap_or_default();
+
+    Ok(match settings.backend {
+        VendingRecordBackend::MongoDb => Arc::new(
+            MongoDbVendingRecordRepository::from_collection(
+                database.collection(&collections.vending_records),
+            )
+            .with_daily_rollups(MongoDbDailyRollupRepository::from_database(
+                database,
+                collections,
+            )),
+        ),
+        VendingRecordBackend::Memory => Arc::new(match &settings.seed_file {
+            Some(path) => InMemoryVendingRecordRepository::from_json_file(path).map_err(|e| {
+                format!(
+                    "Failed to load vending records from {}: {}",
+                    path.display(),
+                    e
+                )
+            })?,
+            None => InMemoryVendingRecordRepository::new(Vec::new()),
+        }),
+        VendingRecordBackend::Postgres => {
+            Arc::new(SqlVendingRecordRepository::connect_postgres(sql_url()).await?)
+        }
+        VendingRecordBackend::Sqlite => {
+            Arc::new(SqlVendingRecordRepository::connect_sqlite(sql_url()).await?)
+        }
+    })
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -19,11 +101,31 @@ async fn main() -> std::io::Result<()> {
         println!("✅ Loaded environment variables from .env file");
     }
 
-    // Initialize logger
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    // Load and validate settings before anything else starts
+    let config = match AppConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            eprintln!(
+                "💡 Check config.toml (or the file named by JEP_CONFIG) and any JEP__* variables"
+            );
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize logger; RUST_LOG still takes precedence over server.log_level
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or(&config.server.log_level));
 
-    // Initialize database connection
-    let db_connection = match DatabaseConnection::new().await {
+    let backend = config.vending_records.backend;
+
+    // Initialize database connection. When vending records live elsewhere MongoDB is only
+    // reached by the routes that need its other collections.
+    let connection = if backend == VendingRecordBackend::MongoDb {
+        DatabaseConnection::new().await
+    } else {
+        DatabaseConnection::new_unverified().await
+    };
+    let db_connection = match connection {
         Ok(connection) => {
             println!("✅ Database connection established");
             connection
@@ -35,27 +137,185 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    let vending_records = match vending_record_repository(
+        &config.vending_records,
+        &db_connection.database,
+        &config.collections,
+    )
+    .await
+    {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Daily rollups only speed up MongoDB summaries
+    match backend {
+        VendingRecordBackend::MongoDb => {
+            spawn_rollup_refresh(db_connection.database.clone(), config.collections.clone())
+        }
+        VendingRecordBackend::Memory => println!("🧪 Serving vending records from memory"),
+        VendingRecordBackend::Postgres => println!("🗄️  Serving vending records from PostgreSQL"),
+        VendingRecordBackend::Sqlite => println!("🗄️  Serving vending records from SQLite"),
+    }
+
+    let cors_policy = config.cors_policy();
+    println!(
+        "🌐 CORS policy ({:?}): {}",
+        config.server.environment,
+        if cors_policy.allowed_origins.is_empty() {
+            "no cross-origin requests allowed".to_string()
+        } else {
+            cors_policy.allowed_origins.join(", ")
+        }
+    );
+
+    if config.rate_limit.enabled {
+        println!(
+            "🚦 Rate limits per minute: {} per address, {} per authenticated client, {} for expensive routes",
+            config.rate_limit.per_ip_per_minute,
+            config.rate_limit.per_api_key_per_minute,
+            config.rate_limit.expensive_per_minute
+        );
+    } else {
+        println!("🚦 Rate limiting disabled");
+    }
+
+    // Bearer tokens are verified offline against keys read once at startup
+    let jwt_verifier = if config.auth.enabled && config.auth.jwt.enabled {
+        match JwtVerifier::from_config(&config.auth.jwt) {
+            Ok(verifier) => {
+                println!(
+                    "🔐 Accepting bearer tokens from {} ({} verification keys)",
+                    config.auth.jwt.issuer,
+                    verifier.key_count()
+                );
+                Some(web::Data::new(verifier))
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to load JWT verification keys: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if !config.auth.enabled {
+        println!(
+            "⚠️  Authentication disabled: /api/* routes are open to anyone who can reach the server"
+        );
+    }
+
+    let base_url = config.server.base_url();
     println!("🚀 Starting JEP-RS API Server...");
-    println!("📍 Server will be available at: http://127.0.0.1:8092");
-    println!("🏥 Health check endpoint: http://127.0.0.1:8092/health");
-    println!("🔍 Ready check endpoint: http://127.0.0.1:8092/health/ready");
-    println!("📊 Vending records API: http://127.0.0.1:8092/api/vending-records");
-    println!("📊 Vending summary API: http://127.0.0.1:8092/api/vending-records/summary");
+    println!("📍 Server will be available at: {}", base_url);
+    println!("🏥 Health check endpoint: {}/health", base_url);
+    println!("🔍 Ready check endpoint: {}/health/ready", base_url);
+    println!("📊 Vending records API: {}/api/vending-records", base_url);
+    println!(
+        "📊 Vending summary API: {}/api/vending-records/summary",
+        base_url
+    );
+    println!(
+        "📈 Vending statistics API: {}/api/analytics/statistics",
+        base_url
+    );
+    println!("🏆 Leaderboard API: {}/api/analytics/leaderboard", base_url);
+    println!(
+        "🔮 Revenue forecast API: {}/api/analytics/forecast",
+        base_url
+    );
+    println!(
+        "🗓️  Purchase heatmap API: {}/api/analytics/heatmap",
+        base_url
+    );
+    println!("📋 KPI API: {}/api/analytics/kpis", base_url);
+    println!(
+        "🔌 Inactive meters report: {}/api/reports/inactive-meters",
+        base_url
+    );
+    println!(
+        "📉 Meter behaviour report: {}/api/reports/meter-behaviour",
+        base_url
+    );
+    println!(
+        "⏳ Credit run-out report: {}/api/reports/credit-runout",
+        base_url
+    );
+    println!(
+        "💵 Operator reconciliation report: {}/api/reports/operator-reconciliation",
+        base_url
+    );
+    println!("🚨 Anomaly flags: {}/api/reports/anomalies", base_url);
+    println!("🧾 Integrity report: {}/api/reports/integrity", base_url);
+    println!(
+        "🔢 Transaction gap report: {}/api/reports/transaction-gaps",
+        base_url
+    );
+    println!("🗂️  Daily rollup status: {}/api/admin/rollups", base_url);
+    println!(
+        "🩺 Data quality report: {}/api/admin/data-quality",
+        base_url
+    );
+    println!("🔑 API keys: {}/api/admin/api-keys", base_url);
+    println!("🪪 Current identity: {}/api/auth/whoami", base_url);
+
+    let bind_address = (config.server.host.clone(), config.server.port);
+    let workers = config.server.workers;
+    let api_keys: Arc<dyn ApiKeyRepository> = Arc::new(MongoDbApiKeyRepository::from_collection(
+        db_connection
+            .database
+            .collection(&config.collections.api_keys),
+    ));
+    let rate_limiter = web::Data::new(RateLimiter::new(config.rate_limit.clone()));
+    let config = web::Data::new(config);
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             // Add database connection to app data
             .app_data(web::Data::new(db_connection.client.clone()))
             .app_data(web::Data::new(db_connection.database.clone()))
+            .app_data(web::Data::new(vending_records.clone()))
+            .app_data(web::Data::new(api_keys.clone()))
+            .app_data(config.clone())
+            .app_data(rate_limiter.clone())
+            // Only registered when bearer tokens are accepted
+            .configure(|cfg| {
+                if let Some(verifier) = &jwt_verifier {
+                    cfg.app_data(verifier.clone());
+                }
+            })
+            // Report malformed query strings, paths and bodies in the JSON error envelope
+            .app_data(error::query_config())
+            .app_data(error::path_config())
+            .app_data(error::json_config())
+            // Per-client request limits, stricter for summaries and reports. Runs after
+            // authentication so clients are counted by verified identity, not by what they send
+            .wrap(from_fn(rate_limit))
+            // Require a bearer token or API key on /api/* routes
+            .wrap(from_fn(authenticate))
             // Add logging middleware
             .wrap(Logger::default())
+            // Answer preflight requests and tag responses for browser dashboards on other origins
+            .wrap(cors_middleware(config.cors_policy()))
             // Configure health routes
             .configure(configure_health_routes)
             // Configure vending records routes
             .configure(configure_vending_routes)
             .configure(configure_vending_summary_routes)
-    })
-    .bind("127.0.0.1:8092")?
-    .run()
-    .await
+            // Configure analytics routes
+            .configure(configure_analytics_routes)
+            // Configure report routes
+            .configure(configure_report_routes)
+            // Configure admin routes
+            .configure(configure_admin_routes)
+            // Configure authentication routes
+            .configure(configure_auth_routes)
+    });
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+    server.bind(bind_address)?.run().await
 }
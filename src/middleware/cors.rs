@@ -0,0 +1,193 @@
+use actix_cors::Cors;
+use actix_web::http::header::RETRY_AFTER;
+
+use super::{RATE_LIMIT_LIMIT, RATE_LIMIT_REMAINING, RATE_LIMIT_RESET};
+use crate::config::CorsConfig;
+
+/// Build the CORS middleware for a validated policy. Preflight (OPTIONS) requests are answered by
+/// the middleware itself, so no route needs an OPTIONS handler.
+pub fn cors_middleware(policy: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(policy.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(policy.allowed_headers.iter().map(String::as_str))
+        .max_age(policy.max_age_secs)
+        // Let dashboards read their remaining quota and back off
+        .expose_headers([
+            RATE_LIMIT_LIMIT,
+            RATE_LIMIT_REMAINING,
+            RATE_LIMIT_RESET,
+            RETRY_AFTER,
+        ]);
+
+    for origin in &policy.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin().send_wildcard()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    if policy.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{configure_auth_routes, configure_health_routes};
+    use crate::config::{AppConfig, CorsProfiles};
+    use crate::middleware::authenticate;
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::header::HeaderName;
+    use actix_web::http::header::{
+        ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+        ORIGIN,
+    };
+    use actix_web::test::{TestRequest, call_service, init_service};
+    use actix_web::{App, http::StatusCode, middleware::from_fn, web};
+
+    const DASHBOARD: &str = "https://dashboard.example.com";
+
+    // The middleware order used by the server: CORS outside authentication
+    macro_rules! test_app {
+        ($policy:expr) => {
+            init_service(
+                App::new()
+                    .app_data(web::Data::new(AppConfig::default()))
+                    .wrap(from_fn(authenticate))
+                    .wrap(cors_middleware(&$policy))
+                    .configure(configure_health_routes)
+                    .configure(configure_auth_routes),
+            )
+            .await
+        };
+    }
+
+    fn production() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: vec![DASHBOARD.to_string()],
+            ..CorsProfiles::default().production
+        }
+    }
+
+    fn preflight(origin: &str) -> TestRequest {
+        TestRequest::default()
+            .method(actix_web::http::Method::OPTIONS)
+            .uri("/api/auth/whoami")
+            .insert_header((ORIGIN, origin))
+            .insert_header((ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+            .insert_header((ACCESS_CONTROL_REQUEST_HEADERS, "x-api-key"))
+    }
+
+    fn header<B>(response: &ServiceResponse<B>, name: HeaderName) -> Option<&str> {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn development_allows_local_dashboards_with_credentials() {
+        let app = test_app!(CorsProfiles::default().development);
+
+        let request = TestRequest::get()
+            .uri("/health/live")
+            .insert_header((ORIGIN, "http://localhost:5173"))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header(&response, ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("http://localhost:5173")
+        );
+        assert_eq!(
+            header(&response, ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            Some("true")
+        );
+
+        // Other origins are refused: the browser withholds a simple response that lacks the
+        // allow-origin header, and a preflight fails outright
+        let request = TestRequest::get()
+            .uri("/health/live")
+            .insert_header((ORIGIN, DASHBOARD))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_ORIGIN), None);
+        let response = call_service(&app, preflight(DASHBOARD).to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_ORIGIN), None);
+    }
+
+    #[actix_web::test]
+    async fn production_allows_only_configured_origins() {
+        let app = test_app!(production());
+
+        let request = TestRequest::get()
+            .uri("/health/live")
+            .insert_header((ORIGIN, DASHBOARD))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header(&response, ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(DASHBOARD)
+        );
+        assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_CREDENTIALS), None);
+
+        // The development dashboards are not trusted in production
+        let response = call_service(&app, preflight("http://localhost:3000").to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_ORIGIN), None);
+
+        // Requests without an Origin header are not cross-origin and pass straight through
+        let request = TestRequest::get().uri("/health/live").to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn preflight_is_answered_without_credentials() {
+        for (policy, origin) in [
+            (CorsProfiles::default().development, "http://127.0.0.1:3000"),
+            (production(), DASHBOARD),
+        ] {
+            let app = test_app!(policy);
+
+            let response = call_service(&app, preflight(origin).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_ORIGIN), Some(origin));
+            assert!(
+                header(&response, ACCESS_CONTROL_ALLOW_HEADERS)
+                    .unwrap()
+                    .contains("x-api-key")
+            );
+
+            // The request that follows still needs credentials, and its rejection stays
+            // readable by the dashboard
+            let request = TestRequest::get()
+                .uri("/api/auth/whoami")
+                .insert_header((ORIGIN, origin))
+                .to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_ORIGIN), Some(origin));
+        }
+    }
+
+    #[actix_web::test]
+    async fn wildcard_origin_is_sent_as_a_wildcard() {
+        let app = test_app!(CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            ..CorsConfig::default()
+        });
+
+        let request = TestRequest::get()
+            .uri("/health/live")
+            .insert_header((ORIGIN, "https://anywhere.example.org"))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, ACCESS_CONTROL_ALLOW_ORIGIN), Some("*"));
+    }
+}
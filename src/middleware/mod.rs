@@ -0,0 +1,9 @@
+mod authentication;
+mod cors;
+mod rate_limit;
+
+pub use authentication::authenticate;
+pub use cors::cors_middleware;
+pub use rate_limit::{
+    RATE_LIMIT_LIMIT, RATE_LIMIT_REMAINING, RATE_LIMIT_RESET, RateLimiter, rate_limit,
+};
@@ -0,0 +1,425 @@
+use actix_web::{
+    Error, HttpMessage, HttpResponse, ResponseError,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    middleware::Next,
+    web,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::auth::{AuthMethod, RequestContext};
+use crate::config::RateLimitConfig;
+use crate::error::AppError;
+
+pub const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+pub const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+const WINDOW: Duration = Duration::from_secs(60);
+// Probes from load balancers and orchestrators are never limited
+const EXEMPT_PATH_PREFIX: &str = "/health";
+
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+/// Outcome of counting one request against a bucket
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    reset_after: Duration,
+}
+
+impl Decision {
+    fn reset_secs(&self) -> u64 {
+        // Round up so clients never retry a moment too early
+        self.reset_after.as_secs() + u64::from(self.reset_after.subsec_nanos() > 0)
+    }
+}
+
+/// In-memory fixed-window counters keyed by client, shared by all workers
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    windows: Mutex<HashMap<String, Window>>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            windows: Mutex::new(HashMap::new()),
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    // Count one request against the bucket, or with `consume` false only look whether it would fit
+    fn count(&self, bucket: &str, limit: u32, now: Instant, consume: bool) -> Decision {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let window = windows.entry(bucket.to_string()).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= WINDOW {
+            window.started = now;
+            window.count = 0;
+        }
+
+        let allowed = window.count < limit;
+        if allowed && consume {
+            window.count += 1;
+        }
+        Decision {
+            allowed,
+            limit,
+            remaining: limit.saturating_sub(window.count),
+            reset_after: WINDOW.saturating_sub(now.duration_since(window.started)),
+        }
+    }
+
+    // Drop expired windows once a minute so clients that went away don't accumulate
+    fn sweep(&self, now: Instant) {
+        let mut last_sweep = self.last_sweep.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(*last_sweep) < WINDOW {
+            return;
+        }
+        *last_sweep = now;
+        self.windows
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|_, window| now.duration_since(window.started) < WINDOW);
+    }
+
+    fn check(&self, bucket: &str, limit: u32, now: Instant) -> Decision {
+        self.count(bucket, limit, now, true)
+    }
+
+    fn client_address(&self, req: &ServiceRequest) -> String {
+        let connection = req.connection_info();
+        if self.config.trust_forwarded_for {
+            connection.realip_remote_addr()
+        } else {
+            connection.peer_addr()
+        }
+        .unwrap_or("unknown")
+        .to_string()
+    }
+
+    /// Bucket and limit for the caller: the identity `authenticate` verified, otherwise its
+    /// address. Unverified credentials never pick the bucket, so they can't dodge the address limit.
+    fn client_bucket(&self, req: &ServiceRequest) -> (String, u32) {
+        let identity = req
+            .extensions()
+            .get::<RequestContext>()
+            .filter(|context| context.auth_method != AuthMethod::None)
+            .map(|context| format!("{:?}:{}", context.auth_method, context.subject));
+        match identity {
+            Some(identity) => (
+                format!("client:{}", identity),
+                self.config.per_api_key_per_minute,
+            ),
+            None => (
+                format!("ip:{}", self.client_address(req)),
+                self.config.per_ip_per_minute,
+            ),
+        }
+    }
+
+    fn failure_bucket(&self, req: &ServiceRequest) -> String {
+        format!("auth-failures:{}", self.client_address(req))
+    }
+
+    /// 429 response when the caller's address used up its failed authentications this minute.
+    /// Checked before credentials are verified, so guessing keys can't flood the key store.
+    pub fn reject_failed_authentications(&self, req: &ServiceRequest) -> Option<HttpResponse> {
+        if !self.config.enabled {
+            return None;
+        }
+        let decision = self.count(
+            &self.failure_bucket(req),
+            self.config.per_ip_per_minute,
+            Instant::now(),
+            false,
+        );
+        (!decision.allowed).then(|| too_many_requests(&decision))
+    }
+
+    /// Count a request whose credentials were missing or rejected against its address
+    pub fn record_failed_authentication(&self, req: &ServiceRequest) {
+        if self.config.enabled {
+            self.check(
+                &self.failure_bucket(req),
+                self.config.per_ip_per_minute,
+                Instant::now(),
+            );
+        }
+    }
+
+    fn is_expensive(&self, path: &str) -> bool {
+        self.config
+            .expensive_paths
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+fn set_rate_limit_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATE_LIMIT_RESET, HeaderValue::from(decision.reset_secs()));
+}
+
+fn too_many_requests(decision: &Decision) -> HttpResponse {
+    let error = AppError::TooManyRequests(format!(
+        "Rate limit of {} requests per minute exceeded. Retry in {} seconds.",
+        decision.limit,
+        decision.reset_secs()
+    ));
+    let mut response = error.error_response();
+    set_rate_limit_headers(response.headers_mut(), decision);
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(decision.reset_secs()));
+    response
+}
+
+/// Count each request against its client's per-minute limit, and against the stricter limit for
+/// expensive routes, answering 429 with Retry-After once a limit is used up. Runs after
+/// `authenticate`, so verified clients are counted by identity.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limiter = match req.app_data::<web::Data<RateLimiter>>() {
+        Some(limiter) if limiter.config.enabled => limiter.clone(),
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    if req.path().starts_with(EXEMPT_PATH_PREFIX) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let now = Instant::now();
+    limiter.sweep(now);
+
+    let (bucket, limit) = limiter.client_bucket(&req);
+    let mut decision = limiter.check(&bucket, limit, now);
+    if decision.allowed && limiter.is_expensive(req.path()) {
+        decision = limiter.check(
+            &format!("expensive:{}", bucket),
+            limiter.config.expensive_per_minute,
+            now,
+        );
+    }
+
+    if !decision.allowed {
+        let response = too_many_requests(&decision);
+        return Ok(req.into_response(response));
+    }
+
+    let mut response = next.call(req).await?.map_into_boxed_body();
+    set_rate_limit_headers(response.headers_mut(), &decision);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{TestRequest, call_service, init_service};
+    use actix_web::{App, http::StatusCode, middleware::from_fn};
+
+    fn limiter(per_ip: u32, per_api_key: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            per_ip_per_minute: per_ip,
+            per_api_key_per_minute: per_api_key,
+            expensive_per_minute: 1,
+            expensive_paths: vec!["/api/reports".to_string()],
+            ..RateLimitConfig::default()
+        })
+    }
+
+    fn request_from(address: &str) -> ServiceRequest {
+        TestRequest::default()
+            .peer_addr(address.parse().unwrap())
+            .to_srv_request()
+    }
+
+    #[test]
+    fn window_allows_the_limit_then_rejects_until_it_resets() {
+        let limiter = limiter(3, 3);
+        let start = Instant::now();
+
+        let remaining: Vec<u32> = (0..3)
+            .map(|_| limiter.check("ip:a", 3, start).remaining)
+            .collect();
+        assert_eq!(remaining, [2, 1, 0]);
+
+        let later = start + Duration::from_secs(20);
+        let rejected = limiter.check("ip:a", 3, later);
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.reset_after, Duration::from_secs(40));
+        assert_eq!(rejected.reset_secs(), 40);
+
+        let next_window = limiter.check("ip:a", 3, start + WINDOW);
+        assert!(next_window.allowed);
+        assert_eq!(next_window.remaining, 2);
+    }
+
+    #[test]
+    fn reset_seconds_round_up() {
+        let limiter = limiter(1, 1);
+        let start = Instant::now();
+        limiter.check("ip:a", 1, start);
+        let decision = limiter.check("ip:a", 1, start + Duration::from_millis(59_500));
+        assert_eq!(decision.reset_secs(), 1);
+    }
+
+    #[test]
+    fn buckets_are_counted_separately() {
+        let limiter = limiter(1, 1);
+        let now = Instant::now();
+        assert!(limiter.check("ip:a", 1, now).allowed);
+        assert!(!limiter.check("ip:a", 1, now).allowed);
+        assert!(limiter.check("ip:b", 1, now).allowed);
+    }
+
+    #[test]
+    fn looking_at_a_bucket_does_not_use_it_up() {
+        let limiter = limiter(1, 1);
+        let now = Instant::now();
+        assert!(limiter.count("ip:a", 1, now, false).allowed);
+        assert!(limiter.count("ip:a", 1, now, false).allowed);
+        assert!(limiter.check("ip:a", 1, now).allowed);
+        assert!(!limiter.count("ip:a", 1, now, false).allowed);
+    }
+
+    #[test]
+    fn sweep_drops_expired_windows_only() {
+        let limiter = limiter(5, 5);
+        let start = *limiter.last_sweep.lock().unwrap();
+        limiter.check("ip:old", 5, start);
+        limiter.check("ip:recent", 5, start + Duration::from_secs(30));
+
+        limiter.sweep(start + WINDOW);
+
+        let windows = limiter.windows.lock().unwrap();
+        assert!(!windows.contains_key("ip:old"));
+        assert!(windows.contains_key("ip:recent"));
+    }
+
+    #[actix_web::test]
+    async fn verified_clients_are_counted_by_identity_and_others_by_address() {
+        let limiter = limiter(2, 7);
+
+        let anonymous = request_from("10.0.0.1:4000");
+        assert_eq!(
+            limiter.client_bucket(&anonymous),
+            ("ip:10.0.0.1".to_string(), 2)
+        );
+
+        let verified = request_from("10.0.0.1:4000");
+        verified.extensions_mut().insert(RequestContext {
+            subject: "key-1".to_string(),
+            auth_method: AuthMethod::ApiKey,
+            ..RequestContext::anonymous()
+        });
+        assert_eq!(
+            limiter.client_bucket(&verified),
+            ("client:ApiKey:key-1".to_string(), 7)
+        );
+
+        // With authentication disabled every caller is anonymous, so the address decides
+        let disabled = request_from("10.0.0.2:4000");
+        disabled
+            .extensions_mut()
+            .insert(RequestContext::anonymous());
+        assert_eq!(limiter.client_bucket(&disabled).0, "ip:10.0.0.2");
+    }
+
+    #[actix_web::test]
+    async fn failed_authentications_lock_out_the_address() {
+        let limiter = limiter(2, 10);
+        let req = request_from("10.0.0.1:4000");
+        let other = request_from("10.0.0.2:4000");
+
+        for _ in 0..2 {
+            assert!(limiter.reject_failed_authentications(&req).is_none());
+            limiter.record_failed_authentication(&req);
+        }
+
+        let response = limiter.reject_failed_authentications(&req).unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(RETRY_AFTER));
+        assert!(limiter.reject_failed_authentications(&other).is_none());
+    }
+
+    #[actix_web::test]
+    async fn middleware_answers_429_once_the_limit_is_used_up() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(limiter(2, 10)))
+                .wrap(from_fn(rate_limit))
+                .route("/api/vending-records", web::get().to(HttpResponse::Ok))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let get = |path: &str| {
+            TestRequest::get()
+                .uri(path)
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .to_request()
+        };
+
+        let first = call_service(&app, get("/api/vending-records")).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers().get(RATE_LIMIT_LIMIT).unwrap(), "2");
+        assert_eq!(first.headers().get(RATE_LIMIT_REMAINING).unwrap(), "1");
+
+        call_service(&app, get("/api/vending-records")).await;
+        let rejected = call_service(&app, get("/api/vending-records")).await;
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(rejected.headers().get(RATE_LIMIT_REMAINING).unwrap(), "0");
+        assert!(rejected.headers().contains_key(RETRY_AFTER));
+
+        // Health probes are never limited
+        let health = call_service(&app, get("/health")).await;
+        assert_eq!(health.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn expensive_paths_have_their_own_stricter_limit() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(limiter(10, 10)))
+                .wrap(from_fn(rate_limit))
+                .route("/api/reports/kpis", web::get().to(HttpResponse::Ok))
+                .route("/api/vending-records", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let get = |path: &str| {
+            TestRequest::get()
+                .uri(path)
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .to_request()
+        };
+
+        assert_eq!(
+            call_service(&app, get("/api/reports/kpis")).await.status(),
+            StatusCode::OK
+        );
+        let rejected = call_service(&app, get("/api/reports/kpis")).await;
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(rejected.headers().get(RATE_LIMIT_LIMIT).unwrap(), "1");
+        assert_eq!(
+            call_service(&app, get("/api/vending-records"))
+                .await
+                .status(),
+            StatusCode::OK
+        );
+    }
+}
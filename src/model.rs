@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VendingRecord {
     #[serde(rename = "_id")]
     pub id: String,
@@ -41,6 +41,26 @@ pub struct DailySummary {
     pub total_transactions: u32,
     pub total_amount: f64,
     pub total_kwh: f64,
+    // Running totals since period start, only when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cumulative_amount: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cumulative_kwh: Option<f64>,
+    // Trailing calendar-day averages, only when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_avg_7d: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_avg_30d: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kwh_avg_7d: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kwh_avg_30d: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SummarySeriesOptions {
+    pub cumulative: bool,      // Running amount and kWh totals on each daily entry
+    pub moving_averages: bool, // 7- and 30-day moving averages on each daily entry
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -63,3 +83,590 @@ pub struct VendingSummary {
     pub period_end: String,   //YYYY-MM-DD
     pub vending_station_summaries: Vec<VendingStationSummary>,
 }
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DistributionStats {
+    pub count: u32,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct HistogramBucket {
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+    pub count: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MetricDistribution {
+    pub stats: DistributionStats,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct GroupStatistics {
+    pub name: String,
+    pub amount: DistributionStats,
+    pub kwh: DistributionStats,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct VendingStatistics {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub amount: MetricDistribution,
+    pub kwh: MetricDistribution,
+    pub vending_station_statistics: Vec<GroupStatistics>,
+    pub community_statistics: Vec<GroupStatistics>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardDimension {
+    Meter,
+    Customer,
+    Community,
+    Station,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardMetric {
+    Amount,
+    Kwh,
+    Transactions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LeaderboardEntry {
+    pub rank: u32,
+    pub name: String,
+    pub total_transactions: u32,
+    pub total_amount: f64,
+    pub total_kwh: f64,
+    pub share_percent: f64, // Share of the period total for the ranked metric
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub dimension: LeaderboardDimension,
+    pub metric: LeaderboardMetric,
+    pub total_transactions: u32,
+    pub total_amount: f64,
+    pub total_kwh: f64,
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InactiveMeter {
+    pub meter_number: String,
+    pub customer_name: Option<String>,
+    pub community: Option<String>,
+    pub vending_station: Option<String>,
+    pub first_purchase: DateTime<Utc>,
+    pub last_purchase: DateTime<Utc>,
+    pub purchase_count: u32,
+    pub typical_interval_days: f64,
+    pub days_since_last_purchase: f64,
+    pub overdue_factor: f64, // Days since last purchase divided by the typical interval
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InactiveMeterReport {
+    pub as_of: DateTime<Utc>,
+    pub inactive_days: u32,
+    pub history_start: String, //YYYY-MM-DD
+    pub min_purchases: u32,
+    pub meters: Vec<InactiveMeter>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeterBehaviour {
+    pub meter_number: String,
+    pub customer_name: Option<String>,
+    pub community: Option<String>,
+    pub vending_station: Option<String>,
+    pub purchase_count: u32,
+    pub average_ticket: f64,
+    pub average_days_between_purchases: Option<f64>,
+    pub interval_std_dev_days: Option<f64>,
+    pub regularity_cv: Option<f64>, // Std dev / mean of purchase intervals; lower is more regular
+    pub recent_transactions: u32,
+    pub recent_amount: f64,
+    pub prior_transactions: u32,
+    pub prior_amount: f64,
+    pub amount_trend_percent: Option<f64>, // Recent window vs prior window; None when prior is zero
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeterBehaviourReport {
+    pub as_of: DateTime<Utc>,
+    pub window_days: u32,
+    pub prior_period_start: String,  //YYYY-MM-DD
+    pub recent_period_start: String, //YYYY-MM-DD
+    pub meters: Vec<MeterBehaviour>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreditRunout {
+    pub meter_number: String,
+    pub customer_name: Option<String>,
+    pub community: Option<String>,
+    pub vending_station: Option<String>,
+    pub last_purchase: DateTime<Utc>,
+    pub last_kwh: f64,
+    pub remaining_credit_at_purchase: Option<f64>,
+    pub daily_consumption_kwh: f64,
+    pub estimated_runout: DateTime<Utc>,
+    pub days_until_runout: f64, // Negative when the credit is estimated to be used up already
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreditRunoutReport {
+    pub as_of: DateTime<Utc>,
+    pub within_days: u32,
+    pub history_start: String, //YYYY-MM-DD
+    pub meters: Vec<CreditRunout>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OperatorShift {
+    pub user_id: String,
+    pub date: String, //YYYY-MM-DD
+    pub vending_stations: Vec<String>,
+    pub transaction_count: u32,
+    pub total_amount: f64,
+    pub first_vend: DateTime<Utc>,
+    pub last_vend: DateTime<Utc>,
+    pub voided_count: u32,
+    pub voided_amount: f64,
+    pub expected_cash: f64, // Total amount net of reversals
+    pub counted_cash: Option<f64>,
+    pub variance: Option<f64>, // Counted minus expected; negative means cash is short
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OperatorReconciliationReport {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub timezone: String,
+    pub shifts: Vec<OperatorShift>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CashCount {
+    pub user_id: String,
+    pub date: String,            //YYYY-MM-DD
+    pub vending_station: String, // Station whose till was counted
+    pub counted_amount: f64,
+    pub notes: Option<String>,
+    pub recorded_by: String, // Subject of the token or API key that recorded the count
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RollupStatus {
+    pub refreshed_through: Option<String>, //YYYY-MM-DD, last complete day served from rollups
+    pub last_refresh_at: Option<DateTime<Utc>>,
+    pub changes_since: Option<DateTime<Utc>>, //Records written or updated after this are checked on the next refresh
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RollupRefresh {
+    pub start_date: Option<String>, //YYYY-MM-DD, None when nothing needed refreshing
+    pub end_date: Option<String>,   //YYYY-MM-DD
+    pub days_refreshed: u32,
+    pub changed_days: Vec<String>, //Already rolled-up days rebuilt because their records changed
+    pub rollup_documents: u64,
+    pub status: RollupStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForecastPoint {
+    pub date: String, //YYYY-MM-DD
+    pub forecast: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeriesForecast {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub rmse: f64,
+    pub points: Vec<ForecastPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HorizonTotal {
+    pub days: u32,
+    pub amount: f64,
+    pub kwh: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StationForecast {
+    pub vending_station: String,
+    pub amount: SeriesForecast,
+    pub kwh: SeriesForecast,
+    pub horizon_totals: Vec<HorizonTotal>, // Sum of point forecasts at 30/60/90 days
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevenueForecast {
+    pub history_start: String, //YYYY-MM-DD
+    pub history_end: String,   //YYYY-MM-DD
+    pub horizon_days: u32,
+    pub confidence_level: u32, // Percent coverage of the prediction intervals
+    pub overall: StationForecast,
+    pub vending_station_forecasts: Vec<StationForecast>,
+    pub skipped_stations: Vec<String>, // Stations with less than two weeks of history
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    UnusualAmount,
+    OutsideOpeningHours,
+    RepeatedVends,
+    TariffMismatch,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AnomalySeverity {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnomalyFlag {
+    pub record_id: String,
+    pub kind: AnomalyKind,
+    pub severity: AnomalySeverity,
+    pub timestamp: DateTime<Utc>,
+    pub meter_number: Option<String>,
+    pub vending_station: Option<String>,
+    pub community: Option<String>,
+    pub user_id: Option<String>,
+    pub amount: Option<f64>,
+    pub details: String,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnomalyScanResult {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub records_scanned: u32,
+    pub flags_raised: u32,
+    pub high_severity: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeterAmountBaseline {
+    pub meter_number: String,
+    pub purchase_count: u32,
+    pub mean_amount: f64,
+    pub std_dev_amount: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StationOpeningHours {
+    pub vending_station: String,
+    pub opens_at: String,  //HH:MM local time
+    pub closes_at: String, //HH:MM local time, earlier than opens_at for overnight hours
+    pub timezone: String,  //IANA timezone name
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateRecord {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub meter_number: Option<String>,
+    pub vending_station: Option<String>,
+    pub user_id: Option<String>,
+    pub amount: Option<f64>,
+    pub token: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub key: String, // The shared token, transaction ID, or meter and amount
+    pub count: u32,
+    pub records: Vec<DuplicateRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub near_duplicate_window_seconds: u32,
+    pub duplicate_tokens: Vec<DuplicateGroup>,
+    pub duplicate_transaction_ids: Vec<DuplicateGroup>,
+    pub near_duplicate_vends: Vec<DuplicateGroup>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransactionIdRule {
+    pub vending_station: String,
+    pub pattern: String, // Regex with a `seq` group and an optional `terminal` group
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionSequenceEntry {
+    pub record_id: String,
+    pub vending_station: String,
+    pub transaction_id: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MissingRange {
+    pub from: u64,
+    pub to: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutOfOrderVend {
+    pub transaction_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub previous_transaction_id: String,
+    pub previous_timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SequenceReset {
+    pub transaction_id: String, // First vend after the counter restarted
+    pub timestamp: DateTime<Utc>,
+    pub previous_transaction_id: String,
+    pub previous_sequence: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SequenceGapReport {
+    pub vending_station: String,
+    pub terminal: Option<String>,
+    pub pattern: String,
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    pub received: u32,
+    pub missing_count: u64,
+    pub duplicate_count: u32,
+    pub missing_ranges: Vec<MissingRange>,
+    pub out_of_order: Vec<OutOfOrderVend>, // Higher sequence numbers vended earlier than lower ones
+    pub resets: Vec<SequenceReset>, // Counter restarts; the runs between them are checked separately
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnparsedTransactionIds {
+    pub vending_station: String,
+    pub count: u32,
+    pub samples: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionGapReport {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub sequences: Vec<SequenceGapReport>,
+    pub unparsed: Vec<UnparsedTransactionIds>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VendingHeatmap {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub timezone: String,
+    pub vending_station: Option<String>,
+    pub community: Option<String>,
+    pub days: Vec<String>,                 // Row labels, Monday first
+    pub transaction_counts: Vec<Vec<u32>>, // [day][hour], 7 rows of 24 local hours
+    pub amounts: Vec<Vec<f64>>,            // [day][hour], 7 rows of 24 local hours
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KpiSet {
+    pub community: Option<String>, // None for the overall figures
+    pub revenue: f64,
+    pub total_kwh: f64,
+    pub active_customers: u32, // Meters with at least one vend in the period
+    pub new_customers: u32,    // Active meters whose first ever vend is in the period
+    pub arpu: f64,             // Revenue per active customer
+    pub kwh_per_customer_month: f64,
+    pub previous_revenue: f64,
+    pub revenue_growth_percent: Option<f64>, // None when the previous period had no revenue
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KpiReport {
+    pub period_start: String,          //YYYY-MM-DD
+    pub period_end: String,            //YYYY-MM-DD
+    pub previous_period_start: String, //YYYY-MM-DD
+    pub period_months: f64,
+    pub overall: KpiSet,
+    pub communities: Vec<KpiSet>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DecodeReport {
+    pub skipped_documents: u32, // Documents that could not be read at all
+    pub skipped_ids: Vec<String>,
+    pub partially_decoded: u32, // Documents returned with malformed fields left empty
+    pub partially_decoded_ids: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct VendingRecordBatch {
+    pub records: Vec<VendingRecord>,
+    pub decode_report: DecodeReport,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataQualityProblem {
+    MissingField,
+    WrongType,
+    NegativeAmount,
+    KwhTariffMismatch,
+    FutureTimestamp,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataQualityIssue {
+    pub problem: DataQualityProblem,
+    pub field: Option<String>, // Affected field for missing_field and wrong_type
+    pub document_count: u32,
+    pub sample_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataQualityReport {
+    pub period_start: String, //YYYY-MM-DD
+    pub period_end: String,   //YYYY-MM-DD
+    pub checked_at: DateTime<Utc>,
+    pub documents_scanned: u32,
+    pub issues: Vec<DataQualityIssue>, // Only problems found at least once
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub prefix: String, // Leading characters of the key, to recognise it without the secret
+    #[serde(skip_serializing, default)]
+    pub key_hash: String, // SHA-256 of the full key; the key itself is never stored
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub stations: Vec<String>, // Vending stations a station_manager or cashier key is limited to
+    #[serde(default)]
+    pub communities: Vec<String>, // Communities a station_manager or cashier key is limited to
+    pub created_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A newly created or rotated key; the only time the secret is returned
+#[derive(Debug, Serialize)]
+pub struct IssuedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+/// Vending stations and communities a caller may see. `None` leaves that dimension unrestricted;
+/// an empty list allows nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AccessScope {
+    pub vending_stations: Option<Vec<String>>,
+    pub communities: Option<Vec<String>>,
+}
+
+impl AccessScope {
+    pub fn is_unrestricted(&self) -> bool {
+        self.vending_stations.is_none() && self.communities.is_none()
+    }
+
+    /// Whether a record with this station and community is visible; records missing a restricted
+    /// field are not
+    pub fn allows(&self, vending_station: Option<&str>, community: Option<&str>) -> bool {
+        let within = |allowed: &Option<Vec<String>>, value: Option<&str>| match allowed {
+            None => true,
+            Some(allowed) => value.is_some_and(|value| allowed.iter().any(|a| a == value)),
+        };
+        within(&self.vending_stations, vending_station) && within(&self.communities, community)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(stations: Option<&[&str]>, communities: Option<&[&str]>) -> AccessScope {
+        let list = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        AccessScope {
+            vending_stations: stations.map(list),
+            communities: communities.map(list),
+        }
+    }
+
+    #[test]
+    fn unrestricted_scope_allows_everything() {
+        let scope = AccessScope::default();
+        assert!(scope.is_unrestricted());
+        assert!(scope.allows(Some("Central"), Some("North")));
+        assert!(scope.allows(None, None));
+    }
+
+    #[test]
+    fn station_scope_allows_listed_stations_in_any_community() {
+        let scope = scope(Some(&["Central"]), None);
+        assert!(!scope.is_unrestricted());
+        assert!(scope.allows(Some("Central"), Some("North")));
+        assert!(scope.allows(Some("Central"), None));
+        assert!(!scope.allows(Some("Harbour"), Some("North")));
+        assert!(!scope.allows(None, Some("North")));
+    }
+
+    #[test]
+    fn community_scope_allows_listed_communities_at_any_station() {
+        let scope = scope(None, Some(&["North", "South"]));
+        assert!(scope.allows(Some("Harbour"), Some("South")));
+        assert!(!scope.allows(Some("Harbour"), Some("East")));
+        assert!(!scope.allows(Some("Harbour"), None));
+    }
+
+    #[test]
+    fn both_lists_must_match() {
+        let scope = scope(Some(&["Central"]), Some(&["North"]));
+        assert!(scope.allows(Some("Central"), Some("North")));
+        assert!(!scope.allows(Some("Central"), Some("South")));
+        assert!(!scope.allows(Some("Harbour"), Some("North")));
+    }
+
+    #[test]
+    fn empty_list_allows_nothing() {
+        let scope = scope(Some(&[]), None);
+        assert!(!scope.is_unrestricted());
+        assert!(!scope.allows(Some("Central"), Some("North")));
+    }
+
+    #[test]
+    fn matching_is_exact() {
+        let scope = scope(Some(&["Central"]), None);
+        assert!(!scope.allows(Some("central"), None));
+        assert!(!scope.allows(Some("Central "), None));
+    }
+}
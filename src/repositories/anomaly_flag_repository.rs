@@ -0,0 +1,27 @@
+use crate::error::AppError;
+use crate::model::{AccessScope, AnomalyFlag, AnomalyKind, AnomalySeverity};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+#[async_trait]
+pub trait AnomalyFlagRepository: Send + Sync {
+    //Store the flags of a rescan, replacing every earlier flag on vends within the date range and
+    //scope. All flags carry detected_at; flags detected before it are removed.
+    async fn replace_flags(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        scope: &AccessScope,
+        detected_at: DateTime<Utc>,
+        flags: &[AnomalyFlag],
+    ) -> Result<u64, AppError>;
+    //Get flags on vends within the date range and scope, optionally filtered by minimum severity and kind
+    async fn get_flags(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        scope: &AccessScope,
+        min_severity: Option<AnomalySeverity>,
+        kind: Option<AnomalyKind>,
+    ) -> Result<Vec<AnomalyFlag>, AppError>;
+}
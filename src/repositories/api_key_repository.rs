@@ -0,0 +1,28 @@
+use crate::error::AppError;
+use crate::model::ApiKey;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    //Store a new key
+    async fn create_api_key(&self, api_key: ApiKey) -> Result<ApiKey, AppError>;
+    //Get every key, revoked ones included, newest first
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError>;
+    //Find the unrevoked key with this hash, used to authenticate requests
+    async fn find_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError>;
+    //Replace the hash and prefix of an unrevoked key; None when there is no such key
+    async fn rotate_api_key(
+        &self,
+        id: &str,
+        key_hash: &str,
+        prefix: &str,
+        rotated_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, AppError>;
+    //Mark an unrevoked key as revoked; None when there is no such key
+    async fn revoke_api_key(
+        &self,
+        id: &str,
+        revoked_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, AppError>;
+}
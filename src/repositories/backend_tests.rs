@@ -0,0 +1,625 @@
+// Every VendingRecordRepository method run over one fixture on each backend, checking that the
+// results match the in-memory backend. SQLite always runs; the PostgreSQL and MongoDB tests are
+// ignored unless run with --ignored, with TEST_POSTGRES_URL pointing at a scratch database (its
+// vending_records table is emptied) and TEST_MONGODB_URI at a server where throwaway collections
+// are created and dropped.
+
+use crate::model::{
+    AccessScope, DataQualityReport, LeaderboardDimension, LeaderboardMetric, SummarySeriesOptions,
+    VendingRecord,
+};
+use crate::repositories::{
+    DailyRollupRepository, InMemoryVendingRecordRepository, MongoDbDailyRollupRepository,
+    MongoDbVendingRecordRepository, SqlVendingRecordRepository, VendingRecordRepository,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{Document, doc};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+fn at(timestamp: &str) -> DateTime<Utc> {
+    timestamp.parse().unwrap()
+}
+
+// id, timestamp, meter, community, station, operator, amount, transaction ID
+type FixtureRow = (
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+    &'static str,
+    &'static str,
+    &'static str,
+    f64,
+    &'static str,
+);
+
+#[rustfmt::skip]
+const FIXTURE: &[FixtureRow] = &[
+    // M-1 last bought before the previous KPI periods, so it is a returning customer
+    ("r01", "2024-06-01T08:00:00Z", Some("M-1"), "North", "Central", "u1", 12.0, "1"),
+    ("r02", "2024-12-05T09:00:00Z", Some("M-7"), "North", "Central", "u1", 15.0, "2"),
+    ("r03", "2024-12-20T10:15:00Z", Some("M-2"), "North", "Central", "u2", 8.0, "3"),
+    ("r04", "2025-01-02T07:30:00Z", Some("M-1"), "North", "Central", "u1", 20.0, "4"),
+    ("r05", "2025-01-03T11:45:00Z", Some("M-3"), "South", "Harbour", "u3", 30.0, "1"),
+    ("r06", "2025-01-04T13:00:00Z", Some("M-2"), "North", "Central", "u2", 9.5, "5"),
+    ("r07", "2025-01-05T18:20:00Z", Some("M-3"), "South", "Harbour", "u3", 45.0, "2"),
+    ("r08", "2025-01-06T06:10:00Z", Some("M-4"), "South", "Harbour", "u3", 5.0, "4"),
+    ("r09", "2025-01-07T16:40:00Z", Some("M-1"), "North", "Central", "u1", 22.0, "6"),
+    // Same meter and amount twice within a minute, and a reused transaction ID
+    ("r10", "2025-01-08T12:00:00Z", Some("M-5"), "East", "Central", "u2", 10.0, "7"),
+    ("r11", "2025-01-08T12:00:30Z", Some("M-5"), "East", "Central", "u2", 10.0, "7"),
+    ("r12", "2025-01-09T09:05:00Z", None, "East", "Harbour", "u3", 3.0, "3"),
+    ("r13", "2025-01-10T20:00:00Z", Some("M-3"), "South", "Harbour", "u3", 40.0, "5"),
+    // A refund, which voids the vend in the cash-up
+    ("r14", "2025-01-11T10:00:00Z", Some("M-2"), "North", "Central", "u2", -9.5, "8"),
+    ("r15", "2025-01-12T14:30:00Z", Some("M-6"), "West", "Central", "u1", 60.0, "9"),
+    // M-3 moved community
+    ("r16", "2025-01-13T08:45:00Z", Some("M-3"), "North", "Harbour", "u3", 35.0, "6"),
+    ("r17", "2025-01-14T19:10:00Z", Some("M-1"), "North", "Central", "u1", 18.0, "10"),
+    ("r18", "2025-01-15T07:00:00Z", Some("M-4"), "South", "Harbour", "u3", 6.0, "7"),
+    ("r19", "2025-02-01T10:00:00Z", Some("M-6"), "West", "Central", "u1", 55.0, "11"),
+];
+
+fn fixture() -> Vec<VendingRecord> {
+    let mut records: Vec<VendingRecord> = FIXTURE
+        .iter()
+        .map(
+            |&(id, timestamp, meter, community, station, user, amount, transaction_id)| {
+                VendingRecord {
+                    id: id.to_string(),
+                    timestamp: at(timestamp),
+                    meter_number: meter.map(str::to_string),
+                    address: Some(format!("{} Road", community)),
+                    community: Some(community.to_string()),
+                    customer_name: meter.map(|meter| format!("Customer {}", meter)),
+                    token: Some(format!("TOKEN-{}", id)),
+                    tariff: Some(0.5),
+                    amount: Some(amount),
+                    kwh: Some(amount * 2.0),
+                    user_id: Some(user.to_string()),
+                    vending_station: Some(station.to_string()),
+                    fixed_charge: Some(1.0),
+                    transaction_id: Some(transaction_id.to_string()),
+                    remaining_credit: Some(amount / 4.0),
+                }
+            },
+        )
+        .collect();
+    // A duplicated token and a record with neither amount nor kWh
+    records[18].token = records[14].token.clone();
+    records[7].amount = None;
+    records[7].kwh = None;
+    records
+}
+
+// A scratch SQLite database file, removed when the test ends
+struct TempDatabase(std::path::PathBuf);
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+async fn sqlite_backend(records: &[VendingRecord]) -> (SqlVendingRecordRepository, TempDatabase) {
+    let path = std::env::temp_dir().join(format!("jep-backend-{}.db", uuid::Uuid::new_v4()));
+    let repository =
+        SqlVendingRecordRepository::connect_sqlite(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+    repository.insert_records(records).await.unwrap();
+    (repository, TempDatabase(path))
+}
+
+async fn postgres_backend(url: &str, records: &[VendingRecord]) -> SqlVendingRecordRepository {
+    let repository = SqlVendingRecordRepository::connect_postgres(url)
+        .await
+        .unwrap();
+    let pool = sqlx::PgPool::connect(url).await.unwrap();
+    sqlx::query("DELETE FROM vending_records")
+        .execute(&pool)
+        .await
+        .unwrap();
+    repository.insert_records(records).await.unwrap();
+    repository
+}
+
+fn mongo_document(record: &VendingRecord) -> Document {
+    doc! {
+        "_id": &record.id,
+        "timestamp": mongodb::bson::DateTime::from_millis(record.timestamp.timestamp_millis()),
+        "meterNumber": &record.meter_number,
+        "address": &record.address,
+        "community": &record.community,
+        "customerName": &record.customer_name,
+        "token": &record.token,
+        "tariff": record.tariff,
+        "amount": record.amount,
+        "kwh": record.kwh,
+        "userId": &record.user_id,
+        "vendingStation": &record.vending_station,
+        "fixedCharge": record.fixed_charge,
+        "transactionId": &record.transaction_id,
+        "remainingCredit": record.remaining_credit,
+    }
+}
+
+async fn mongo_backend(
+    uri: &str,
+    records: &[VendingRecord],
+) -> (
+    MongoDbVendingRecordRepository,
+    mongodb::Collection<Document>,
+) {
+    let client = mongodb::Client::with_uri_str(uri).await.unwrap();
+    let collection = client
+        .database("jep_backend_tests")
+        .collection::<Document>(&format!(
+            "vending_records_{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+    collection
+        .insert_many(records.iter().map(mongo_document))
+        .await
+        .unwrap();
+    let repository = MongoDbVendingRecordRepository::from_collection(
+        collection.clone_with_type::<VendingRecord>(),
+    );
+    (repository, collection)
+}
+
+// Numbers may differ in the last bits with summation order; inactive meters are measured from
+// the current time, so allow a relative error well above either
+fn assert_same(path: &str, expected: &Value, actual: &Value) {
+    match (expected, actual) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+            assert!(
+                (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0),
+                "{}: expected {}, got {}",
+                path,
+                a,
+                b
+            );
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            assert_eq!(a.len(), b.len(), "{}: array length differs", path);
+            for (index, (a, b)) in a.iter().zip(b).enumerate() {
+                assert_same(&format!("{}[{}]", path, index), a, b);
+            }
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            let keys = |map: &serde_json::Map<String, Value>| {
+                let mut keys: Vec<String> = map.keys().cloned().collect();
+                keys.sort();
+                keys
+            };
+            assert_eq!(keys(a), keys(b), "{}: fields differ", path);
+            for (key, value) in a {
+                assert_same(&format!("{}.{}", path, key), value, &b[key]);
+            }
+        }
+        _ => assert_eq!(expected, actual, "{}", path),
+    }
+}
+
+fn json<T: Serialize>(value: T) -> Value {
+    serde_json::to_value(value).unwrap()
+}
+
+// Each method's results, labelled by the call that produced them
+async fn run_every_method(repository: &dyn VendingRecordRepository) -> Vec<(String, Value)> {
+    let start = at("2025-01-01T00:00:00Z");
+    let end = at("2025-01-31T23:59:59Z");
+    let mut results = Vec::new();
+    let mut push = |label: &str, value: Value| results.push((label.to_string(), value));
+
+    push(
+        "get_vending_records",
+        json(
+            repository
+                .get_vending_records(start, end)
+                .await
+                .unwrap()
+                .records,
+        ),
+    );
+    for (cumulative, moving_averages) in [(false, false), (true, true)] {
+        let series = SummarySeriesOptions {
+            cumulative,
+            moving_averages,
+        };
+        push(
+            &format!("get_vending_summary({:?})", series),
+            json(
+                repository
+                    .get_vending_summary(start, end, series)
+                    .await
+                    .unwrap(),
+            ),
+        );
+    }
+    push(
+        "get_vending_statistics",
+        json(
+            repository
+                .get_vending_statistics(start, end, 4)
+                .await
+                .unwrap(),
+        ),
+    );
+    for dimension in [
+        LeaderboardDimension::Meter,
+        LeaderboardDimension::Customer,
+        LeaderboardDimension::Community,
+        LeaderboardDimension::Station,
+    ] {
+        for metric in [
+            LeaderboardMetric::Amount,
+            LeaderboardMetric::Kwh,
+            LeaderboardMetric::Transactions,
+        ] {
+            push(
+                &format!("get_leaderboard({:?}, {:?})", dimension, metric),
+                json(
+                    repository
+                        .get_leaderboard(start, end, dimension, metric, 3)
+                        .await
+                        .unwrap(),
+                ),
+            );
+        }
+    }
+    for (inactive_since, min_purchases) in
+        [("2025-01-10T00:00:00Z", 1), ("2025-01-16T00:00:00Z", 3)]
+    {
+        push(
+            &format!("get_inactive_meters({}, {})", inactive_since, min_purchases),
+            json(
+                repository
+                    .get_inactive_meters(
+                        at("2024-12-01T00:00:00Z"),
+                        at(inactive_since),
+                        min_purchases,
+                    )
+                    .await
+                    .unwrap(),
+            ),
+        );
+    }
+    for meter in [None, Some("M-3")] {
+        push(
+            &format!("get_meter_behaviour({:?})", meter),
+            json(
+                repository
+                    .get_meter_behaviour(end, 14, meter)
+                    .await
+                    .unwrap(),
+            ),
+        );
+    }
+    push(
+        "get_credit_runout",
+        json(
+            repository
+                .get_credit_runout(at("2024-12-01T00:00:00Z"), end)
+                .await
+                .unwrap(),
+        ),
+    );
+    for user in [None, Some("u3")] {
+        push(
+            &format!("get_operator_shifts({:?})", user),
+            json(
+                repository
+                    .get_operator_shifts(start, end, "Africa/Lagos", user)
+                    .await
+                    .unwrap(),
+            ),
+        );
+    }
+    push(
+        "get_meter_amount_baselines",
+        json(
+            repository
+                .get_meter_amount_baselines(start, end)
+                .await
+                .unwrap(),
+        ),
+    );
+    push(
+        "get_integrity_report",
+        json(
+            repository
+                .get_integrity_report(start, end, 60)
+                .await
+                .unwrap(),
+        ),
+    );
+    push(
+        "get_transaction_sequence",
+        json(
+            repository
+                .get_transaction_sequence(start, end)
+                .await
+                .unwrap(),
+        ),
+    );
+    for (station, community) in [(None, None), (Some("Harbour"), None), (None, Some("North"))] {
+        push(
+            &format!("get_heatmap({:?}, {:?})", station, community),
+            json(
+                repository
+                    .get_heatmap(start, end, "Africa/Lagos", station, community)
+                    .await
+                    .unwrap(),
+            ),
+        );
+    }
+    for (kpi_start, kpi_end) in [
+        (start, end),
+        (at("2025-01-08T00:00:00Z"), at("2025-01-15T23:59:59Z")),
+    ] {
+        push(
+            &format!("get_kpis({}, {})", kpi_start, kpi_end),
+            json(repository.get_kpis(kpi_start, kpi_end).await.unwrap()),
+        );
+    }
+    push(
+        "get_data_quality_report",
+        json(
+            repository
+                .get_data_quality_report(start, end, at("2025-01-20T00:00:00Z"), 5)
+                .await
+                .unwrap(),
+        ),
+    );
+
+    results
+}
+
+fn scopes() -> Vec<AccessScope> {
+    vec![
+        AccessScope::default(),
+        AccessScope {
+            vending_stations: Some(vec!["Central".to_string()]),
+            communities: None,
+        },
+        AccessScope {
+            vending_stations: None,
+            communities: Some(vec!["South".to_string(), "East".to_string()]),
+        },
+    ]
+}
+
+async fn assert_backend_matches(name: &str, backend: Arc<dyn VendingRecordRepository>) {
+    let reference = InMemoryVendingRecordRepository::new(fixture());
+    for scope in scopes() {
+        let expected = run_every_method(reference.with_scope(scope.clone()).as_ref()).await;
+        let actual = run_every_method(backend.with_scope(scope.clone()).as_ref()).await;
+        for ((label, expected), (_, actual)) in expected.iter().zip(&actual) {
+            assert_same(&format!("{} {} {:?}", name, label, scope), expected, actual);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn sqlite_backend_matches_in_memory() {
+    let (repository, _database) = sqlite_backend(&fixture()).await;
+    assert_backend_matches("sqlite", Arc::new(repository)).await;
+}
+
+#[actix_web::test]
+#[ignore = "needs PostgreSQL: set TEST_POSTGRES_URL and run with --ignored"]
+async fn postgres_backend_matches_in_memory() {
+    let url = std::env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL is not set");
+    let repository = postgres_backend(&url, &fixture()).await;
+    assert_backend_matches("postgres", Arc::new(repository)).await;
+}
+
+#[actix_web::test]
+#[ignore = "needs MongoDB: set TEST_MONGODB_URI and run with --ignored"]
+async fn mongodb_backend_matches_in_memory() {
+    let uri = std::env::var("TEST_MONGODB_URI").expect("TEST_MONGODB_URI is not set");
+    let (repository, collection) = mongo_backend(&uri, &fixture()).await;
+    assert_backend_matches("mongodb", Arc::new(repository)).await;
+    collection.drop().await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "needs MongoDB: set TEST_MONGODB_URI and run with --ignored"]
+async fn mongodb_rollup_summary_matches_raw_summary() {
+    let uri = std::env::var("TEST_MONGODB_URI").expect("TEST_MONGODB_URI is not set");
+    let (raw, records) = mongo_backend(&uri, &fixture()).await;
+    let database = records.client().database("jep_backend_tests");
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let rollups = database.collection::<Document>(&format!("daily_rollups_{}", suffix));
+    let state = database.collection::<Document>(&format!("rollup_state_{}", suffix));
+    let daily_rollups = MongoDbDailyRollupRepository::from_collections(
+        records.clone(),
+        rollups.clone(),
+        state.clone(),
+    );
+    let refresh = daily_rollups
+        .refresh_incremental(Utc::now().date_naive(), 7)
+        .await
+        .unwrap();
+    assert!(refresh.rollup_documents > 0);
+    let rolled_up: Arc<dyn VendingRecordRepository> =
+        Arc::new(raw.clone().with_daily_rollups(daily_rollups));
+    let raw: Arc<dyn VendingRecordRepository> = Arc::new(raw);
+
+    // Whole days only, and partial days at either end that must be read from the raw records
+    let ranges = [
+        (at("2025-01-01T00:00:00Z"), at("2025-01-31T23:59:59.999Z")),
+        (at("2025-01-03T12:00:00Z"), at("2025-01-14T06:00:00Z")),
+        (at("2024-12-20T10:15:00Z"), at("2025-02-01T00:00:00Z")),
+    ];
+    for scope in scopes() {
+        let raw = raw.with_scope(scope.clone());
+        let rolled_up = rolled_up.with_scope(scope.clone());
+        for (start, end) in ranges {
+            for (cumulative, moving_averages) in [(false, false), (true, true)] {
+                let series = SummarySeriesOptions {
+                    cumulative,
+                    moving_averages,
+                };
+                let expected = raw.get_vending_summary(start, end, series).await.unwrap();
+                let actual = rolled_up
+                    .get_vending_summary(start, end, series)
+                    .await
+                    .unwrap();
+                assert_same(
+                    &format!("summary {}..{} {:?} {:?}", start, end, series, scope),
+                    &json(expected),
+                    &json(actual),
+                );
+            }
+        }
+    }
+
+    records.drop().await.unwrap();
+    rollups.drop().await.unwrap();
+    state.drop().await.unwrap();
+}
+
+// Problem, field, document count and sample IDs of each issue in a data quality report
+fn quality_issues(report: &DataQualityReport) -> Vec<(String, Option<String>, u32, Vec<String>)> {
+    let mut issues: Vec<_> = report
+        .issues
+        .iter()
+        .map(|issue| {
+            (
+                format!("{:?}", issue.problem),
+                issue.field.clone(),
+                issue.document_count,
+                issue.sample_ids.clone(),
+            )
+        })
+        .collect();
+    issues.sort();
+    issues
+}
+
+#[actix_web::test]
+#[ignore = "needs MongoDB: set TEST_MONGODB_URI and run with --ignored"]
+async fn mongodb_data_quality_report_finds_legacy_documents() {
+    let uri = std::env::var("TEST_MONGODB_URI").expect("TEST_MONGODB_URI is not set");
+    let records = fixture();
+    let document = |index: usize, id: &str| {
+        let mut document = mongo_document(&records[index]);
+        document.insert("_id", id);
+        document
+    };
+    let mut documents = vec![document(3, "q1")];
+    // Central and Harbour records, each broken in one way
+    let mut string_amount = document(4, "q2");
+    string_amount.insert("amount", "30.0");
+    documents.push(string_amount);
+    let mut string_timestamp = document(5, "q3");
+    string_timestamp.insert("timestamp", "2025-01-04T13:00:00Z");
+    documents.push(string_timestamp);
+    let mut no_timestamp = document(6, "q4");
+    no_timestamp.remove("timestamp");
+    documents.push(no_timestamp);
+    let mut no_meter = document(8, "q5");
+    no_meter.remove("meterNumber");
+    documents.push(no_meter);
+    let mut future = document(12, "q6");
+    future.insert(
+        "timestamp",
+        mongodb::bson::DateTime::from_millis(at("2025-03-01T00:00:00Z").timestamp_millis()),
+    );
+    documents.push(future);
+    let mut string_kwh = document(14, "q7");
+    string_kwh.insert("kwh", "120");
+    documents.push(string_kwh);
+    let mut numeric_transaction_id = document(16, "q8");
+    numeric_transaction_id.insert("transactionId", 10_i32);
+    documents.push(numeric_transaction_id);
+
+    let collection = mongodb::Client::with_uri_str(&uri)
+        .await
+        .unwrap()
+        .database("jep_backend_tests")
+        .collection::<Document>(&format!(
+            "vending_records_{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+    collection.insert_many(documents).await.unwrap();
+    let repository: Arc<dyn VendingRecordRepository> =
+        Arc::new(MongoDbVendingRecordRepository::from_collection(
+            collection.clone_with_type::<VendingRecord>(),
+        ));
+    let start = at("2025-01-01T00:00:00Z");
+    let end = at("2025-01-31T23:59:59Z");
+    let now = at("2025-01-20T00:00:00Z");
+    let issue = |problem: &str, field: Option<&str>, id: &str| {
+        (
+            problem.to_string(),
+            field.map(str::to_string),
+            1,
+            vec![id.to_string()],
+        )
+    };
+
+    let report = repository
+        .get_data_quality_report(start, end, now, 5)
+        .await
+        .unwrap();
+    // Documents without a date timestamp are reported but not counted as in the range
+    assert_eq!(report.documents_scanned, 5);
+    let mut expected = vec![
+        issue("MissingField", Some("timestamp"), "q4"),
+        issue("WrongType", Some("timestamp"), "q3"),
+        issue("FutureTimestamp", None, "q6"),
+        issue("MissingField", Some("meterNumber"), "q5"),
+        issue("WrongType", Some("amount"), "q2"),
+        issue("WrongType", Some("kwh"), "q7"),
+        issue("WrongType", Some("transactionId"), "q8"),
+    ];
+    expected.sort();
+    assert_eq!(quality_issues(&report), expected);
+
+    let harbour = repository.with_scope(AccessScope {
+        vending_stations: Some(vec!["Harbour".to_string()]),
+        communities: None,
+    });
+    let report = harbour
+        .get_data_quality_report(start, end, now, 5)
+        .await
+        .unwrap();
+    assert_eq!(report.documents_scanned, 1);
+    let mut expected = vec![
+        issue("MissingField", Some("timestamp"), "q4"),
+        issue("FutureTimestamp", None, "q6"),
+        issue("WrongType", Some("amount"), "q2"),
+    ];
+    expected.sort();
+    assert_eq!(quality_issues(&report), expected);
+
+    collection.drop().await.unwrap();
+}
+
+#[actix_web::test]
+async fn in_memory_kpis_count_meters_seen_earlier_as_returning() {
+    let records = fixture();
+    let start = at("2025-01-08T00:00:00Z");
+    let end = at("2025-01-15T23:59:59Z");
+    // Only the window from the previous period on, as the SQL backend loads it
+    let window: Vec<VendingRecord> = records
+        .iter()
+        .filter(|record| record.timestamp >= start - (end - start))
+        .cloned()
+        .collect();
+    let returning = ["M-1".to_string(), "M-2".to_string()].into_iter().collect();
+
+    let bounded = InMemoryVendingRecordRepository::new(window).kpi_report(start, end, &returning);
+    let full = InMemoryVendingRecordRepository::new(records)
+        .get_kpis(start, end)
+        .await
+        .unwrap();
+
+    assert_same("kpis", &json(full), &json(bounded));
+}
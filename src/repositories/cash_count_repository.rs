@@ -0,0 +1,17 @@
+use crate::error::AppError;
+use crate::model::CashCount;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait CashCountRepository: Send + Sync {
+    //Record the counted cash for an operator's day at a station, replacing any earlier count
+    async fn save_cash_count(&self, cash_count: CashCount) -> Result<CashCount, AppError>;
+    //Get cash counts for days between start_date and end_date (YYYY-MM-DD, inclusive),
+    //only at the given stations when vending_stations is set
+    async fn get_cash_counts(
+        &self,
+        start_date: &str,
+        end_date: &str,
+        vending_stations: Option<&[String]>,
+    ) -> Result<Vec<CashCount>, AppError>;
+}
@@ -0,0 +1,25 @@
+use crate::error::AppError;
+use crate::model::{RollupRefresh, RollupStatus};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+
+#[async_trait]
+pub trait DailyRollupRepository: Send + Sync {
+    //Get the last complete day covered by the rollups
+    async fn get_rollup_status(&self) -> Result<RollupStatus, AppError>;
+    //Recompute rollups for every day after the last refresh and for earlier days whose records
+    //changed since then: records updated or inserted after the last refresh, or days within
+    //lookback_days before today whose raw totals no longer match their rollups
+    async fn refresh_incremental(
+        &self,
+        today: NaiveDate,
+        lookback_days: u32,
+    ) -> Result<RollupRefresh, AppError>;
+    //Recompute rollups for the given days (inclusive), e.g. after records were corrected
+    async fn refresh_range(
+        &self,
+        start_day: NaiveDate,
+        end_day: NaiveDate,
+        today: NaiveDate,
+    ) -> Result<RollupRefresh, AppError>;
+}
@@ -0,0 +1,1119 @@
+use crate::analytics::TARIFF_TOLERANCE;
+use crate::error::AppError;
+use crate::model::{
+    AccessScope, CreditRunout, DailySummary, DataQualityIssue, DataQualityProblem,
+    DataQualityReport, DecodeReport, DistributionStats, DuplicateGroup, DuplicateRecord,
+    GroupStatistics, HistogramBucket, InactiveMeter, IntegrityReport, KpiReport, KpiSet,
+    Leaderboard, LeaderboardDimension, LeaderboardEntry, LeaderboardMetric, MeterAmountBaseline,
+    MeterBehaviour, MetricDistribution, OperatorShift, SummarySeriesOptions,
+    TransactionSequenceEntry, VendingHeatmap, VendingRecord, VendingRecordBatch,
+    VendingStationSummary, VendingStatistics, VendingSummary,
+};
+use crate::repositories::VendingRecordRepository;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Vending records held in memory, for handler tests and local demos without MongoDB.
+/// Filtering and aggregation follow the MongoDB pipelines, minus the daily rollups.
+pub struct InMemoryVendingRecordRepository {
+    records: Arc<Vec<VendingRecord>>,
+    scope: AccessScope,
+}
+
+impl InMemoryVendingRecordRepository {
+    pub fn new(records: Vec<VendingRecord>) -> Self {
+        Self {
+            records: Arc::new(records),
+            scope: AccessScope::default(),
+        }
+    }
+
+    fn in_scope(&self, record: &VendingRecord) -> bool {
+        self.scope.allows(
+            record.vending_station.as_deref(),
+            record.community.as_deref(),
+        )
+    }
+
+    /// Load records from a JSON array in the same shape the API returns them
+    pub fn from_json_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        let records: Vec<VendingRecord> = serde_json::from_str(&contents)?;
+        Ok(Self::new(records))
+    }
+
+    // Records with start_date <= timestamp <= end_date, oldest first
+    fn records_between(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Vec<&VendingRecord> {
+        let mut records: Vec<&VendingRecord> = self
+            .records
+            .iter()
+            .filter(|record| record.timestamp >= start_date && record.timestamp <= end_date)
+            .filter(|record| self.in_scope(record))
+            .collect();
+        records.sort_by_key(|record| record.timestamp);
+        records
+    }
+
+    /// KPI figures for the period, where `returning_meters` lists meters known to have vended
+    /// before the records held here begin
+    pub fn kpi_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        returning_meters: &BTreeSet<String>,
+    ) -> KpiReport {
+        let previous_start = start_date - (end_date - start_date);
+        let period_months =
+            ((end_date - start_date).num_seconds() as f64 / 86_400.0 / DAYS_PER_MONTH)
+                .max(1.0 / DAYS_PER_MONTH);
+
+        // The history up to end_date tells new customers from returning ones, along with
+        // `returning_meters` when older records were left out
+        let mut meters: BTreeMap<(Option<&str>, &str), MeterKpiTotals> = BTreeMap::new();
+        for record in self.records_between(DateTime::<Utc>::MIN_UTC, end_date) {
+            let community = record.community.as_deref().unwrap_or("Unknown");
+            let totals = meters
+                .entry((record.meter_number.as_deref(), community))
+                .or_default();
+            totals.first_purchase.get_or_insert(record.timestamp);
+            if record.timestamp >= start_date {
+                totals.current_vends += 1;
+                totals.current_amount += record.amount.unwrap_or(0.0);
+                totals.current_kwh += record.kwh.unwrap_or(0.0);
+            } else if record.timestamp >= previous_start {
+                totals.previous_amount += record.amount.unwrap_or(0.0);
+            }
+        }
+
+        // A meter is new if its first vend in any community falls in the period
+        let mut meter_first_purchase: BTreeMap<Option<&str>, DateTime<Utc>> = BTreeMap::new();
+        for ((meter_number, _), totals) in &meters {
+            if let Some(first_purchase) = totals.first_purchase {
+                let first = meter_first_purchase
+                    .entry(*meter_number)
+                    .or_insert(first_purchase);
+                *first = (*first).min(first_purchase);
+            }
+        }
+
+        let mut communities: BTreeMap<&str, KpiTotals> = BTreeMap::new();
+        // Meters that moved community are one customer overall
+        let mut overall_meters: BTreeMap<Option<&str>, (KpiTotals, bool, bool)> = BTreeMap::new();
+        for ((meter_number, community), totals) in &meters {
+            let is_active =
+                meter_number.is_some_and(|meter| !meter.is_empty()) && totals.current_vends > 0;
+            let is_new = is_active
+                && meter_first_purchase
+                    .get(meter_number)
+                    .is_some_and(|first| *first >= start_date)
+                && !meter_number.is_some_and(|meter| returning_meters.contains(meter));
+
+            let community_totals = communities.entry(community).or_default();
+            community_totals.add(totals);
+            community_totals.active_customers += is_active as u32;
+            community_totals.new_customers += is_new as u32;
+
+            let (meter_totals, active, new) = overall_meters.entry(*meter_number).or_default();
+            meter_totals.add(totals);
+            *active |= is_active;
+            *new |= is_new;
+        }
+
+        let mut overall = KpiTotals::default();
+        for (meter_totals, is_active, is_new) in overall_meters.into_values() {
+            overall.revenue += meter_totals.revenue;
+            overall.total_kwh += meter_totals.total_kwh;
+            overall.previous_revenue += meter_totals.previous_revenue;
+            overall.active_customers += is_active as u32;
+            overall.new_customers += is_new as u32;
+        }
+
+        KpiReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            previous_period_start: previous_start.format("%Y-%m-%d").to_string(),
+            period_months,
+            overall: overall.into_kpi_set(None, period_months),
+            communities: communities
+                .into_iter()
+                .filter(|(_, totals)| {
+                    totals.revenue != 0.0
+                        || totals.previous_revenue != 0.0
+                        || totals.active_customers > 0
+                })
+                .map(|(community, totals)| {
+                    totals.into_kpi_set(Some(community.to_string()), period_months)
+                })
+                .collect(),
+        }
+    }
+}
+
+// Same rule as the MongoDB queries: only non-empty strings identify a meter, operator or station
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|value| !value.is_empty())
+}
+
+fn parse_timezone(timezone: &str) -> Result<chrono_tz::Tz, AppError> {
+    timezone
+        .parse::<chrono_tz::Tz>()
+        .map_err(|_| AppError::Validation(format!("Invalid timezone: '{}'", timezone)))
+}
+
+// Records grouped by meter number, each group oldest first
+fn group_by_meter<'a>(records: &[&'a VendingRecord]) -> BTreeMap<&'a str, Vec<&'a VendingRecord>> {
+    let mut meters: BTreeMap<&str, Vec<&VendingRecord>> = BTreeMap::new();
+    for record in records {
+        if let Some(meter_number) = non_empty(&record.meter_number) {
+            meters.entry(meter_number).or_default().push(record);
+        }
+    }
+    meters
+}
+
+fn days_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 86_400_000.0
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn population_std_dev(values: &[f64]) -> Option<f64> {
+    let mean = mean(values)?;
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / values.len() as f64;
+    Some(variance.sqrt())
+}
+
+// Nearest-rank percentile of values sorted in ascending order
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn distribution(values: &[f64]) -> DistributionStats {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.is_empty() {
+        return DistributionStats::default();
+    }
+
+    DistributionStats {
+        count: sorted.len() as u32,
+        min: sorted[0],
+        max: sorted[sorted.len() - 1],
+        mean: mean(&sorted).unwrap_or(0.0),
+        median: percentile(&sorted, 0.5),
+        p90: percentile(&sorted, 0.9),
+        p99: percentile(&sorted, 0.99),
+    }
+}
+
+// Buckets of roughly equal size like $bucketAuto: equal values never straddle two buckets,
+// and each bucket ends where the next one starts
+fn auto_histogram(values: &[f64], buckets: u32) -> Vec<HistogramBucket> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.is_empty() || buckets == 0 {
+        return Vec::new();
+    }
+
+    let bucket_size = sorted.len().div_ceil(buckets as usize);
+    let mut histogram = Vec::new();
+    let mut start = 0;
+    while start < sorted.len() {
+        let mut end = (start + bucket_size).min(sorted.len());
+        while end < sorted.len() && sorted[end] == sorted[end - 1] {
+            end += 1;
+        }
+        histogram.push(HistogramBucket {
+            lower_bound: sorted[start],
+            upper_bound: sorted.get(end).copied().unwrap_or(sorted[sorted.len() - 1]),
+            count: (end - start) as u32,
+        });
+        start = end;
+    }
+    histogram
+}
+
+fn group_statistics(
+    records: &[&VendingRecord],
+    key: fn(&VendingRecord) -> &Option<String>,
+) -> Vec<GroupStatistics> {
+    let mut groups: BTreeMap<&str, (Vec<f64>, Vec<f64>)> = BTreeMap::new();
+    for record in records {
+        let name = key(record).as_deref().unwrap_or("Unknown");
+        let (amounts, kwhs) = groups.entry(name).or_default();
+        amounts.extend(record.amount);
+        kwhs.extend(record.kwh);
+    }
+    groups
+        .into_iter()
+        .map(|(name, (amounts, kwhs))| GroupStatistics {
+            name: name.to_string(),
+            amount: distribution(&amounts),
+            kwh: distribution(&kwhs),
+        })
+        .collect()
+}
+
+fn leaderboard_key(record: &VendingRecord, dimension: LeaderboardDimension) -> &str {
+    let key = match dimension {
+        LeaderboardDimension::Meter => &record.meter_number,
+        LeaderboardDimension::Customer => &record.customer_name,
+        LeaderboardDimension::Community => &record.community,
+        LeaderboardDimension::Station => &record.vending_station,
+    };
+    key.as_deref().unwrap_or("Unknown")
+}
+
+fn leaderboard_metric(entry: &LeaderboardEntry, metric: LeaderboardMetric) -> f64 {
+    match metric {
+        LeaderboardMetric::Amount => entry.total_amount,
+        LeaderboardMetric::Kwh => entry.total_kwh,
+        LeaderboardMetric::Transactions => entry.total_transactions as f64,
+    }
+}
+
+// Running totals and trailing calendar-day averages over one station's days, as in the
+// MongoDB window stages: days without vends count as zero and windows are shortened at
+// the start of the period
+fn add_summary_series(
+    daily: &mut [(NaiveDate, DailySummary)],
+    period_start: NaiveDate,
+    series: SummarySeriesOptions,
+) {
+    let (mut cumulative_amount, mut cumulative_kwh) = (0.0, 0.0);
+    for index in 0..daily.len() {
+        let day = daily[index].0;
+        let elapsed_days = (day - period_start).num_days() + 1;
+        let window_average = |days: i64, value: fn(&DailySummary) -> f64| {
+            let window_start = day - chrono::Duration::days(days - 1);
+            let sum: f64 = daily[..=index]
+                .iter()
+                .filter(|(date, _)| *date >= window_start)
+                .map(|(_, summary)| value(summary))
+                .sum();
+            sum / days.min(elapsed_days) as f64
+        };
+        let averages = series.moving_averages.then(|| {
+            (
+                window_average(7, |summary| summary.total_amount),
+                window_average(30, |summary| summary.total_amount),
+                window_average(7, |summary| summary.total_kwh),
+                window_average(30, |summary| summary.total_kwh),
+            )
+        });
+
+        let summary = &mut daily[index].1;
+        if series.cumulative {
+            cumulative_amount += summary.total_amount;
+            cumulative_kwh += summary.total_kwh;
+            summary.cumulative_amount = Some(cumulative_amount);
+            summary.cumulative_kwh = Some(cumulative_kwh);
+        }
+        if let Some((amount_7d, amount_30d, kwh_7d, kwh_30d)) = averages {
+            summary.amount_avg_7d = Some(amount_7d);
+            summary.amount_avg_30d = Some(amount_30d);
+            summary.kwh_avg_7d = Some(kwh_7d);
+            summary.kwh_avg_30d = Some(kwh_30d);
+        }
+    }
+}
+
+// Purchase pattern of one meter, split into the recent window and the window before it
+fn meter_behaviour(
+    meter_number: &str,
+    history: &[&VendingRecord],
+    recent_start: DateTime<Utc>,
+) -> MeterBehaviour {
+    let latest = history[history.len() - 1];
+    let amounts: Vec<f64> = history.iter().filter_map(|record| record.amount).collect();
+    let intervals: Vec<f64> = history
+        .windows(2)
+        .map(|pair| days_between(pair[0].timestamp, pair[1].timestamp))
+        .collect();
+    let average_interval = mean(&intervals);
+    let interval_std_dev = population_std_dev(&intervals);
+
+    let (mut recent_transactions, mut recent_amount) = (0, 0.0);
+    let (mut prior_transactions, mut prior_amount) = (0, 0.0);
+    for record in history {
+        if record.timestamp >= recent_start {
+            recent_transactions += 1;
+            recent_amount += record.amount.unwrap_or(0.0);
+        } else {
+            prior_transactions += 1;
+            prior_amount += record.amount.unwrap_or(0.0);
+        }
+    }
+
+    // Intervals need at least two gaps before their spread means anything
+    let regularity_cv = match (average_interval, interval_std_dev) {
+        (Some(mean), Some(std_dev)) if mean > 0.0 && history.len() > 2 => Some(std_dev / mean),
+        _ => None,
+    };
+
+    MeterBehaviour {
+        meter_number: meter_number.to_string(),
+        customer_name: latest.customer_name.clone(),
+        community: latest.community.clone(),
+        vending_station: latest.vending_station.clone(),
+        purchase_count: history.len() as u32,
+        average_ticket: mean(&amounts).unwrap_or(0.0),
+        average_days_between_purchases: average_interval,
+        interval_std_dev_days: interval_std_dev,
+        regularity_cv,
+        recent_transactions,
+        recent_amount,
+        prior_transactions,
+        prior_amount,
+        amount_trend_percent: (prior_amount > 0.0)
+            .then(|| (recent_amount - prior_amount) / prior_amount * 100.0),
+    }
+}
+
+// Consumption rate of one meter extrapolated from its last vend. remaining_credit is the
+// balance left on the meter when the token was bought, so the credit after the last vend is
+// that balance plus the kWh just bought.
+fn credit_runout(
+    meter_number: &str,
+    history: &[&VendingRecord],
+    as_of: DateTime<Utc>,
+) -> Option<CreditRunout> {
+    let mut observed_days = 0.0;
+    let mut consumed_kwh = 0.0;
+    for pair in history.windows(2) {
+        let (vend, next) = (pair[0], pair[1]);
+        let kwh = vend.kwh.unwrap_or(0.0);
+        observed_days += days_between(vend.timestamp, next.timestamp);
+        consumed_kwh += match (vend.remaining_credit, next.remaining_credit) {
+            (Some(remaining), Some(next_remaining)) => (remaining + kwh - next_remaining).max(0.0),
+            _ => kwh,
+        };
+    }
+    if observed_days <= 0.0 || consumed_kwh <= 0.0 {
+        return None;
+    }
+
+    let latest = history[history.len() - 1];
+    let daily_consumption_kwh = consumed_kwh / observed_days;
+    let last_kwh = latest.kwh.unwrap_or(0.0);
+    let available_kwh = latest.remaining_credit.unwrap_or(0.0).max(0.0) + last_kwh;
+    let days_of_credit = available_kwh / daily_consumption_kwh;
+    let estimated_runout =
+        latest.timestamp + chrono::Duration::seconds((days_of_credit * 86_400.0) as i64);
+
+    Some(CreditRunout {
+        meter_number: meter_number.to_string(),
+        customer_name: latest.customer_name.clone(),
+        community: latest.community.clone(),
+        vending_station: latest.vending_station.clone(),
+        last_purchase: latest.timestamp,
+        last_kwh,
+        remaining_credit_at_purchase: latest.remaining_credit,
+        daily_consumption_kwh,
+        estimated_runout,
+        days_until_runout: (estimated_runout - as_of).num_seconds() as f64 / 86_400.0,
+    })
+}
+
+fn duplicate_group(key: String, records: &[&VendingRecord]) -> DuplicateGroup {
+    DuplicateGroup {
+        key,
+        count: records.len() as u32,
+        records: records
+            .iter()
+            .map(|record| DuplicateRecord {
+                id: record.id.clone(),
+                timestamp: record.timestamp,
+                meter_number: record.meter_number.clone(),
+                vending_station: record.vending_station.clone(),
+                user_id: record.user_id.clone(),
+                amount: record.amount,
+                token: record.token.clone(),
+                transaction_id: record.transaction_id.clone(),
+            })
+            .collect(),
+    }
+}
+
+// Largest groups first, then by key
+fn sort_duplicate_groups(groups: &mut [DuplicateGroup]) {
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+}
+
+// Records that share the value of a string field
+fn exact_duplicates(
+    records: &[&VendingRecord],
+    key: fn(&VendingRecord) -> &Option<String>,
+) -> Vec<DuplicateGroup> {
+    let mut groups: BTreeMap<&str, Vec<&VendingRecord>> = BTreeMap::new();
+    for record in records {
+        if let Some(value) = non_empty(key(record)) {
+            groups.entry(value).or_default().push(record);
+        }
+    }
+    let mut duplicates: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .map(|(value, group)| duplicate_group(value.to_string(), &group))
+        .collect();
+    sort_duplicate_groups(&mut duplicates);
+    duplicates
+}
+
+// Vends of the same amount on the same meter, split into clusters wherever the gap to the
+// previous vend exceeds the window
+fn near_duplicates(records: &[&VendingRecord], window_seconds: u32) -> Vec<DuplicateGroup> {
+    let mut partitions: BTreeMap<String, Vec<&VendingRecord>> = BTreeMap::new();
+    for record in records {
+        if let (Some(meter_number), Some(amount)) = (non_empty(&record.meter_number), record.amount)
+        {
+            partitions
+                .entry(format!("{} / {}", meter_number, amount))
+                .or_default()
+                .push(record);
+        }
+    }
+
+    let window = chrono::Duration::seconds(window_seconds as i64);
+    let mut duplicates = Vec::new();
+    for (key, partition) in partitions {
+        let mut cluster: Vec<&VendingRecord> = Vec::new();
+        for record in partition {
+            if let Some(previous) = cluster.last()
+                && record.timestamp - previous.timestamp > window
+            {
+                if cluster.len() > 1 {
+                    duplicates.push(duplicate_group(key.clone(), &cluster));
+                }
+                cluster.clear();
+            }
+            cluster.push(record);
+        }
+        if cluster.len() > 1 {
+            duplicates.push(duplicate_group(key, &cluster));
+        }
+    }
+    sort_duplicate_groups(&mut duplicates);
+    duplicates
+}
+
+// Average days per month, used to express kWh per customer as a monthly figure
+const DAYS_PER_MONTH: f64 = 365.25 / 12.0;
+
+// Per-meter sums feeding the KPI figures
+#[derive(Default)]
+struct MeterKpiTotals {
+    first_purchase: Option<DateTime<Utc>>,
+    current_vends: u32,
+    current_amount: f64,
+    current_kwh: f64,
+    previous_amount: f64,
+}
+
+#[derive(Default)]
+struct KpiTotals {
+    revenue: f64,
+    total_kwh: f64,
+    previous_revenue: f64,
+    active_customers: u32,
+    new_customers: u32,
+}
+
+impl KpiTotals {
+    fn add(&mut self, meter: &MeterKpiTotals) {
+        self.revenue += meter.current_amount;
+        self.total_kwh += meter.current_kwh;
+        self.previous_revenue += meter.previous_amount;
+    }
+
+    fn into_kpi_set(self, community: Option<String>, period_months: f64) -> KpiSet {
+        let (arpu, kwh_per_customer_month) = if self.active_customers > 0 {
+            let customers = self.active_customers as f64;
+            (
+                self.revenue / customers,
+                self.total_kwh / customers / period_months,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        KpiSet {
+            community,
+            revenue: self.revenue,
+            total_kwh: self.total_kwh,
+            active_customers: self.active_customers,
+            new_customers: self.new_customers,
+            arpu,
+            kwh_per_customer_month,
+            previous_revenue: self.previous_revenue,
+            revenue_growth_percent: (self.previous_revenue != 0.0)
+                .then(|| (self.revenue - self.previous_revenue) / self.previous_revenue * 100.0),
+        }
+    }
+}
+
+const HEATMAP_DAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+// Fields every vending record should carry. Typed records cannot hold a value of the wrong
+// type, so only missing values are checked.
+type FieldCheck = (&'static str, fn(&VendingRecord) -> bool);
+const REQUIRED_FIELDS: [FieldCheck; 10] = [
+    ("meterNumber", |record| record.meter_number.is_none()),
+    ("address", |record| record.address.is_none()),
+    ("community", |record| record.community.is_none()),
+    ("customerName", |record| record.customer_name.is_none()),
+    ("token", |record| record.token.is_none()),
+    ("userId", |record| record.user_id.is_none()),
+    ("vendingStation", |record| record.vending_station.is_none()),
+    ("amount", |record| record.amount.is_none()),
+    ("kwh", |record| record.kwh.is_none()),
+    ("tariff", |record| record.tariff.is_none()),
+];
+
+// Same rule as the tariff_mismatch anomaly: the amount must match kWh * tariff,
+// with or without the fixed charge
+fn kwh_tariff_mismatch(record: &VendingRecord) -> bool {
+    let (Some(amount), Some(kwh), Some(tariff)) = (record.amount, record.kwh, record.tariff) else {
+        return false;
+    };
+    if amount <= 0.0 {
+        return false;
+    }
+    let energy_cost = kwh * tariff;
+    let with_fixed_charge = energy_cost + record.fixed_charge.unwrap_or(0.0);
+    let deviation = (amount - energy_cost)
+        .abs()
+        .min((amount - with_fixed_charge).abs());
+    deviation / amount > TARIFF_TOLERANCE
+}
+
+#[async_trait]
+impl VendingRecordRepository for InMemoryVendingRecordRepository {
+    fn with_scope(&self, scope: AccessScope) -> Arc<dyn VendingRecordRepository> {
+        Arc::new(Self {
+            records: self.records.clone(),
+            scope,
+        })
+    }
+
+    async fn get_vending_records(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<VendingRecordBatch, AppError> {
+        // Records were decoded when they were loaded, so there is nothing to report
+        Ok(VendingRecordBatch {
+            records: self
+                .records
+                .iter()
+                .filter(|record| record.timestamp >= start_date && record.timestamp <= end_date)
+                .filter(|record| self.in_scope(record))
+                .cloned()
+                .collect(),
+            decode_report: DecodeReport::default(),
+        })
+    }
+
+    async fn get_vending_summary(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        series: SummarySeriesOptions,
+    ) -> Result<VendingSummary, AppError> {
+        // Group by vending station and UTC date for daily summaries
+        let mut stations: BTreeMap<&str, BTreeMap<NaiveDate, DailySummary>> = BTreeMap::new();
+        for record in self.records_between(start_date, end_date) {
+            let station = record.vending_station.as_deref().unwrap_or("Unknown");
+            let day = record.timestamp.date_naive();
+            let daily = stations
+                .entry(station)
+                .or_default()
+                .entry(day)
+                .or_insert_with(|| DailySummary {
+                    date: day.format("%Y-%m-%d").to_string(),
+                    ..DailySummary::default()
+                });
+            daily.total_transactions += 1;
+            daily.total_amount += record.amount.unwrap_or(0.0);
+            daily.total_kwh += record.kwh.unwrap_or(0.0);
+        }
+
+        let period_start = start_date.format("%Y-%m-%d").to_string();
+        let period_end = end_date.format("%Y-%m-%d").to_string();
+        let mut summary = VendingSummary {
+            period_start: period_start.clone(),
+            period_end: period_end.clone(),
+            ..VendingSummary::default()
+        };
+        for (station, days) in stations {
+            let mut daily: Vec<(NaiveDate, DailySummary)> = days.into_iter().collect();
+            if series.cumulative || series.moving_averages {
+                add_summary_series(&mut daily, start_date.date_naive(), series);
+            }
+            let daily_summaries: Vec<DailySummary> =
+                daily.into_iter().map(|(_, summary)| summary).collect();
+
+            let station_summary = VendingStationSummary {
+                vending_station: station.to_string(),
+                total_transactions: daily_summaries.iter().map(|d| d.total_transactions).sum(),
+                total_amount: daily_summaries.iter().map(|d| d.total_amount).sum(),
+                total_kwh: daily_summaries.iter().map(|d| d.total_kwh).sum(),
+                period_start: period_start.clone(),
+                period_end: period_end.clone(),
+                daily_summaries,
+            };
+            summary.total_transactions += station_summary.total_transactions;
+            summary.total_amount += station_summary.total_amount;
+            summary.total_kwh += station_summary.total_kwh;
+            summary.vending_station_summaries.push(station_summary);
+        }
+
+        Ok(summary)
+    }
+
+    async fn get_vending_statistics(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        histogram_buckets: u32,
+    ) -> Result<VendingStatistics, AppError> {
+        let records = self.records_between(start_date, end_date);
+        let amounts: Vec<f64> = records.iter().filter_map(|record| record.amount).collect();
+        let kwhs: Vec<f64> = records.iter().filter_map(|record| record.kwh).collect();
+
+        Ok(VendingStatistics {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            amount: MetricDistribution {
+                stats: distribution(&amounts),
+                histogram: auto_histogram(&amounts, histogram_buckets),
+            },
+            kwh: MetricDistribution {
+                stats: distribution(&kwhs),
+                histogram: auto_histogram(&kwhs, histogram_buckets),
+            },
+            vending_station_statistics: group_statistics(&records, |record| {
+                &record.vending_station
+            }),
+            community_statistics: group_statistics(&records, |record| &record.community),
+        })
+    }
+
+    async fn get_leaderboard(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        dimension: LeaderboardDimension,
+        metric: LeaderboardMetric,
+        limit: u32,
+    ) -> Result<Leaderboard, AppError> {
+        let mut totals = LeaderboardEntry::default();
+        let mut groups: BTreeMap<&str, LeaderboardEntry> = BTreeMap::new();
+        for record in self.records_between(start_date, end_date) {
+            let entry = groups
+                .entry(leaderboard_key(record, dimension))
+                .or_default();
+            for entry in [&mut totals, entry] {
+                entry.total_transactions += 1;
+                entry.total_amount += record.amount.unwrap_or(0.0);
+                entry.total_kwh += record.kwh.unwrap_or(0.0);
+            }
+        }
+
+        // Highest metric first, ties by name
+        let metric_total = leaderboard_metric(&totals, metric);
+        let mut entries: Vec<LeaderboardEntry> = groups
+            .into_iter()
+            .map(|(name, entry)| LeaderboardEntry {
+                name: name.to_string(),
+                ..entry
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            leaderboard_metric(b, metric).total_cmp(&leaderboard_metric(a, metric))
+        });
+        entries.truncate(limit as usize);
+        for (index, entry) in entries.iter_mut().enumerate() {
+            entry.rank = index as u32 + 1;
+            entry.share_percent = if metric_total != 0.0 {
+                leaderboard_metric(entry, metric) / metric_total * 100.0
+            } else {
+                0.0
+            };
+        }
+
+        Ok(Leaderboard {
+            dimension,
+            metric,
+            total_transactions: totals.total_transactions,
+            total_amount: totals.total_amount,
+            total_kwh: totals.total_kwh,
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            entries,
+        })
+    }
+
+    async fn get_inactive_meters(
+        &self,
+        history_start: DateTime<Utc>,
+        inactive_since: DateTime<Utc>,
+        min_purchases: u32,
+    ) -> Result<Vec<InactiveMeter>, AppError> {
+        let records = self.records_between(history_start, DateTime::<Utc>::MAX_UTC);
+        let now = Utc::now();
+        let mut meters = Vec::new();
+
+        for (meter_number, history) in group_by_meter(&records) {
+            let (first, last) = (history[0], history[history.len() - 1]);
+            // Meters that bought regularly but have gone quiet
+            if (history.len() as u32) < min_purchases || last.timestamp >= inactive_since {
+                continue;
+            }
+
+            let span_days = (last.timestamp - first.timestamp).num_seconds() as f64 / 86_400.0;
+            let typical_interval_days = if history.len() > 1 {
+                span_days / (history.len() - 1) as f64
+            } else {
+                0.0
+            };
+            let days_since_last_purchase = (now - last.timestamp).num_seconds() as f64 / 86_400.0;
+            let overdue_factor = if typical_interval_days > 0.0 {
+                days_since_last_purchase / typical_interval_days
+            } else {
+                0.0
+            };
+
+            meters.push(InactiveMeter {
+                meter_number: meter_number.to_string(),
+                customer_name: last.customer_name.clone(),
+                community: last.community.clone(),
+                vending_station: last.vending_station.clone(),
+                first_purchase: first.timestamp,
+                last_purchase: last.timestamp,
+                purchase_count: history.len() as u32,
+                typical_interval_days,
+                days_since_last_purchase,
+                overdue_factor,
+            });
+        }
+
+        // Most overdue relative to their own buying rhythm first
+        meters.sort_by(|a, b| b.overdue_factor.total_cmp(&a.overdue_factor));
+
+        Ok(meters)
+    }
+
+    async fn get_meter_behaviour(
+        &self,
+        as_of: DateTime<Utc>,
+        window_days: u32,
+        meter_number: Option<&str>,
+    ) -> Result<Vec<MeterBehaviour>, AppError> {
+        let window = chrono::Duration::days(window_days as i64);
+        let recent_start = as_of - window;
+        let prior_start = recent_start - window;
+
+        let records = self.records_between(prior_start, as_of);
+        Ok(group_by_meter(&records)
+            .into_iter()
+            .filter(|(meter, _)| meter_number.is_none_or(|wanted| wanted == *meter))
+            .map(|(meter, history)| meter_behaviour(meter, &history, recent_start))
+            .collect())
+    }
+
+    async fn get_credit_runout(
+        &self,
+        history_start: DateTime<Utc>,
+        as_of: DateTime<Utc>,
+    ) -> Result<Vec<CreditRunout>, AppError> {
+        let records = self.records_between(history_start, as_of);
+        // Meters with a single vend have no consumption rate to extrapolate from
+        let mut meters: Vec<CreditRunout> = group_by_meter(&records)
+            .into_iter()
+            .filter_map(|(meter, history)| credit_runout(meter, &history, as_of))
+            .collect();
+
+        meters.sort_by_key(|meter| meter.estimated_runout);
+
+        Ok(meters)
+    }
+
+    async fn get_operator_shifts(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        operator: Option<&str>,
+    ) -> Result<Vec<OperatorShift>, AppError> {
+        let tz = parse_timezone(timezone)?;
+
+        // Group by local date first so shifts come out sorted by date, then operator
+        let mut shifts: BTreeMap<(String, &str), (OperatorShift, BTreeSet<&str>)> = BTreeMap::new();
+        for record in self.records_between(start_date, end_date) {
+            let Some(user_id) = non_empty(&record.user_id) else {
+                continue;
+            };
+            if operator.is_some_and(|operator| operator != user_id) {
+                continue;
+            }
+            let date = record
+                .timestamp
+                .with_timezone(&tz)
+                .format("%Y-%m-%d")
+                .to_string();
+            let (shift, stations) = shifts.entry((date.clone(), user_id)).or_insert_with(|| {
+                (
+                    OperatorShift {
+                        user_id: user_id.to_string(),
+                        date,
+                        vending_stations: Vec::new(),
+                        transaction_count: 0,
+                        total_amount: 0.0,
+                        first_vend: record.timestamp,
+                        last_vend: record.timestamp,
+                        voided_count: 0,
+                        voided_amount: 0.0,
+                        expected_cash: 0.0,
+                        counted_cash: None,
+                        variance: None,
+                    },
+                    BTreeSet::new(),
+                )
+            });
+
+            // Typed records carry no terminal void flag, so only negative reversals count as voided
+            let amount = record.amount.unwrap_or(0.0);
+            if amount < 0.0 {
+                shift.voided_count += 1;
+                shift.voided_amount += amount.abs();
+            } else {
+                shift.transaction_count += 1;
+                shift.total_amount += amount;
+            }
+            shift.expected_cash += amount;
+            shift.last_vend = record.timestamp;
+            stations.insert(record.vending_station.as_deref().unwrap_or("Unknown"));
+        }
+
+        Ok(shifts
+            .into_values()
+            .map(|(mut shift, stations)| {
+                shift.vending_stations = stations.into_iter().map(str::to_string).collect();
+                shift
+            })
+            .collect())
+    }
+
+    async fn get_meter_amount_baselines(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<MeterAmountBaseline>, AppError> {
+        let records = self.records_between(start_date, end_date);
+        Ok(group_by_meter(&records)
+            .into_iter()
+            .filter_map(|(meter_number, history)| {
+                let amounts: Vec<f64> = history.iter().filter_map(|record| record.amount).collect();
+                Some(MeterAmountBaseline {
+                    meter_number: meter_number.to_string(),
+                    purchase_count: amounts.len() as u32,
+                    mean_amount: mean(&amounts)?,
+                    std_dev_amount: population_std_dev(&amounts).unwrap_or(0.0),
+                })
+            })
+            .collect())
+    }
+
+    async fn get_integrity_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        window_seconds: u32,
+    ) -> Result<IntegrityReport, AppError> {
+        let records = self.records_between(start_date, end_date);
+
+        Ok(IntegrityReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            near_duplicate_window_seconds: window_seconds,
+            duplicate_tokens: exact_duplicates(&records, |record| &record.token),
+            duplicate_transaction_ids: exact_duplicates(&records, |record| &record.transaction_id),
+            near_duplicate_vends: near_duplicates(&records, window_seconds),
+        })
+    }
+
+    async fn get_transaction_sequence(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<TransactionSequenceEntry>, AppError> {
+        let mut entries: Vec<TransactionSequenceEntry> = self
+            .records_between(start_date, end_date)
+            .into_iter()
+            .filter_map(|record| {
+                Some(TransactionSequenceEntry {
+                    record_id: record.id.clone(),
+                    vending_station: non_empty(&record.vending_station)?.to_string(),
+                    transaction_id: non_empty(&record.transaction_id)?.to_string(),
+                    timestamp: record.timestamp,
+                })
+            })
+            .collect();
+
+        // Stable, so vends stay in time order within each station
+        entries.sort_by(|a, b| a.vending_station.cmp(&b.vending_station));
+
+        Ok(entries)
+    }
+
+    async fn get_heatmap(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        vending_station: Option<&str>,
+        community: Option<&str>,
+    ) -> Result<VendingHeatmap, AppError> {
+        let tz = parse_timezone(timezone)?;
+
+        let mut transaction_counts = vec![vec![0_u32; 24]; 7];
+        let mut amounts = vec![vec![0.0; 24]; 7];
+        for record in self.records_between(start_date, end_date) {
+            if vending_station
+                .is_some_and(|station| record.vending_station.as_deref() != Some(station))
+                || community.is_some_and(|community| record.community.as_deref() != Some(community))
+            {
+                continue;
+            }
+            let local = record.timestamp.with_timezone(&tz);
+            let (day, hour) = (
+                local.weekday().num_days_from_monday() as usize,
+                local.hour() as usize,
+            );
+            transaction_counts[day][hour] += 1;
+            amounts[day][hour] += record.amount.unwrap_or(0.0);
+        }
+
+        Ok(VendingHeatmap {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            timezone: timezone.to_string(),
+            vending_station: vending_station.map(str::to_string),
+            community: community.map(str::to_string),
+            days: HEATMAP_DAYS.iter().map(|day| day.to_string()).collect(),
+            transaction_counts,
+            amounts,
+        })
+    }
+
+    async fn get_kpis(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<KpiReport, AppError> {
+        Ok(self.kpi_report(start_date, end_date, &BTreeSet::new()))
+    }
+
+    async fn get_data_quality_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        now: DateTime<Utc>,
+        sample_size: u32,
+    ) -> Result<DataQualityReport, AppError> {
+        let in_range: Vec<&VendingRecord> = self
+            .records
+            .iter()
+            .filter(|record| record.timestamp >= start_date && record.timestamp <= end_date)
+            .filter(|record| self.in_scope(record))
+            .collect();
+
+        let mut issues = Vec::new();
+        let mut report =
+            |problem: DataQualityProblem, field: Option<&str>, matches: Vec<&VendingRecord>| {
+                if matches.is_empty() {
+                    return;
+                }
+                issues.push(DataQualityIssue {
+                    problem,
+                    field: field.map(str::to_string),
+                    document_count: matches.len() as u32,
+                    sample_ids: matches
+                        .iter()
+                        .take(sample_size as usize)
+                        .map(|record| record.id.clone())
+                        .collect(),
+                });
+            };
+
+        // Future timestamps fall outside any date range, so they are checked everywhere
+        report(
+            DataQualityProblem::FutureTimestamp,
+            None,
+            self.records
+                .iter()
+                .filter(|record| record.timestamp > now && self.in_scope(record))
+                .collect(),
+        );
+        for (field, is_missing) in REQUIRED_FIELDS {
+            report(
+                DataQualityProblem::MissingField,
+                Some(field),
+                in_range.iter().copied().filter(|r| is_missing(r)).collect(),
+            );
+        }
+        report(
+            DataQualityProblem::NegativeAmount,
+            None,
+            in_range
+                .iter()
+                .copied()
+                .filter(|record| record.amount.is_some_and(|amount| amount < 0.0))
+                .collect(),
+        );
+        report(
+            DataQualityProblem::KwhTariffMismatch,
+            None,
+            in_range
+                .iter()
+                .copied()
+                .filter(|record| kwh_tariff_mismatch(record))
+                .collect(),
+        );
+        issues.sort_by_key(|issue| std::cmp::Reverse(issue.document_count));
+
+        Ok(DataQualityReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            checked_at: now,
+            documents_scanned: in_range.len() as u32,
+            issues,
+        })
+    }
+}
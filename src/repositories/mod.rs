@@ -1,5 +1,35 @@
+mod anomaly_flag_repository;
+mod api_key_repository;
+#[cfg(test)]
+mod backend_tests;
+mod cash_count_repository;
+mod daily_rollup_repository;
+mod in_memory_vending_record_repo;
+mod mongodb_anomaly_flag_repo;
+mod mongodb_api_key_repo;
+mod mongodb_cash_count_repo;
+mod mongodb_daily_rollup_repo;
+mod mongodb_station_hours_repo;
+mod mongodb_transaction_id_rule_repo;
 mod mongodb_vending_record_repo;
+mod sql_vending_record_repo;
+mod station_hours_repository;
+mod transaction_id_rule_repository;
 mod vending_record_repository;
 
+pub use anomaly_flag_repository::AnomalyFlagRepository;
+pub use api_key_repository::ApiKeyRepository;
+pub use cash_count_repository::CashCountRepository;
+pub use daily_rollup_repository::DailyRollupRepository;
+pub use in_memory_vending_record_repo::InMemoryVendingRecordRepository;
+pub use mongodb_anomaly_flag_repo::MongoDbAnomalyFlagRepository;
+pub use mongodb_api_key_repo::MongoDbApiKeyRepository;
+pub use mongodb_cash_count_repo::MongoDbCashCountRepository;
+pub use mongodb_daily_rollup_repo::MongoDbDailyRollupRepository;
+pub use mongodb_station_hours_repo::MongoDbStationHoursRepository;
+pub use mongodb_transaction_id_rule_repo::MongoDbTransactionIdRuleRepository;
 pub use mongodb_vending_record_repo::MongoDbVendingRecordRepository;
+pub use sql_vending_record_repo::SqlVendingRecordRepository;
+pub use station_hours_repository::StationHoursRepository;
+pub use transaction_id_rule_repository::TransactionIdRuleRepository;
 pub use vending_record_repository::VendingRecordRepository;
@@ -0,0 +1,176 @@
+use crate::error::AppError;
+use crate::model::{AccessScope, AnomalyFlag, AnomalyKind, AnomalySeverity};
+use crate::repositories::AnomalyFlagRepository;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::{
+    Collection,
+    bson::{Document, doc, to_bson},
+};
+use serde::{Deserialize, Serialize};
+
+// Internal struct for MongoDB operations with BSON DateTime
+#[derive(Debug, Serialize, Deserialize)]
+struct MongoAnomalyFlag {
+    #[serde(rename = "recordId")]
+    pub record_id: String,
+    pub kind: AnomalyKind,
+    pub severity: AnomalySeverity,
+    pub timestamp: mongodb::bson::DateTime,
+    #[serde(rename = "meterNumber", default)]
+    pub meter_number: Option<String>,
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: Option<String>,
+    // Flags stored before communities were recorded have none and only show to unscoped callers
+    #[serde(default)]
+    pub community: Option<String>,
+    #[serde(rename = "userId", default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub amount: Option<f64>,
+    pub details: String,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: mongodb::bson::DateTime,
+}
+
+impl From<MongoAnomalyFlag> for AnomalyFlag {
+    fn from(mongo_flag: MongoAnomalyFlag) -> Self {
+        AnomalyFlag {
+            record_id: mongo_flag.record_id,
+            kind: mongo_flag.kind,
+            severity: mongo_flag.severity,
+            timestamp: mongo_flag.timestamp.to_chrono(),
+            meter_number: mongo_flag.meter_number,
+            vending_station: mongo_flag.vending_station,
+            community: mongo_flag.community,
+            user_id: mongo_flag.user_id,
+            amount: mongo_flag.amount,
+            details: mongo_flag.details,
+            detected_at: mongo_flag.detected_at.to_chrono(),
+        }
+    }
+}
+
+impl From<&AnomalyFlag> for MongoAnomalyFlag {
+    fn from(flag: &AnomalyFlag) -> Self {
+        MongoAnomalyFlag {
+            record_id: flag.record_id.clone(),
+            kind: flag.kind,
+            severity: flag.severity,
+            timestamp: mongodb::bson::DateTime::from_millis(flag.timestamp.timestamp_millis()),
+            meter_number: flag.meter_number.clone(),
+            vending_station: flag.vending_station.clone(),
+            community: flag.community.clone(),
+            user_id: flag.user_id.clone(),
+            amount: flag.amount,
+            details: flag.details.clone(),
+            detected_at: mongodb::bson::DateTime::from_millis(flag.detected_at.timestamp_millis()),
+        }
+    }
+}
+
+pub struct MongoDbAnomalyFlagRepository {
+    collection: Collection<MongoAnomalyFlag>,
+}
+
+impl MongoDbAnomalyFlagRepository {
+    pub fn from_collection(collection: Collection<AnomalyFlag>) -> Self {
+        Self {
+            collection: collection.clone_with_type::<MongoAnomalyFlag>(),
+        }
+    }
+}
+
+// Flags on vends within the date range and the caller's stations and communities
+fn range_filter(
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    scope: &AccessScope,
+) -> Document {
+    let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+    let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+
+    let mut filter = doc! {
+        "timestamp": { "$gte": start_bson, "$lte": end_bson }
+    };
+    if let Some(stations) = &scope.vending_stations {
+        filter.insert("vendingStation", doc! { "$in": stations });
+    }
+    if let Some(communities) = &scope.communities {
+        filter.insert("community", doc! { "$in": communities });
+    }
+    filter
+}
+
+#[async_trait]
+impl AnomalyFlagRepository for MongoDbAnomalyFlagRepository {
+    async fn replace_flags(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        scope: &AccessScope,
+        detected_at: DateTime<Utc>,
+        flags: &[AnomalyFlag],
+    ) -> Result<u64, AppError> {
+        // Insert the new flags before removing the old ones, so a failed write leaves the
+        // previous scan in place rather than no flags at all
+        let saved = if flags.is_empty() {
+            0
+        } else {
+            self.collection
+                .insert_many(flags.iter().map(MongoAnomalyFlag::from))
+                .await?
+                .inserted_ids
+                .len() as u64
+        };
+
+        let mut stale = range_filter(start_date, end_date, scope);
+        stale.insert(
+            "detectedAt",
+            doc! { "$lt": mongodb::bson::DateTime::from_millis(detected_at.timestamp_millis()) },
+        );
+        self.collection.delete_many(stale).await?;
+
+        Ok(saved)
+    }
+
+    async fn get_flags(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        scope: &AccessScope,
+        min_severity: Option<AnomalySeverity>,
+        kind: Option<AnomalyKind>,
+    ) -> Result<Vec<AnomalyFlag>, AppError> {
+        let mut filter = range_filter(start_date, end_date, scope);
+        if let Some(min_severity) = min_severity {
+            let severities: Vec<_> = [
+                AnomalySeverity::Low,
+                AnomalySeverity::Medium,
+                AnomalySeverity::High,
+            ]
+            .into_iter()
+            .filter(|severity| *severity >= min_severity)
+            .map(|severity| to_bson(&severity))
+            .collect::<Result<_, _>>()?;
+            filter.insert("severity", doc! { "$in": severities });
+        }
+        if let Some(kind) = kind {
+            filter.insert("kind", to_bson(&kind)?);
+        }
+
+        let mut cursor = self
+            .collection
+            .find(filter)
+            .sort(doc! { "timestamp": -1 })
+            .await?;
+        let mut flags = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            flags.push(AnomalyFlag::from(result?));
+        }
+
+        Ok(flags)
+    }
+}
@@ -0,0 +1,262 @@
+use crate::error::AppError;
+use crate::model::ApiKey;
+use crate::repositories::ApiKeyRepository;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::{
+    Collection, IndexModel,
+    bson::doc,
+    options::{IndexOptions, ReturnDocument},
+};
+use serde::{Deserialize, Serialize};
+
+fn to_bson_datetime(datetime: DateTime<Utc>) -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_millis(datetime.timestamp_millis())
+}
+
+// Internal struct for MongoDB operations with BSON DateTime
+#[derive(Debug, Serialize, Deserialize)]
+struct MongoApiKey {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    #[serde(rename = "keyHash")]
+    pub key_hash: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub stations: Vec<String>,
+    #[serde(default)]
+    pub communities: Vec<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: mongodb::bson::DateTime,
+    #[serde(rename = "rotatedAt", default)]
+    pub rotated_at: Option<mongodb::bson::DateTime>,
+    #[serde(rename = "revokedAt", default)]
+    pub revoked_at: Option<mongodb::bson::DateTime>,
+}
+
+impl From<MongoApiKey> for ApiKey {
+    fn from(mongo_key: MongoApiKey) -> Self {
+        ApiKey {
+            id: mongo_key.id,
+            name: mongo_key.name,
+            prefix: mongo_key.prefix,
+            key_hash: mongo_key.key_hash,
+            roles: mongo_key.roles,
+            stations: mongo_key.stations,
+            communities: mongo_key.communities,
+            created_at: mongo_key.created_at.to_chrono(),
+            rotated_at: mongo_key.rotated_at.map(|at| at.to_chrono()),
+            revoked_at: mongo_key.revoked_at.map(|at| at.to_chrono()),
+        }
+    }
+}
+
+impl From<ApiKey> for MongoApiKey {
+    fn from(api_key: ApiKey) -> Self {
+        MongoApiKey {
+            id: api_key.id,
+            name: api_key.name,
+            prefix: api_key.prefix,
+            key_hash: api_key.key_hash,
+            roles: api_key.roles,
+            stations: api_key.stations,
+            communities: api_key.communities,
+            created_at: to_bson_datetime(api_key.created_at),
+            rotated_at: api_key.rotated_at.map(to_bson_datetime),
+            revoked_at: api_key.revoked_at.map(to_bson_datetime),
+        }
+    }
+}
+
+pub struct MongoDbApiKeyRepository {
+    collection: Collection<MongoApiKey>,
+}
+
+impl MongoDbApiKeyRepository {
+    pub fn from_collection(collection: Collection<ApiKey>) -> Self {
+        Self {
+            collection: collection.clone_with_type::<MongoApiKey>(),
+        }
+    }
+
+    async fn ensure_indexes(&self) -> Result<(), AppError> {
+        // Every authenticated request looks its key up by hash
+        let index = IndexModel::builder()
+            .keys(doc! { "keyHash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.collection.create_index(index).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MongoDbApiKeyRepository {
+    async fn create_api_key(&self, api_key: ApiKey) -> Result<ApiKey, AppError> {
+        self.ensure_indexes().await?;
+        let mongo_key = MongoApiKey::from(api_key);
+        self.collection.insert_one(&mongo_key).await?;
+        Ok(ApiKey::from(mongo_key))
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        let mut cursor = self
+            .collection
+            .find(doc! {})
+            .sort(doc! { "createdAt": -1 })
+            .await?;
+        let mut keys = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            keys.push(ApiKey::from(result?));
+        }
+
+        Ok(keys)
+    }
+
+    async fn find_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        let key = self
+            .collection
+            .find_one(doc! { "keyHash": key_hash, "revokedAt": null })
+            .await?;
+        Ok(key.map(ApiKey::from))
+    }
+
+    async fn rotate_api_key(
+        &self,
+        id: &str,
+        key_hash: &str,
+        prefix: &str,
+        rotated_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, AppError> {
+        let key = self
+            .collection
+            .find_one_and_update(
+                doc! { "_id": id, "revokedAt": null },
+                doc! { "$set": {
+                    "keyHash": key_hash,
+                    "prefix": prefix,
+                    "rotatedAt": to_bson_datetime(rotated_at),
+                } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?;
+        Ok(key.map(ApiKey::from))
+    }
+
+    async fn revoke_api_key(
+        &self,
+        id: &str,
+        revoked_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, AppError> {
+        let key = self
+            .collection
+            .find_one_and_update(
+                doc! { "_id": id, "revokedAt": null },
+                doc! { "$set": { "revokedAt": to_bson_datetime(revoked_at) } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?;
+        Ok(key.map(ApiKey::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{generate_api_key, hash_api_key};
+
+    fn api_key(id: &str, key: &str, prefix: String) -> ApiKey {
+        ApiKey {
+            id: id.to_string(),
+            name: format!("{} key", id),
+            prefix,
+            key_hash: hash_api_key(key),
+            roles: vec!["cashier".to_string()],
+            stations: vec!["Central".to_string()],
+            communities: Vec::new(),
+            created_at: Utc::now(),
+            rotated_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB: set TEST_MONGODB_URI and run with --ignored"]
+    async fn keys_are_found_rotated_and_revoked() {
+        let uri = std::env::var("TEST_MONGODB_URI").expect("TEST_MONGODB_URI is not set");
+        let collection = mongodb::Client::with_uri_str(&uri)
+            .await
+            .unwrap()
+            .database("jep_backend_tests")
+            .collection::<ApiKey>(&format!("api_keys_{}", uuid::Uuid::new_v4().simple()));
+        let repo = MongoDbApiKeyRepository::from_collection(collection.clone());
+
+        let (key, prefix) = generate_api_key();
+        repo.create_api_key(api_key("key-1", &key, prefix))
+            .await
+            .unwrap();
+        let found = repo
+            .find_active_api_key(&hash_api_key(&key))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, "key-1");
+        assert_eq!(found.stations, vec!["Central"]);
+
+        let (new_key, new_prefix) = generate_api_key();
+        let rotated = repo
+            .rotate_api_key("key-1", &hash_api_key(&new_key), &new_prefix, Utc::now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rotated.prefix, new_prefix);
+        assert!(rotated.rotated_at.is_some());
+        // The old secret stops working as soon as the key is rotated
+        assert!(
+            repo.find_active_api_key(&hash_api_key(&key))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.find_active_api_key(&hash_api_key(&new_key))
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let revoked = repo
+            .revoke_api_key("key-1", Utc::now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(revoked.revoked_at.is_some());
+        assert!(
+            repo.find_active_api_key(&hash_api_key(&new_key))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        // Revoked keys can be neither rotated nor revoked again, but stay listed
+        assert!(
+            repo.rotate_api_key("key-1", "hash", "prefix", Utc::now())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.revoke_api_key("key-1", Utc::now())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(repo.get_api_keys().await.unwrap().len(), 1);
+
+        collection.drop().await.unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use crate::error::AppError;
+use crate::model::CashCount;
+use crate::repositories::CashCountRepository;
+use async_trait::async_trait;
+use mongodb::{Collection, bson::doc};
+use serde::{Deserialize, Serialize};
+
+// Internal struct for MongoDB operations with BSON DateTime
+#[derive(Debug, Serialize, Deserialize)]
+struct MongoCashCount {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub date: String,
+    // Counts recorded before stations and identities were stored lack these fields
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: String,
+    #[serde(rename = "countedAmount")]
+    pub counted_amount: f64,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(rename = "recordedBy", default)]
+    pub recorded_by: String,
+    #[serde(rename = "recordedAt")]
+    pub recorded_at: mongodb::bson::DateTime,
+}
+
+impl From<MongoCashCount> for CashCount {
+    fn from(mongo_count: MongoCashCount) -> Self {
+        CashCount {
+            user_id: mongo_count.user_id,
+            date: mongo_count.date,
+            vending_station: mongo_count.vending_station,
+            counted_amount: mongo_count.counted_amount,
+            notes: mongo_count.notes,
+            recorded_by: mongo_count.recorded_by,
+            recorded_at: mongo_count.recorded_at.to_chrono(),
+        }
+    }
+}
+
+impl From<CashCount> for MongoCashCount {
+    fn from(cash_count: CashCount) -> Self {
+        MongoCashCount {
+            user_id: cash_count.user_id,
+            date: cash_count.date,
+            vending_station: cash_count.vending_station,
+            counted_amount: cash_count.counted_amount,
+            notes: cash_count.notes,
+            recorded_by: cash_count.recorded_by,
+            recorded_at: mongodb::bson::DateTime::from_millis(
+                cash_count.recorded_at.timestamp_millis(),
+            ),
+        }
+    }
+}
+
+pub struct MongoDbCashCountRepository {
+    collection: Collection<MongoCashCount>,
+}
+
+impl MongoDbCashCountRepository {
+    pub fn from_collection(collection: Collection<CashCount>) -> Self {
+        Self {
+            collection: collection.clone_with_type::<MongoCashCount>(),
+        }
+    }
+}
+
+#[async_trait]
+impl CashCountRepository for MongoDbCashCountRepository {
+    async fn save_cash_count(&self, cash_count: CashCount) -> Result<CashCount, AppError> {
+        let mongo_count = MongoCashCount::from(cash_count);
+
+        // One count per operator, day and station; a recount replaces the previous one
+        self.collection
+            .replace_one(
+                doc! {
+                    "userId": &mongo_count.user_id,
+                    "date": &mongo_count.date,
+                    "vendingStation": &mongo_count.vending_station,
+                },
+                &mongo_count,
+            )
+            .upsert(true)
+            .await?;
+
+        Ok(CashCount::from(mongo_count))
+    }
+
+    async fn get_cash_counts(
+        &self,
+        start_date: &str,
+        end_date: &str,
+        vending_stations: Option<&[String]>,
+    ) -> Result<Vec<CashCount>, AppError> {
+        // Dates are stored as YYYY-MM-DD strings, which compare chronologically
+        let mut filter = doc! {
+            "date": { "$gte": start_date, "$lte": end_date }
+        };
+        if let Some(vending_stations) = vending_stations {
+            filter.insert("vendingStation", doc! { "$in": vending_stations });
+        }
+
+        let mut cursor = self.collection.find(filter).await?;
+        let mut counts = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            counts.push(CashCount::from(result?));
+        }
+
+        Ok(counts)
+    }
+}
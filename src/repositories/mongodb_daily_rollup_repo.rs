@@ -0,0 +1,625 @@
+use crate::config::CollectionNames;
+use crate::error::AppError;
+use crate::model::{RollupRefresh, RollupStatus};
+use crate::repositories::DailyRollupRepository;
+use async_trait::async_trait;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use mongodb::{
+    Collection, Database, IndexModel,
+    bson::{Bson, Document, doc, oid::ObjectId},
+    options::IndexOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+// Identifier of the watermark document in the state collection
+const ROLLUP_STATE_ID: &str = "daily_rollups";
+// Days recomputed per aggregation when catching up on a long backlog
+const REFRESH_CHUNK_DAYS: u64 = 31;
+// Taken off the change watermark to allow for clock differences between writers and this server
+const CHANGE_WATERMARK_MARGIN_MS: i64 = 10 * 60 * 1000;
+// Relative difference above which a day's raw totals no longer match its rollups
+const TOTALS_TOLERANCE: f64 = 1e-9;
+
+// Internal struct for the refresh watermark with BSON DateTime
+#[derive(Debug, Serialize, Deserialize)]
+struct MongoRollupState {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "refreshedThrough")]
+    pub refreshed_through: String,
+    #[serde(rename = "lastRefreshAt")]
+    pub last_refresh_at: mongodb::bson::DateTime,
+    #[serde(rename = "changesSince", default)]
+    pub changes_since: Option<mongodb::bson::DateTime>,
+}
+
+impl From<MongoRollupState> for RollupStatus {
+    fn from(mongo_state: MongoRollupState) -> Self {
+        RollupStatus {
+            refreshed_through: Some(mongo_state.refreshed_through),
+            last_refresh_at: Some(mongo_state.last_refresh_at.to_chrono()),
+            changes_since: mongo_state.changes_since.map(|since| since.to_chrono()),
+        }
+    }
+}
+
+fn start_of_day(day: NaiveDate) -> mongodb::bson::DateTime {
+    let start = DateTime::<Utc>::from_naive_utc_and_offset(
+        day.and_hms_opt(0, 0, 0).expect("midnight is a valid time"),
+        Utc,
+    );
+    mongodb::bson::DateTime::from_millis(start.timestamp_millis())
+}
+
+fn parse_day(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+fn format_day(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d").to_string()
+}
+
+// Smallest ObjectId generated at or after the given time, for comparing insert times
+fn object_id_at(time: mongodb::bson::DateTime) -> ObjectId {
+    let mut bytes = [0u8; 12];
+    bytes[..4].copy_from_slice(&((time.timestamp_millis() / 1000) as u32).to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+// Read a numeric aggregation output regardless of whether Mongo produced an int or a double
+fn bson_number(value: Option<&Bson>) -> f64 {
+    match value {
+        Some(Bson::Double(v)) => *v,
+        Some(Bson::Int32(v)) => *v as f64,
+        Some(Bson::Int64(v)) => *v as f64,
+        _ => 0.0,
+    }
+}
+
+// Per-day transaction count, amount and kWh, used to tell whether a day's rollups are current
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct DayTotals {
+    transactions: f64,
+    amount: f64,
+    kwh: f64,
+}
+
+impl DayTotals {
+    fn matches(&self, other: &DayTotals) -> bool {
+        let close =
+            |a: f64, b: f64| (a - b).abs() <= TOTALS_TOLERANCE * a.abs().max(b.abs()).max(1.0);
+        self.transactions == other.transactions
+            && close(self.amount, other.amount)
+            && close(self.kwh, other.kwh)
+    }
+}
+
+// Split sorted days into runs of consecutive days
+fn consecutive_spans(days: &BTreeSet<NaiveDate>) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut spans: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    for &day in days {
+        match spans.last_mut() {
+            Some((_, end)) if *end + Days::new(1) == day => *end = day,
+            _ => spans.push((day, day)),
+        }
+    }
+    spans
+}
+
+/// Materialized per station/community/day totals in `daily_rollups`, recomputed from `vending_records`
+#[derive(Clone)]
+pub struct MongoDbDailyRollupRepository {
+    records: Collection<Document>,
+    rollups: Collection<Document>,
+    state: Collection<MongoRollupState>,
+}
+
+impl MongoDbDailyRollupRepository {
+    pub fn from_collections(
+        records: Collection<Document>,
+        rollups: Collection<Document>,
+        state: Collection<Document>,
+    ) -> Self {
+        Self {
+            records,
+            rollups,
+            state: state.clone_with_type::<MongoRollupState>(),
+        }
+    }
+
+    /// Repository over the configured vending record, rollup and rollup state collections
+    pub fn from_database(database: &Database, collections: &CollectionNames) -> Self {
+        Self::from_collections(
+            database.collection(&collections.vending_records),
+            database.collection(&collections.daily_rollups),
+            database.collection(&collections.rollup_state),
+        )
+    }
+
+    /// Name of the rollup collection, for pipelines that read it through `$unionWith`
+    pub fn rollup_collection_name(&self) -> &str {
+        self.rollups.name()
+    }
+
+    async fn ensure_indexes(&self) -> Result<(), AppError> {
+        // Unique key required by $merge; date first so range reads use it too
+        let index = IndexModel::builder()
+            .keys(doc! { "date": 1, "vendingStation": 1, "community": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.rollups.create_index(index).await?;
+
+        // Lets the change scan find records updated since the last refresh without a full scan
+        let changes = IndexModel::builder()
+            .keys(doc! { "updatedAt": 1 })
+            .options(IndexOptions::builder().sparse(true).build())
+            .build();
+        self.records.create_index(changes).await?;
+        Ok(())
+    }
+
+    async fn earliest_record_day(&self) -> Result<Option<NaiveDate>, AppError> {
+        let earliest = self
+            .records
+            .find_one(doc! { "timestamp": { "$type": "date" } })
+            .sort(doc! { "timestamp": 1 })
+            .await?;
+
+        Ok(earliest
+            .and_then(|record| record.get_datetime("timestamp").ok().copied())
+            .map(|timestamp| timestamp.to_chrono().date_naive()))
+    }
+
+    // Days up to end_day with records written or updated at or after `since`. Records carry their
+    // change time in `updatedAt`; records without it fall back to the insert time of an ObjectId
+    // `_id`. String ids without `updatedAt` never match here and rely on days_out_of_date.
+    async fn days_changed_since(
+        &self,
+        since: mongodb::bson::DateTime,
+        end_day: NaiveDate,
+    ) -> Result<BTreeSet<NaiveDate>, AppError> {
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": { "$lt": start_of_day(end_day + Days::new(1)) },
+                    "$or": [
+                        { "updatedAt": { "$gte": since } },
+                        { "updatedAt": { "$exists": false }, "_id": { "$gte": object_id_at(since) } }
+                    ]
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": { "$dateToString": { "format": "%Y-%m-%d", "date": "$timestamp" } }
+                }
+            },
+        ];
+
+        let mut cursor = self.records.aggregate(pipeline).await?;
+
+        use futures_util::stream::StreamExt;
+        let mut days = BTreeSet::new();
+        while let Some(result) = cursor.next().await {
+            if let Some(day) = result?.get_str("_id").ok().and_then(parse_day) {
+                days.insert(day);
+            }
+        }
+        Ok(days)
+    }
+
+    // Totals per day from an aggregation whose rows are keyed by YYYY-MM-DD
+    async fn daily_totals(
+        collection: &Collection<Document>,
+        pipeline: Vec<Document>,
+    ) -> Result<HashMap<String, DayTotals>, AppError> {
+        let mut cursor = collection.aggregate(pipeline).await?;
+
+        use futures_util::stream::StreamExt;
+        let mut totals = HashMap::new();
+        while let Some(result) = cursor.next().await {
+            let row = result?;
+            if let Ok(date) = row.get_str("_id") {
+                totals.insert(
+                    date.to_string(),
+                    DayTotals {
+                        transactions: bson_number(row.get("transactions")),
+                        amount: bson_number(row.get("amount")),
+                        kwh: bson_number(row.get("kwh")),
+                    },
+                );
+            }
+        }
+        Ok(totals)
+    }
+
+    // Days in [start_day, end_day] whose raw totals differ from their rollups, which catches late
+    // vends and deletions that carry no change time
+    async fn days_out_of_date(
+        &self,
+        start_day: NaiveDate,
+        end_day: NaiveDate,
+    ) -> Result<BTreeSet<NaiveDate>, AppError> {
+        let raw = Self::daily_totals(
+            &self.records,
+            vec![
+                doc! {
+                    "$match": {
+                        "timestamp": {
+                            "$gte": start_of_day(start_day),
+                            "$lt": start_of_day(end_day + Days::new(1))
+                        }
+                    }
+                },
+                doc! {
+                    "$group": {
+                        "_id": { "$dateToString": { "format": "%Y-%m-%d", "date": "$timestamp" } },
+                        "transactions": { "$sum": 1_i64 },
+                        "amount": { "$sum": { "$ifNull": ["$amount", 0.0] } },
+                        "kwh": { "$sum": { "$ifNull": ["$kwh", 0.0] } }
+                    }
+                },
+            ],
+        )
+        .await?;
+        let rolled_up = Self::daily_totals(
+            &self.rollups,
+            vec![
+                doc! {
+                    "$match": {
+                        "date": { "$gte": format_day(start_day), "$lte": format_day(end_day) }
+                    }
+                },
+                doc! {
+                    "$group": {
+                        "_id": "$date",
+                        "transactions": { "$sum": "$totalTransactions" },
+                        "amount": { "$sum": "$totalAmount" },
+                        "kwh": { "$sum": "$totalKwh" }
+                    }
+                },
+            ],
+        )
+        .await?;
+
+        Ok(start_day
+            .iter_days()
+            .take_while(|day| *day <= end_day)
+            .filter(|day| {
+                let date = format_day(*day);
+                let raw = raw.get(&date).copied().unwrap_or_default();
+                let rolled_up = rolled_up.get(&date).copied().unwrap_or_default();
+                !raw.matches(&rolled_up)
+            })
+            .collect())
+    }
+
+    // Replace the rollups of [start_day, end_day] with freshly aggregated totals.
+    // Rows are replaced in place, so summaries keep reading complete days while this runs.
+    async fn rebuild_days(&self, start_day: NaiveDate, end_day: NaiveDate) -> Result<(), AppError> {
+        let start_str = start_day.format("%Y-%m-%d").to_string();
+        let end_str = end_day.format("%Y-%m-%d").to_string();
+        let refreshed_at = mongodb::bson::DateTime::now();
+
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": {
+                        "$gte": start_of_day(start_day),
+                        "$lt": start_of_day(end_day + Days::new(1))
+                    }
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": {
+                        "vendingStation": { "$ifNull": ["$vendingStation", "Unknown"] },
+                        "community": { "$ifNull": ["$community", "Unknown"] },
+                        "date": { "$dateToString": { "format": "%Y-%m-%d", "date": "$timestamp" } }
+                    },
+                    "totalTransactions": { "$sum": 1_i64 },
+                    "totalAmount": { "$sum": { "$ifNull": ["$amount", 0.0] } },
+                    "totalKwh": { "$sum": { "$ifNull": ["$kwh", 0.0] } }
+                }
+            },
+            doc! {
+                "$project": {
+                    "_id": 0,
+                    "vendingStation": "$_id.vendingStation",
+                    "community": "$_id.community",
+                    "date": "$_id.date",
+                    "totalTransactions": 1,
+                    "totalAmount": 1,
+                    "totalKwh": 1,
+                    "refreshedAt": refreshed_at
+                }
+            },
+            doc! {
+                "$merge": {
+                    "into": self.rollups.name(),
+                    "on": ["date", "vendingStation", "community"],
+                    "whenMatched": "replace",
+                    "whenNotMatched": "insert"
+                }
+            },
+        ];
+
+        self.records
+            .aggregate(pipeline)
+            .allow_disk_use(true)
+            .await?;
+
+        // Rows this rebuild did not write belong to combinations whose records are gone
+        self.rollups
+            .delete_many(doc! {
+                "date": { "$gte": &start_str, "$lte": &end_str },
+                "refreshedAt": { "$lt": refreshed_at }
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    // Rebuild a span of days in chunks, returning the number of rollup rows now covering it
+    async fn rebuild_span(
+        &self,
+        start_day: NaiveDate,
+        end_day: NaiveDate,
+    ) -> Result<u64, AppError> {
+        self.ensure_indexes().await?;
+
+        let mut chunk_start = start_day;
+        while chunk_start <= end_day {
+            let chunk_end = (chunk_start + Days::new(REFRESH_CHUNK_DAYS - 1)).min(end_day);
+            self.rebuild_days(chunk_start, chunk_end).await?;
+            chunk_start = chunk_end + Days::new(1);
+        }
+
+        let rollup_documents = self
+            .rollups
+            .count_documents(doc! {
+                "date": {
+                    "$gte": start_day.format("%Y-%m-%d").to_string(),
+                    "$lte": end_day.format("%Y-%m-%d").to_string()
+                }
+            })
+            .await?;
+
+        Ok(rollup_documents)
+    }
+
+    async fn save_watermark(
+        &self,
+        refreshed_through: NaiveDate,
+        changes_since: Option<mongodb::bson::DateTime>,
+    ) -> Result<(), AppError> {
+        let state = MongoRollupState {
+            id: ROLLUP_STATE_ID.to_string(),
+            refreshed_through: format_day(refreshed_through),
+            last_refresh_at: mongodb::bson::DateTime::now(),
+            changes_since,
+        };
+        self.state
+            .replace_one(doc! { "_id": ROLLUP_STATE_ID }, &state)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DailyRollupRepository for MongoDbDailyRollupRepository {
+    async fn get_rollup_status(&self) -> Result<RollupStatus, AppError> {
+        let state = self.state.find_one(doc! { "_id": ROLLUP_STATE_ID }).await?;
+        Ok(state.map(RollupStatus::from).unwrap_or_default())
+    }
+
+    async fn refresh_incremental(
+        &self,
+        today: NaiveDate,
+        lookback_days: u32,
+    ) -> Result<RollupRefresh, AppError> {
+        let status = self.get_rollup_status().await?;
+        let refreshed_through = status.refreshed_through.as_deref().and_then(parse_day);
+        // Today is never complete, so rollups stop at yesterday
+        let end_day = today - Days::new(1);
+        // Taken before reading records so anything written during this refresh is found next time
+        let changes_since = mongodb::bson::DateTime::from_millis(
+            mongodb::bson::DateTime::now().timestamp_millis() - CHANGE_WATERMARK_MARGIN_MS,
+        );
+
+        let mut days = BTreeSet::new();
+        let mut changed_days = BTreeSet::new();
+        match refreshed_through {
+            Some(refreshed_through) => {
+                // Days never rolled up
+                days.extend(
+                    (refreshed_through + Days::new(1))
+                        .iter_days()
+                        .take_while(|day| *day <= end_day),
+                );
+
+                // Rolled-up days that got new or changed records since the last refresh
+                let checked_through = refreshed_through.min(end_day);
+                if let Some(since) = status.changes_since {
+                    changed_days.extend(
+                        self.days_changed_since(
+                            mongodb::bson::DateTime::from_millis(since.timestamp_millis()),
+                            checked_through,
+                        )
+                        .await?,
+                    );
+                }
+                let lookback_start = today - Days::new(lookback_days as u64);
+                if lookback_start <= checked_through {
+                    changed_days.extend(
+                        self.days_out_of_date(lookback_start, checked_through)
+                            .await?,
+                    );
+                }
+                days.extend(changed_days.iter().copied());
+            }
+            None => {
+                if let Some(earliest) = self.earliest_record_day().await? {
+                    days.extend(earliest.iter_days().take_while(|day| *day <= end_day));
+                }
+            }
+        }
+
+        let (Some(&start_day), Some(&last_day)) = (days.first(), days.last()) else {
+            // Nothing to rebuild, but the change scan still covered everything up to now
+            if let Some(refreshed_through) = refreshed_through {
+                self.save_watermark(refreshed_through, Some(changes_since))
+                    .await?;
+            }
+            return Ok(RollupRefresh {
+                start_date: None,
+                end_date: None,
+                days_refreshed: 0,
+                changed_days: Vec::new(),
+                rollup_documents: 0,
+                status: self.get_rollup_status().await?,
+            });
+        };
+
+        let mut rollup_documents = 0;
+        for (span_start, span_end) in consecutive_spans(&days) {
+            rollup_documents += self.rebuild_span(span_start, span_end).await?;
+        }
+        self.save_watermark(
+            end_day.max(refreshed_through.unwrap_or(end_day)),
+            Some(changes_since),
+        )
+        .await?;
+
+        Ok(RollupRefresh {
+            start_date: Some(format_day(start_day)),
+            end_date: Some(format_day(last_day)),
+            days_refreshed: days.len() as u32,
+            changed_days: changed_days.into_iter().map(format_day).collect(),
+            rollup_documents,
+            status: self.get_rollup_status().await?,
+        })
+    }
+
+    async fn refresh_range(
+        &self,
+        start_day: NaiveDate,
+        end_day: NaiveDate,
+        today: NaiveDate,
+    ) -> Result<RollupRefresh, AppError> {
+        let end_day = end_day.min(today - Days::new(1));
+        let status = self.get_rollup_status().await?;
+
+        if start_day > end_day {
+            return Ok(RollupRefresh {
+                start_date: None,
+                end_date: None,
+                days_refreshed: 0,
+                changed_days: Vec::new(),
+                rollup_documents: 0,
+                status,
+            });
+        }
+
+        let rollup_documents = self.rebuild_span(start_day, end_day).await?;
+
+        // Only move the watermark when the rebuilt span leaves no gap behind it
+        let refreshed_through = status.refreshed_through.as_deref().and_then(parse_day);
+        if let Some(refreshed_through) = refreshed_through
+            && start_day <= refreshed_through + Days::new(1)
+            && end_day > refreshed_through
+        {
+            self.save_watermark(
+                end_day,
+                status
+                    .changes_since
+                    .map(|since| mongodb::bson::DateTime::from_millis(since.timestamp_millis())),
+            )
+            .await?;
+        }
+
+        Ok(RollupRefresh {
+            start_date: Some(start_day.format("%Y-%m-%d").to_string()),
+            end_date: Some(end_day.format("%Y-%m-%d").to_string()),
+            days_refreshed: ((end_day - start_day).num_days() + 1) as u32,
+            changed_days: Vec::new(),
+            rollup_documents,
+            status: self.get_rollup_status().await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn consecutive_spans_split_on_gaps() {
+        assert!(consecutive_spans(&BTreeSet::new()).is_empty());
+
+        let days: BTreeSet<NaiveDate> = [
+            "2025-01-30",
+            "2025-01-31",
+            "2025-02-01",
+            "2025-02-03",
+            "2025-02-05",
+            "2025-02-06",
+        ]
+        .into_iter()
+        .map(day)
+        .collect();
+        assert_eq!(
+            consecutive_spans(&days),
+            vec![
+                (day("2025-01-30"), day("2025-02-01")),
+                (day("2025-02-03"), day("2025-02-03")),
+                (day("2025-02-05"), day("2025-02-06")),
+            ]
+        );
+    }
+
+    #[test]
+    fn day_totals_match_within_tolerance() {
+        let raw = DayTotals {
+            transactions: 3.0,
+            amount: 150.25,
+            kwh: 61.5,
+        };
+
+        assert!(raw.matches(&raw));
+        // Summing in a different order can move the last bits of a double
+        assert!(raw.matches(&DayTotals {
+            amount: 150.25 + 1e-12,
+            ..raw
+        }));
+        assert!(!raw.matches(&DayTotals {
+            transactions: 4.0,
+            ..raw
+        }));
+        assert!(!raw.matches(&DayTotals {
+            amount: 150.26,
+            ..raw
+        }));
+        assert!(!raw.matches(&DayTotals { kwh: 61.0, ..raw }));
+        // Tiny totals are compared absolutely rather than relative to zero
+        assert!(DayTotals::default().matches(&DayTotals {
+            kwh: 1e-10,
+            ..DayTotals::default()
+        }));
+    }
+
+    #[test]
+    fn object_id_at_orders_by_insert_second() {
+        let time = mongodb::bson::DateTime::from_millis(1_736_000_000_500);
+        let id = object_id_at(time);
+
+        assert_eq!(id.timestamp().timestamp_millis(), 1_736_000_000_000);
+        // Ids from later seconds always sort after it
+        assert!(id < object_id_at(mongodb::bson::DateTime::from_millis(1_736_000_001_000)));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::error::AppError;
+use crate::model::StationOpeningHours;
+use crate::repositories::StationHoursRepository;
+use async_trait::async_trait;
+use mongodb::{Collection, bson::doc};
+use serde::{Deserialize, Serialize};
+
+// Internal struct for MongoDB operations
+#[derive(Debug, Serialize, Deserialize)]
+struct MongoStationOpeningHours {
+    #[serde(rename = "_id")]
+    pub vending_station: String,
+    #[serde(rename = "opensAt")]
+    pub opens_at: String,
+    #[serde(rename = "closesAt")]
+    pub closes_at: String,
+    pub timezone: String,
+}
+
+impl From<MongoStationOpeningHours> for StationOpeningHours {
+    fn from(mongo_hours: MongoStationOpeningHours) -> Self {
+        StationOpeningHours {
+            vending_station: mongo_hours.vending_station,
+            opens_at: mongo_hours.opens_at,
+            closes_at: mongo_hours.closes_at,
+            timezone: mongo_hours.timezone,
+        }
+    }
+}
+
+impl From<StationOpeningHours> for MongoStationOpeningHours {
+    fn from(hours: StationOpeningHours) -> Self {
+        MongoStationOpeningHours {
+            vending_station: hours.vending_station,
+            opens_at: hours.opens_at,
+            closes_at: hours.closes_at,
+            timezone: hours.timezone,
+        }
+    }
+}
+
+pub struct MongoDbStationHoursRepository {
+    collection: Collection<MongoStationOpeningHours>,
+}
+
+impl MongoDbStationHoursRepository {
+    pub fn from_collection(collection: Collection<StationOpeningHours>) -> Self {
+        Self {
+            collection: collection.clone_with_type::<MongoStationOpeningHours>(),
+        }
+    }
+}
+
+#[async_trait]
+impl StationHoursRepository for MongoDbStationHoursRepository {
+    async fn get_opening_hours(&self) -> Result<Vec<StationOpeningHours>, AppError> {
+        let mut cursor = self
+            .collection
+            .find(doc! {})
+            .sort(doc! { "_id": 1 })
+            .await?;
+        let mut hours = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            hours.push(StationOpeningHours::from(result?));
+        }
+
+        Ok(hours)
+    }
+
+    async fn save_opening_hours(
+        &self,
+        opening_hours: StationOpeningHours,
+    ) -> Result<StationOpeningHours, AppError> {
+        let mongo_hours = MongoStationOpeningHours::from(opening_hours);
+        self.collection
+            .replace_one(doc! { "_id": &mongo_hours.vending_station }, &mongo_hours)
+            .upsert(true)
+            .await?;
+        Ok(StationOpeningHours::from(mongo_hours))
+    }
+}
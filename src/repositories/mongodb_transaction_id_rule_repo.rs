@@ -0,0 +1,72 @@
+use crate::error::AppError;
+use crate::model::TransactionIdRule;
+use crate::repositories::TransactionIdRuleRepository;
+use async_trait::async_trait;
+use mongodb::{Collection, bson::doc};
+use serde::{Deserialize, Serialize};
+
+// Internal struct for MongoDB operations
+#[derive(Debug, Serialize, Deserialize)]
+struct MongoTransactionIdRule {
+    #[serde(rename = "_id")]
+    pub vending_station: String,
+    pub pattern: String,
+}
+
+impl From<MongoTransactionIdRule> for TransactionIdRule {
+    fn from(mongo_rule: MongoTransactionIdRule) -> Self {
+        TransactionIdRule {
+            vending_station: mongo_rule.vending_station,
+            pattern: mongo_rule.pattern,
+        }
+    }
+}
+
+impl From<TransactionIdRule> for MongoTransactionIdRule {
+    fn from(rule: TransactionIdRule) -> Self {
+        MongoTransactionIdRule {
+            vending_station: rule.vending_station,
+            pattern: rule.pattern,
+        }
+    }
+}
+
+pub struct MongoDbTransactionIdRuleRepository {
+    collection: Collection<MongoTransactionIdRule>,
+}
+
+impl MongoDbTransactionIdRuleRepository {
+    pub fn from_collection(collection: Collection<TransactionIdRule>) -> Self {
+        Self {
+            collection: collection.clone_with_type::<MongoTransactionIdRule>(),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionIdRuleRepository for MongoDbTransactionIdRuleRepository {
+    async fn get_rules(&self) -> Result<Vec<TransactionIdRule>, AppError> {
+        let mut cursor = self
+            .collection
+            .find(doc! {})
+            .sort(doc! { "_id": 1 })
+            .await?;
+        let mut rules = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            rules.push(TransactionIdRule::from(result?));
+        }
+
+        Ok(rules)
+    }
+
+    async fn save_rule(&self, rule: TransactionIdRule) -> Result<TransactionIdRule, AppError> {
+        let mongo_rule = MongoTransactionIdRule::from(rule);
+        self.collection
+            .replace_one(doc! { "_id": &mongo_rule.vending_station }, &mongo_rule)
+            .upsert(true)
+            .await?;
+        Ok(TransactionIdRule::from(mongo_rule))
+    }
+}
@@ -1,34 +1,53 @@
-use crate::model::{DailySummary, VendingRecord, VendingStationSummary, VendingSummary};
-use crate::repositories::VendingRecordRepository;
+use crate::analytics::TARIFF_TOLERANCE;
+use crate::error::AppError;
+use crate::model::{
+    AccessScope, CreditRunout, DailySummary, DataQualityIssue, DataQualityProblem,
+    DataQualityReport, DecodeReport, DistributionStats, DuplicateGroup, DuplicateRecord,
+    GroupStatistics, HistogramBucket, InactiveMeter, IntegrityReport, KpiReport, KpiSet,
+    Leaderboard, LeaderboardDimension, LeaderboardEntry, LeaderboardMetric, MeterAmountBaseline,
+    MeterBehaviour, MetricDistribution, OperatorShift, SummarySeriesOptions,
+    TransactionSequenceEntry, VendingHeatmap, VendingRecord, VendingRecordBatch,
+    VendingStationSummary, VendingStatistics, VendingSummary,
+};
+use crate::repositories::{
+    DailyRollupRepository, MongoDbDailyRollupRepository, VendingRecordRepository,
+};
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use mongodb::{Collection, bson::doc};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use mongodb::{
+    Collection,
+    bson::{Bson, Document, doc},
+};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::sync::Arc;
 
 // Internal struct for MongoDB operations with BSON DateTime
+// Legacy documents may lack any of the descriptive fields, so only the timestamp is required
 #[derive(Debug, Serialize, Deserialize)]
 struct MongoVendingRecord {
     #[serde(rename = "_id")]
-    pub id: String,
+    pub id: Bson,
     pub timestamp: mongodb::bson::DateTime,
-    #[serde(rename = "meterNumber")]
-    pub meter_number: String,
-    pub address: String,
-    pub community: String,
-    #[serde(rename = "customerName")]
-    pub customer_name: String,
-    pub token: String,
+    #[serde(rename = "meterNumber", default)]
+    pub meter_number: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub community: Option<String>,
+    #[serde(rename = "customerName", default)]
+    pub customer_name: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
     #[serde(default)]
     pub tariff: Option<f64>,
     #[serde(default)]
     pub amount: Option<f64>,
     #[serde(default)]
     pub kwh: Option<f64>,
-    #[serde(rename = "userId")]
-    pub user_id: String,
-    #[serde(rename = "vendingStation")]
-    pub vending_station: String,
+    #[serde(rename = "userId", default)]
+    pub user_id: Option<String>,
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: Option<String>,
     #[serde(rename = "fixedCharge", default)]
     pub fixed_charge: Option<f64>,
     #[serde(rename = "transactionId", default)]
@@ -40,18 +59,18 @@ struct MongoVendingRecord {
 impl From<MongoVendingRecord> for VendingRecord {
     fn from(mongo_record: MongoVendingRecord) -> Self {
         VendingRecord {
-            id: mongo_record.id,
+            id: document_id(&mongo_record.id),
             timestamp: mongo_record.timestamp.to_chrono(),
-            meter_number: Some(mongo_record.meter_number),
-            address: Some(mongo_record.address),
-            community: Some(mongo_record.community),
-            customer_name: Some(mongo_record.customer_name),
-            token: Some(mongo_record.token),
+            meter_number: mongo_record.meter_number,
+            address: mongo_record.address,
+            community: mongo_record.community,
+            customer_name: mongo_record.customer_name,
+            token: mongo_record.token,
             tariff: mongo_record.tariff,
             amount: mongo_record.amount,
             kwh: mongo_record.kwh,
-            user_id: Some(mongo_record.user_id),
-            vending_station: Some(mongo_record.vending_station),
+            user_id: mongo_record.user_id,
+            vending_station: mongo_record.vending_station,
             fixed_charge: mongo_record.fixed_charge,
             transaction_id: mongo_record.transaction_id,
             remaining_credit: mongo_record.remaining_credit,
@@ -59,8 +78,456 @@ impl From<MongoVendingRecord> for VendingRecord {
     }
 }
 
+// Record ids are strings in current data but ObjectIds in some imports
+fn document_id(id: &Bson) -> String {
+    match id {
+        Bson::String(id) => id.clone(),
+        Bson::ObjectId(id) => id.to_hex(),
+        other => other.to_string(),
+    }
+}
+
+// Keep whatever fields of a malformed record still have the expected type. Without a usable
+// timestamp the record cannot be placed in any report, so it is skipped.
+fn salvage_record(document: &Document) -> Option<VendingRecord> {
+    let timestamp = document.get_datetime("timestamp").ok()?.to_chrono();
+    let text = |key: &str| document.get_str(key).ok().map(str::to_string);
+    let number = |key: &str| match document.get(key) {
+        Some(Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_)) => {
+            Some(bson_number(document.get(key)))
+        }
+        _ => None,
+    };
+
+    Some(VendingRecord {
+        id: document.get("_id").map(document_id).unwrap_or_default(),
+        timestamp,
+        meter_number: text("meterNumber"),
+        address: text("address"),
+        community: text("community"),
+        customer_name: text("customerName"),
+        token: text("token"),
+        tariff: number("tariff"),
+        amount: number("amount"),
+        kwh: number("kwh"),
+        user_id: text("userId"),
+        vending_station: text("vendingStation"),
+        fixed_charge: number("fixedCharge"),
+        transaction_id: text("transactionId"),
+        remaining_credit: number("remainingCredit"),
+    })
+}
+
+// Malformed documents are counted in full, but only this many ids are listed
+const MAX_REPORTED_IDS: usize = 100;
+
+// Decode one raw document, falling back to salvage_record and noting the outcome in the report
+fn decode_document(
+    document: Document,
+    records: &mut Vec<VendingRecord>,
+    decode_report: &mut DecodeReport,
+) {
+    match mongodb::bson::from_document::<MongoVendingRecord>(document.clone()) {
+        Ok(mongo_record) => records.push(VendingRecord::from(mongo_record)),
+        Err(e) => {
+            let id = document.get("_id").map(document_id).unwrap_or_default();
+            eprintln!("Malformed vending record {}: {}", id, e);
+            match salvage_record(&document) {
+                Some(record) => {
+                    records.push(record);
+                    decode_report.partially_decoded += 1;
+                    if decode_report.partially_decoded_ids.len() < MAX_REPORTED_IDS {
+                        decode_report.partially_decoded_ids.push(id);
+                    }
+                }
+                None => {
+                    decode_report.skipped_documents += 1;
+                    if decode_report.skipped_ids.len() < MAX_REPORTED_IDS {
+                        decode_report.skipped_ids.push(id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Per-meter purchase history produced by the inactive meter aggregation
+#[derive(Debug, Deserialize)]
+struct MongoMeterActivity {
+    #[serde(rename = "_id")]
+    pub meter_number: String,
+    #[serde(rename = "firstPurchase")]
+    pub first_purchase: mongodb::bson::DateTime,
+    #[serde(rename = "lastPurchase")]
+    pub last_purchase: mongodb::bson::DateTime,
+    #[serde(rename = "purchaseCount")]
+    pub purchase_count: i64,
+    #[serde(rename = "customerName", default)]
+    pub customer_name: Option<String>,
+    #[serde(default)]
+    pub community: Option<String>,
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: Option<String>,
+}
+
+// Per-meter purchase pattern produced by the meter behaviour aggregation
+#[derive(Debug, Deserialize)]
+struct MongoMeterBehaviour {
+    #[serde(rename = "_id")]
+    pub meter_number: String,
+    #[serde(rename = "purchaseCount")]
+    pub purchase_count: i64,
+    #[serde(rename = "averageTicket", default)]
+    pub average_ticket: Option<f64>,
+    #[serde(rename = "averageInterval", default)]
+    pub average_interval: Option<f64>,
+    #[serde(rename = "intervalStdDev", default)]
+    pub interval_std_dev: Option<f64>,
+    #[serde(rename = "recentTransactions")]
+    pub recent_transactions: i64,
+    #[serde(rename = "recentAmount")]
+    pub recent_amount: f64,
+    #[serde(rename = "priorTransactions")]
+    pub prior_transactions: i64,
+    #[serde(rename = "priorAmount")]
+    pub prior_amount: f64,
+    #[serde(rename = "customerName", default)]
+    pub customer_name: Option<String>,
+    #[serde(default)]
+    pub community: Option<String>,
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: Option<String>,
+}
+
+impl From<MongoMeterBehaviour> for MeterBehaviour {
+    fn from(mongo_behaviour: MongoMeterBehaviour) -> Self {
+        // Intervals need at least two gaps before their spread means anything
+        let regularity_cv = match (
+            mongo_behaviour.average_interval,
+            mongo_behaviour.interval_std_dev,
+        ) {
+            (Some(mean), Some(std_dev)) if mean > 0.0 && mongo_behaviour.purchase_count > 2 => {
+                Some(std_dev / mean)
+            }
+            _ => None,
+        };
+        let amount_trend_percent = if mongo_behaviour.prior_amount > 0.0 {
+            Some(
+                (mongo_behaviour.recent_amount - mongo_behaviour.prior_amount)
+                    / mongo_behaviour.prior_amount
+                    * 100.0,
+            )
+        } else {
+            None
+        };
+
+        MeterBehaviour {
+            meter_number: mongo_behaviour.meter_number,
+            customer_name: mongo_behaviour.customer_name,
+            community: mongo_behaviour.community,
+            vending_station: mongo_behaviour.vending_station,
+            purchase_count: mongo_behaviour.purchase_count as u32,
+            average_ticket: mongo_behaviour.average_ticket.unwrap_or(0.0),
+            average_days_between_purchases: mongo_behaviour.average_interval,
+            interval_std_dev_days: mongo_behaviour.interval_std_dev,
+            regularity_cv,
+            recent_transactions: mongo_behaviour.recent_transactions as u32,
+            recent_amount: mongo_behaviour.recent_amount,
+            prior_transactions: mongo_behaviour.prior_transactions as u32,
+            prior_amount: mongo_behaviour.prior_amount,
+            amount_trend_percent,
+        }
+    }
+}
+
+// Per-meter consumption history produced by the credit run-out aggregation
+#[derive(Debug, Deserialize)]
+struct MongoMeterConsumption {
+    #[serde(rename = "_id")]
+    pub meter_number: String,
+    #[serde(rename = "observedDays", default)]
+    pub observed_days: f64,
+    #[serde(rename = "consumedKwh", default)]
+    pub consumed_kwh: f64,
+    #[serde(rename = "lastPurchase")]
+    pub last_purchase: mongodb::bson::DateTime,
+    #[serde(rename = "lastKwh", default)]
+    pub last_kwh: Option<f64>,
+    #[serde(rename = "lastRemainingCredit", default)]
+    pub last_remaining_credit: Option<f64>,
+    #[serde(rename = "customerName", default)]
+    pub customer_name: Option<String>,
+    #[serde(default)]
+    pub community: Option<String>,
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: Option<String>,
+}
+
+impl MongoMeterConsumption {
+    // remaining_credit is the balance left on the meter when the token was bought,
+    // so the credit after the last vend is that balance plus the kWh just bought
+    fn into_runout(self, as_of: DateTime<Utc>) -> Option<CreditRunout> {
+        if self.observed_days <= 0.0 || self.consumed_kwh <= 0.0 {
+            return None;
+        }
+        let daily_consumption_kwh = self.consumed_kwh / self.observed_days;
+        let last_purchase = self.last_purchase.to_chrono();
+        let last_kwh = self.last_kwh.unwrap_or(0.0);
+        let available_kwh = self.last_remaining_credit.unwrap_or(0.0).max(0.0) + last_kwh;
+        let days_of_credit = available_kwh / daily_consumption_kwh;
+        let estimated_runout =
+            last_purchase + chrono::Duration::seconds((days_of_credit * 86_400.0) as i64);
+
+        Some(CreditRunout {
+            meter_number: self.meter_number,
+            customer_name: self.customer_name,
+            community: self.community,
+            vending_station: self.vending_station,
+            last_purchase,
+            last_kwh,
+            remaining_credit_at_purchase: self.last_remaining_credit,
+            daily_consumption_kwh,
+            estimated_runout,
+            days_until_runout: (estimated_runout - as_of).num_seconds() as f64 / 86_400.0,
+        })
+    }
+}
+
+// Per-operator daily totals produced by the shift reconciliation aggregation
+#[derive(Debug, Deserialize)]
+struct MongoOperatorShift {
+    #[serde(rename = "_id")]
+    pub key: MongoOperatorShiftKey,
+    #[serde(rename = "vendingStations", default)]
+    pub vending_stations: Vec<String>,
+    #[serde(rename = "transactionCount")]
+    pub transaction_count: i64,
+    #[serde(rename = "totalAmount")]
+    pub total_amount: f64,
+    #[serde(rename = "firstVend")]
+    pub first_vend: mongodb::bson::DateTime,
+    #[serde(rename = "lastVend")]
+    pub last_vend: mongodb::bson::DateTime,
+    #[serde(rename = "voidedCount")]
+    pub voided_count: i64,
+    #[serde(rename = "voidedAmount")]
+    pub voided_amount: f64,
+    #[serde(rename = "expectedCash")]
+    pub expected_cash: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MongoOperatorShiftKey {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub date: String,
+}
+
+impl From<MongoOperatorShift> for OperatorShift {
+    fn from(mongo_shift: MongoOperatorShift) -> Self {
+        OperatorShift {
+            user_id: mongo_shift.key.user_id,
+            date: mongo_shift.key.date,
+            vending_stations: mongo_shift.vending_stations,
+            transaction_count: mongo_shift.transaction_count as u32,
+            total_amount: mongo_shift.total_amount,
+            first_vend: mongo_shift.first_vend.to_chrono(),
+            last_vend: mongo_shift.last_vend.to_chrono(),
+            voided_count: mongo_shift.voided_count as u32,
+            voided_amount: mongo_shift.voided_amount,
+            expected_cash: mongo_shift.expected_cash,
+            counted_cash: None,
+            variance: None,
+        }
+    }
+}
+
+// Per-meter amount statistics produced by the baseline aggregation
+#[derive(Debug, Deserialize)]
+struct MongoMeterAmountBaseline {
+    #[serde(rename = "_id")]
+    pub meter_number: String,
+    #[serde(rename = "purchaseCount")]
+    pub purchase_count: i64,
+    #[serde(rename = "meanAmount")]
+    pub mean_amount: f64,
+    #[serde(rename = "stdDevAmount", default)]
+    pub std_dev_amount: Option<f64>,
+}
+
+impl From<MongoMeterAmountBaseline> for MeterAmountBaseline {
+    fn from(mongo_baseline: MongoMeterAmountBaseline) -> Self {
+        MeterAmountBaseline {
+            meter_number: mongo_baseline.meter_number,
+            purchase_count: mongo_baseline.purchase_count as u32,
+            mean_amount: mongo_baseline.mean_amount,
+            std_dev_amount: mongo_baseline.std_dev_amount.unwrap_or(0.0),
+        }
+    }
+}
+
+// Records of one duplicate group as pushed by the integrity aggregation
+#[derive(Debug, Deserialize)]
+struct MongoDuplicateRecord {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub timestamp: mongodb::bson::DateTime,
+    #[serde(rename = "meterNumber", default)]
+    pub meter_number: Option<String>,
+    #[serde(rename = "vendingStation", default)]
+    pub vending_station: Option<String>,
+    #[serde(rename = "userId", default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub amount: Option<f64>,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(rename = "transactionId", default)]
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MongoDuplicateGroup {
+    pub key: String,
+    pub count: i64,
+    pub records: Vec<MongoDuplicateRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MongoIntegrityFacets {
+    #[serde(rename = "duplicateTokens", default)]
+    pub duplicate_tokens: Vec<MongoDuplicateGroup>,
+    #[serde(rename = "duplicateTransactionIds", default)]
+    pub duplicate_transaction_ids: Vec<MongoDuplicateGroup>,
+    #[serde(rename = "nearDuplicates", default)]
+    pub near_duplicates: Vec<MongoDuplicateGroup>,
+}
+
+// Transaction ID projection used for sequence gap analysis
+#[derive(Debug, Deserialize)]
+struct MongoTransactionSequenceEntry {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "vendingStation")]
+    pub vending_station: String,
+    #[serde(rename = "transactionId")]
+    pub transaction_id: String,
+    pub timestamp: mongodb::bson::DateTime,
+}
+
+impl From<MongoTransactionSequenceEntry> for TransactionSequenceEntry {
+    fn from(mongo_entry: MongoTransactionSequenceEntry) -> Self {
+        TransactionSequenceEntry {
+            record_id: mongo_entry.id,
+            vending_station: mongo_entry.vending_station,
+            transaction_id: mongo_entry.transaction_id,
+            timestamp: DateTime::from_timestamp_millis(mongo_entry.timestamp.timestamp_millis())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// One day-of-week/hour cell of the heatmap aggregation
+#[derive(Debug, Deserialize)]
+struct MongoHeatmapCell {
+    #[serde(rename = "_id")]
+    pub key: MongoHeatmapKey,
+    #[serde(rename = "transactionCount")]
+    pub transaction_count: i64,
+    #[serde(rename = "totalAmount")]
+    pub total_amount: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MongoHeatmapKey {
+    pub day: i32, // ISO day of week, 1 = Monday
+    pub hour: i32,
+}
+
+const HEATMAP_DAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+impl From<MongoDuplicateGroup> for DuplicateGroup {
+    fn from(mongo_group: MongoDuplicateGroup) -> Self {
+        DuplicateGroup {
+            key: mongo_group.key,
+            count: mongo_group.count as u32,
+            records: mongo_group
+                .records
+                .into_iter()
+                .map(|record| DuplicateRecord {
+                    id: record.id,
+                    timestamp: record.timestamp.to_chrono(),
+                    meter_number: record.meter_number,
+                    vending_station: record.vending_station,
+                    user_id: record.user_id,
+                    amount: record.amount,
+                    token: record.token,
+                    transaction_id: record.transaction_id,
+                })
+                .collect(),
+        }
+    }
+}
+
+// Expressions reading a field of a legacy document as null when it holds the wrong type, so one
+// bad document cannot fail the deserialization of a whole report
+fn number_or_null(field: &str) -> Document {
+    let field_path = format!("${}", field);
+    doc! { "$cond": [{ "$isNumber": &field_path }, &field_path, null] }
+}
+
+fn string_or_null(field: &str) -> Document {
+    let field_path = format!("${}", field);
+    doc! { "$cond": [{ "$eq": [{ "$type": &field_path }, "string"] }, &field_path, null] }
+}
+
+// Fields kept for every record listed in a duplicate group
+// Record ids are pushed as strings, since some imports use ObjectIds
+fn duplicate_record_fields() -> Document {
+    doc! {
+        "_id": { "$toString": "$_id" },
+        "timestamp": "$timestamp",
+        "meterNumber": string_or_null("meterNumber"),
+        "vendingStation": string_or_null("vendingStation"),
+        "userId": string_or_null("userId"),
+        "amount": number_or_null("amount"),
+        "token": string_or_null("token"),
+        "transactionId": string_or_null("transactionId")
+    }
+}
+
+// Facet stages grouping records that share the value of a string field
+fn exact_duplicate_stages(field: &str) -> Vec<Document> {
+    let field_path = format!("${}", field);
+    vec![
+        doc! { "$match": { field: { "$type": "string", "$ne": "" } } },
+        doc! { "$sort": { "timestamp": 1 } },
+        doc! {
+            "$group": {
+                "_id": &field_path,
+                "count": { "$sum": 1_i64 },
+                "records": { "$push": duplicate_record_fields() }
+            }
+        },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+        doc! { "$project": { "_id": 0, "key": "$_id", "count": 1, "records": 1 } },
+        doc! { "$sort": { "count": -1, "key": 1 } },
+    ]
+}
+
+#[derive(Clone)]
 pub struct MongoDbVendingRecordRepository {
     collection: Collection<MongoVendingRecord>,
+    daily_rollups: Option<MongoDbDailyRollupRepository>,
+    scope: AccessScope,
 }
 
 impl MongoDbVendingRecordRepository {
@@ -69,68 +536,537 @@ impl MongoDbVendingRecordRepository {
         let mongo_collection = collection.clone_with_type::<MongoVendingRecord>();
         Self {
             collection: mongo_collection,
+            daily_rollups: None,
+            scope: AccessScope::default(),
+        }
+    }
+
+    /// Serve complete past days of summaries from the materialized daily rollups
+    pub fn with_daily_rollups(mut self, daily_rollups: MongoDbDailyRollupRepository) -> Self {
+        self.daily_rollups = Some(daily_rollups);
+        self
+    }
+
+    // Restriction on vendingStation/community, shared by raw records and rollups
+    fn scope_filter(&self) -> Option<Document> {
+        if self.scope.is_unrestricted() {
+            return None;
+        }
+        let mut filter = Document::new();
+        if let Some(stations) = &self.scope.vending_stations {
+            filter.insert("vendingStation", doc! { "$in": stations });
+        }
+        if let Some(communities) = &self.scope.communities {
+            filter.insert("community", doc! { "$in": communities });
+        }
+        Some(filter)
+    }
+
+    // Every pipeline starts from the caller's stations and communities only
+    fn restrict(&self, mut pipeline: Vec<Document>) -> Vec<Document> {
+        if let Some(filter) = self.scope_filter() {
+            pipeline.insert(0, doc! { "$match": filter });
+        }
+        pipeline
+    }
+
+    // Complete days inside [start_date, end_date] that the rollups already cover
+    async fn rollup_window(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Option<(NaiveDate, NaiveDate)>, AppError> {
+        let Some(daily_rollups) = &self.daily_rollups else {
+            return Ok(None);
+        };
+        let Some(refreshed_through) = daily_rollups
+            .get_rollup_status()
+            .await?
+            .refreshed_through
+            .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+        else {
+            return Ok(None);
+        };
+
+        Ok(full_day_window(
+            start_date,
+            end_date,
+            refreshed_through,
+            Utc::now().date_naive(),
+        ))
+    }
+}
+
+// Complete days inside [start_date, end_date] that are no later than the rollup watermark and
+// finished before today
+fn full_day_window(
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    refreshed_through: NaiveDate,
+    today: NaiveDate,
+) -> Option<(NaiveDate, NaiveDate)> {
+    // A partial first or last day has to come from the raw records
+    let first_full_day = if start_date == start_of_day(start_date.date_naive()) {
+        start_date.date_naive()
+    } else {
+        start_date.date_naive() + Days::new(1)
+    };
+    let last_full_day = if end_date + chrono::Duration::milliseconds(1)
+        >= start_of_day(end_date.date_naive() + Days::new(1))
+    {
+        end_date.date_naive()
+    } else {
+        end_date.date_naive() - Days::new(1)
+    };
+    let last_rollup_day = last_full_day
+        .min(refreshed_through)
+        .min(today - Days::new(1));
+
+    if first_full_day > last_rollup_day {
+        return None;
+    }
+    Some((first_full_day, last_rollup_day))
+}
+
+fn start_of_day(day: NaiveDate) -> DateTime<Utc> {
+    DateTime::from_naive_utc_and_offset(
+        day.and_hms_opt(0, 0, 0).expect("midnight is a valid time"),
+        Utc,
+    )
+}
+
+// Read a numeric aggregation output regardless of whether Mongo produced an int or a double
+fn bson_number(value: Option<&Bson>) -> f64 {
+    match value {
+        Some(Bson::Double(v)) => *v,
+        Some(Bson::Int32(v)) => *v as f64,
+        Some(Bson::Int64(v)) => *v as f64,
+        _ => 0.0,
+    }
+}
+
+// Missing fields stay None, unlike bson_number which reads them as zero
+fn optional_number(value: Option<&Bson>) -> Option<f64> {
+    value.map(|value| bson_number(Some(value)))
+}
+
+// Window stages over the per-station daily totals. Moving averages cover calendar days, so days
+// without vends count as zero, and are shortened at the start of the period.
+fn summary_series_stages(start_date: DateTime<Utc>, series: SummarySeriesOptions) -> Vec<Document> {
+    let period_start = mongodb::bson::DateTime::from_millis(
+        start_of_day(start_date.date_naive()).timestamp_millis(),
+    );
+
+    let mut window_output = Document::new();
+    let mut averages = Document::new();
+    if series.cumulative {
+        for (output, field) in [
+            ("cumulativeAmount", "$dailyAmount"),
+            ("cumulativeKwh", "$dailyKwh"),
+        ] {
+            window_output.insert(
+                output,
+                doc! { "$sum": field, "window": { "documents": ["unbounded", "current"] } },
+            );
+        }
+    }
+    if series.moving_averages {
+        for (prefix, field) in [("amount", "$dailyAmount"), ("kwh", "$dailyKwh")] {
+            for days in [7_i64, 30] {
+                let window_sum = format!("{}Sum{}d", prefix, days);
+                window_output.insert(
+                    &window_sum,
+                    doc! { "$sum": field, "window": { "range": [1 - days, 0], "unit": "day" } },
+                );
+                averages.insert(
+                    format!("{}Avg{}d", prefix, days),
+                    doc! {
+                        "$divide": [format!("${}", window_sum), { "$min": [days, "$elapsedDays"] }]
+                    },
+                );
+            }
+        }
+    }
+
+    let mut stages = vec![
+        doc! {
+            "$addFields": {
+                "day": { "$dateFromString": { "dateString": "$_id.date", "format": "%Y-%m-%d" } }
+            }
+        },
+        doc! {
+            "$setWindowFields": {
+                "partitionBy": "$_id.vendingStation",
+                "sortBy": { "day": 1 },
+                "output": window_output
+            }
+        },
+    ];
+    if series.moving_averages {
+        stages.push(doc! {
+            "$addFields": {
+                "elapsedDays": {
+                    "$add": [
+                        { "$dateDiff": { "startDate": period_start, "endDate": "$day", "unit": "day" } },
+                        1
+                    ]
+                }
+            }
+        });
+        stages.push(doc! { "$addFields": averages });
+    }
+    stages
+}
+
+// Fields every vending record should carry, with the BSON type expected for each
+const REQUIRED_STRING_FIELDS: [&str; 7] = [
+    "meterNumber",
+    "address",
+    "community",
+    "customerName",
+    "token",
+    "userId",
+    "vendingStation",
+];
+const REQUIRED_NUMBER_FIELDS: [&str; 3] = ["amount", "kwh", "tariff"];
+const OPTIONAL_NUMBER_FIELDS: [&str; 2] = ["fixedCharge", "remainingCredit"];
+
+// One data quality check: which documents it matches, as an aggregation expression
+struct DataQualityCheck {
+    problem: DataQualityProblem,
+    field: Option<&'static str>,
+    condition: Document,
+}
+
+fn data_quality_checks(
+    start_bson: mongodb::bson::DateTime,
+    end_bson: mongodb::bson::DateTime,
+    now_bson: mongodb::bson::DateTime,
+) -> Vec<DataQualityCheck> {
+    let in_range = doc! {
+        "$and": [
+            { "$eq": [{ "$type": "$timestamp" }, "date"] },
+            { "$gte": ["$timestamp", start_bson] },
+            { "$lte": ["$timestamp", end_bson] }
+        ]
+    };
+    let within_range = |condition: Document| doc! { "$and": [in_range.clone(), condition] };
+    let missing =
+        |field: &str| doc! { "$in": [{ "$type": format!("${}", field) }, ["missing", "null"]] };
+    let not_of_type = |field: &str, types: Vec<&str>| {
+        let field_type = doc! { "$type": format!("${}", field) };
+        doc! {
+            "$and": [
+                { "$not": [{ "$in": [field_type.clone(), ["missing", "null"]] }] },
+                { "$not": [{ "$in": [field_type, types] }] }
+            ]
+        }
+    };
+    let number_types = || vec!["double", "int", "long", "decimal"];
+
+    let mut checks = Vec::new();
+    // Without a date timestamp a document can't be placed in any range, so these are checked everywhere
+    checks.push(DataQualityCheck {
+        problem: DataQualityProblem::MissingField,
+        field: Some("timestamp"),
+        condition: missing("timestamp"),
+    });
+    checks.push(DataQualityCheck {
+        problem: DataQualityProblem::WrongType,
+        field: Some("timestamp"),
+        condition: not_of_type("timestamp", vec!["date"]),
+    });
+    checks.push(DataQualityCheck {
+        problem: DataQualityProblem::FutureTimestamp,
+        field: None,
+        condition: doc! {
+            "$and": [
+                { "$eq": [{ "$type": "$timestamp" }, "date"] },
+                { "$gt": ["$timestamp", now_bson] }
+            ]
+        },
+    });
+    for field in REQUIRED_STRING_FIELDS
+        .iter()
+        .chain(REQUIRED_NUMBER_FIELDS.iter())
+    {
+        checks.push(DataQualityCheck {
+            problem: DataQualityProblem::MissingField,
+            field: Some(field),
+            condition: within_range(missing(field)),
+        });
+    }
+    for field in REQUIRED_STRING_FIELDS
+        .iter()
+        .chain(["transactionId"].iter())
+    {
+        checks.push(DataQualityCheck {
+            problem: DataQualityProblem::WrongType,
+            field: Some(field),
+            condition: within_range(not_of_type(field, vec!["string"])),
+        });
+    }
+    for field in REQUIRED_NUMBER_FIELDS
+        .iter()
+        .chain(OPTIONAL_NUMBER_FIELDS.iter())
+    {
+        checks.push(DataQualityCheck {
+            problem: DataQualityProblem::WrongType,
+            field: Some(field),
+            condition: within_range(not_of_type(field, number_types())),
+        });
+    }
+    checks.push(DataQualityCheck {
+        problem: DataQualityProblem::NegativeAmount,
+        field: None,
+        condition: within_range(doc! {
+            "$and": [{ "$isNumber": "$amount" }, { "$lt": ["$amount", 0] }]
+        }),
+    });
+    // Same rule as the tariff_mismatch anomaly: the amount must match kWh * tariff,
+    // with or without the fixed charge
+    let energy_cost = doc! { "$multiply": ["$kwh", "$tariff"] };
+    let fixed_charge = doc! { "$cond": [{ "$isNumber": "$fixedCharge" }, "$fixedCharge", 0] };
+    checks.push(DataQualityCheck {
+        problem: DataQualityProblem::KwhTariffMismatch,
+        field: None,
+        condition: within_range(doc! {
+            "$and": [
+                { "$isNumber": "$amount" },
+                { "$isNumber": "$kwh" },
+                { "$isNumber": "$tariff" },
+                { "$gt": ["$amount", 0] },
+                {
+                    "$gt": [
+                        {
+                            "$divide": [
+                                {
+                                    "$min": [
+                                        { "$abs": { "$subtract": ["$amount", energy_cost.clone()] } },
+                                        { "$abs": { "$subtract": ["$amount", { "$add": [energy_cost, fixed_charge] }] } }
+                                    ]
+                                },
+                                "$amount"
+                            ]
+                        },
+                        TARIFF_TOLERANCE
+                    ]
+                }
+            ]
+        }),
+    });
+    checks
+}
+
+// Boolean the terminal sets on a raw record when a vend was cancelled before cash was taken.
+// A vend is voided when this is true or when its amount is negative (a reversal of an earlier vend).
+const VOIDED_FIELD: &str = "voided";
+
+// Average days per month, used to express kWh per customer as a monthly figure
+const DAYS_PER_MONTH: f64 = 365.25 / 12.0;
+
+// Totals per community (or overall) from the KPI aggregation, with the ratios derived here
+fn parse_kpi_set(totals: &Document, community: Option<String>, period_months: f64) -> KpiSet {
+    let revenue = bson_number(totals.get("revenue"));
+    let total_kwh = bson_number(totals.get("totalKwh"));
+    let active_customers = bson_number(totals.get("activeCustomers")) as u32;
+    let previous_revenue = bson_number(totals.get("previousRevenue"));
+    let (arpu, kwh_per_customer_month) = if active_customers > 0 {
+        let customers = active_customers as f64;
+        (revenue / customers, total_kwh / customers / period_months)
+    } else {
+        (0.0, 0.0)
+    };
+
+    KpiSet {
+        community,
+        revenue,
+        total_kwh,
+        active_customers,
+        new_customers: bson_number(totals.get("newCustomers")) as u32,
+        arpu,
+        kwh_per_customer_month,
+        previous_revenue,
+        revenue_growth_percent: (previous_revenue != 0.0)
+            .then(|| (revenue - previous_revenue) / previous_revenue * 100.0),
+    }
+}
+
+// $group accumulators describing the distribution of a numeric field
+fn distribution_accumulators(field: &str, prefix: &str) -> Document {
+    let input = format!("$num{}", field);
+    doc! {
+        format!("{}Count", prefix): { "$sum": { "$cond": [{ "$isNumber": &input }, 1, 0] } },
+        format!("{}Min", prefix): { "$min": &input },
+        format!("{}Max", prefix): { "$max": &input },
+        format!("{}Mean", prefix): { "$avg": &input },
+        format!("{}Percentiles", prefix): {
+            "$percentile": {
+                "input": &input,
+                "p": [0.5, 0.9, 0.99],
+                "method": "approximate"
+            }
         }
     }
 }
 
+fn parse_distribution(doc: &Document, prefix: &str) -> DistributionStats {
+    let percentiles = doc
+        .get_array(format!("{}Percentiles", prefix))
+        .map(|values| values.iter().map(|v| bson_number(Some(v))).collect())
+        .unwrap_or_else(|_| vec![0.0; 3]);
+
+    DistributionStats {
+        count: bson_number(doc.get(format!("{}Count", prefix))) as u32,
+        min: bson_number(doc.get(format!("{}Min", prefix))),
+        max: bson_number(doc.get(format!("{}Max", prefix))),
+        mean: bson_number(doc.get(format!("{}Mean", prefix))),
+        median: percentiles.first().copied().unwrap_or(0.0),
+        p90: percentiles.get(1).copied().unwrap_or(0.0),
+        p99: percentiles.get(2).copied().unwrap_or(0.0),
+    }
+}
+
+fn parse_histogram(buckets: &[Bson]) -> Vec<HistogramBucket> {
+    buckets
+        .iter()
+        .filter_map(|bucket| match bucket {
+            Bson::Document(bucket) => {
+                let bounds = bucket.get_document("_id").ok()?;
+                Some(HistogramBucket {
+                    lower_bound: bson_number(bounds.get("min")),
+                    upper_bound: bson_number(bounds.get("max")),
+                    count: bson_number(bucket.get("count")) as u32,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_group_statistics(groups: &[Bson]) -> Vec<GroupStatistics> {
+    groups
+        .iter()
+        .filter_map(|group| match group {
+            Bson::Document(group) => Some(GroupStatistics {
+                name: group.get_str("_id").unwrap_or("Unknown").to_string(),
+                amount: parse_distribution(group, "amount"),
+                kwh: parse_distribution(group, "kwh"),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// Document field holding the key a leaderboard is grouped by
+fn leaderboard_field(dimension: LeaderboardDimension) -> &'static str {
+    match dimension {
+        LeaderboardDimension::Meter => "$meterNumber",
+        LeaderboardDimension::Customer => "$customerName",
+        LeaderboardDimension::Community => "$community",
+        LeaderboardDimension::Station => "$vendingStation",
+    }
+}
+
+// Grouped total a leaderboard is ranked by
+fn leaderboard_sort_key(metric: LeaderboardMetric) -> &'static str {
+    match metric {
+        LeaderboardMetric::Amount => "totalAmount",
+        LeaderboardMetric::Kwh => "totalKwh",
+        LeaderboardMetric::Transactions => "totalTransactions",
+    }
+}
+
 #[async_trait]
 impl VendingRecordRepository for MongoDbVendingRecordRepository {
+    fn with_scope(&self, scope: AccessScope) -> Arc<dyn VendingRecordRepository> {
+        Arc::new(Self {
+            scope,
+            ..self.clone()
+        })
+    }
+
     async fn get_vending_records(
         &self,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
-    ) -> Result<Vec<VendingRecord>, Box<dyn Error>> {
+    ) -> Result<VendingRecordBatch, AppError> {
         // Convert chrono DateTime to MongoDB DateTime
         let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
         let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
 
         // Create filter for date range
-        let filter = doc! {
+        let mut filter = doc! {
             "timestamp": {
                 "$gte": start_bson,
                 "$lte": end_bson
             }
         };
+        if let Some(scope_filter) = self.scope_filter() {
+            filter.extend(scope_filter);
+        }
 
-        // Execute query
-        let mut cursor = self.collection.find(filter).await?;
+        // Execute query on raw documents so one malformed record cannot fail the whole batch
+        let mut cursor = self
+            .collection
+            .clone_with_type::<Document>()
+            .find(filter)
+            .await?;
         let mut records = Vec::new();
+        let mut decode_report = DecodeReport::default();
 
         // Collect results and convert to API format
         use futures_util::stream::StreamExt;
         while let Some(result) = cursor.next().await {
-            match result {
-                Ok(mongo_record) => {
-                    let api_record = VendingRecord::from(mongo_record);
-                    records.push(api_record);
-                }
-                Err(e) => return Err(Box::new(e)),
-            }
+            decode_document(result?, &mut records, &mut decode_report);
         }
 
-        Ok(records)
+        Ok(VendingRecordBatch {
+            records,
+            decode_report,
+        })
     }
 
     async fn get_vending_summary(
         &self,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
-    ) -> Result<VendingSummary, Box<dyn Error>> {
+        series: SummarySeriesOptions,
+    ) -> Result<VendingSummary, AppError> {
         // Convert chrono DateTime to MongoDB DateTime for filtering
         let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
         let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
 
-        // Build aggregation pipeline
-        let pipeline = vec![
-            // Match documents within date range
-            doc! {
-                "$match": {
-                    "timestamp": {
-                        "$gte": start_bson,
-                        "$lte": end_bson
-                    }
+        // Raw records only cover what the rollups do not: partial days, today and
+        // anything after the last refresh
+        let rollup_window = self.rollup_window(start_date, end_date).await?;
+        let raw_match = match rollup_window {
+            Some((first_day, last_day)) => {
+                let rollup_start = mongodb::bson::DateTime::from_millis(
+                    start_of_day(first_day).timestamp_millis(),
+                );
+                let rollup_end = mongodb::bson::DateTime::from_millis(
+                    start_of_day(last_day + Days::new(1)).timestamp_millis(),
+                );
+                doc! {
+                    "$or": [
+                        { "timestamp": { "$gte": start_bson, "$lt": rollup_start } },
+                        { "timestamp": { "$gte": rollup_end, "$lte": end_bson } }
+                    ]
+                }
+            }
+            None => doc! {
+                "timestamp": {
+                    "$gte": start_bson,
+                    "$lte": end_bson
                 }
             },
+        };
+
+        // Build aggregation pipeline
+        let mut pipeline = vec![
+            // Match documents within date range
+            doc! { "$match": raw_match },
             // Add computed fields for date processing
             doc! {
                 "$addFields": {
@@ -157,6 +1093,45 @@ impl VendingRecordRepository for MongoDbVendingRecordRepository {
                     "dailyKwh": { "$sum": "$safeKwh" }
                 }
             },
+        ];
+
+        // Pull the complete days from the rollups in the same per-station daily shape
+        if let (Some((first_day, last_day)), Some(daily_rollups)) =
+            (rollup_window, &self.daily_rollups)
+        {
+            let mut rollup_match = doc! {
+                "date": {
+                    "$gte": first_day.format("%Y-%m-%d").to_string(),
+                    "$lte": last_day.format("%Y-%m-%d").to_string()
+                }
+            };
+            // $unionWith reads another collection, so the scope is applied there as well
+            if let Some(scope_filter) = self.scope_filter() {
+                rollup_match.extend(scope_filter);
+            }
+            pipeline.push(doc! {
+                "$unionWith": {
+                    "coll": daily_rollups.rollup_collection_name(),
+                    "pipeline": [
+                        { "$match": rollup_match },
+                        {
+                            "$group": {
+                                "_id": { "vendingStation": "$vendingStation", "date": "$date" },
+                                "dailyTransactions": { "$sum": "$totalTransactions" },
+                                "dailyAmount": { "$sum": "$totalAmount" },
+                                "dailyKwh": { "$sum": "$totalKwh" }
+                            }
+                        }
+                    ]
+                }
+            });
+        }
+
+        if series.cumulative || series.moving_averages {
+            pipeline.extend(summary_series_stages(start_date, series));
+        }
+
+        pipeline.extend([
             // Group by vending station to create station summaries
             doc! {
                 "$group": {
@@ -169,7 +1144,13 @@ impl VendingRecordRepository for MongoDbVendingRecordRepository {
                             "date": "$_id.date",
                             "total_transactions": "$dailyTransactions",
                             "total_amount": "$dailyAmount",
-                            "total_kwh": "$dailyKwh"
+                            "total_kwh": "$dailyKwh",
+                            "cumulative_amount": "$cumulativeAmount",
+                            "cumulative_kwh": "$cumulativeKwh",
+                            "amount_avg_7d": "$amountAvg7d",
+                            "amount_avg_30d": "$amountAvg30d",
+                            "kwh_avg_7d": "$kwhAvg7d",
+                            "kwh_avg_30d": "$kwhAvg30d"
                         }
                     }
                 }
@@ -203,20 +1184,20 @@ impl VendingRecordRepository for MongoDbVendingRecordRepository {
                     }
                 }
             },
-        ];
+        ]);
 
         // Execute aggregation
-        let mut cursor = self.collection.aggregate(pipeline).await?;
+        let mut cursor = self.collection.aggregate(self.restrict(pipeline)).await?;
 
         use futures_util::stream::StreamExt;
         if let Some(result) = cursor.next().await {
             let doc = result?;
 
             // Extract values from the aggregation result
-            let grand_total_transactions =
-                doc.get_i32("grandTotalTransactions").unwrap_or(0) as u32;
-            let grand_total_amount = doc.get_f64("grandTotalAmount").unwrap_or(0.0);
-            let grand_total_kwh = doc.get_f64("grandTotalKwh").unwrap_or(0.0);
+            // Rollup counts are stored as 64-bit, so totals may come back as either int width
+            let grand_total_transactions = bson_number(doc.get("grandTotalTransactions")) as u32;
+            let grand_total_amount = bson_number(doc.get("grandTotalAmount"));
+            let grand_total_kwh = bson_number(doc.get("grandTotalKwh"));
 
             // Parse station summaries
             let mut station_summaries = Vec::new();
@@ -228,9 +1209,9 @@ impl VendingRecordRepository for MongoDbVendingRecordRepository {
                             .unwrap_or("Unknown")
                             .to_string();
                         let total_transactions =
-                            station.get_i32("total_transactions").unwrap_or(0) as u32;
-                        let total_amount = station.get_f64("total_amount").unwrap_or(0.0);
-                        let total_kwh = station.get_f64("total_kwh").unwrap_or(0.0);
+                            bson_number(station.get("total_transactions")) as u32;
+                        let total_amount = bson_number(station.get("total_amount"));
+                        let total_kwh = bson_number(station.get("total_kwh"));
 
                         // Parse daily summaries
                         let mut daily_summaries = Vec::new();
@@ -239,12 +1220,24 @@ impl VendingRecordRepository for MongoDbVendingRecordRepository {
                                 if let mongodb::bson::Bson::Document(daily) = daily_doc {
                                     let daily_summary = DailySummary {
                                         date: daily.get_str("date").unwrap_or("").to_string(),
-                                        total_transactions: daily
-                                            .get_i32("total_transactions")
-                                            .unwrap_or(0)
+                                        total_transactions: bson_number(
+                                            daily.get("total_transactions"),
+                                        )
                                             as u32,
-                                        total_amount: daily.get_f64("total_amount").unwrap_or(0.0),
-                                        total_kwh: daily.get_f64("total_kwh").unwrap_or(0.0),
+                                        total_amount: bson_number(daily.get("total_amount")),
+                                        total_kwh: bson_number(daily.get("total_kwh")),
+                                        cumulative_amount: optional_number(
+                                            daily.get("cumulative_amount"),
+                                        ),
+                                        cumulative_kwh: optional_number(
+                                            daily.get("cumulative_kwh"),
+                                        ),
+                                        amount_avg_7d: optional_number(daily.get("amount_avg_7d")),
+                                        amount_avg_30d: optional_number(
+                                            daily.get("amount_avg_30d"),
+                                        ),
+                                        kwh_avg_7d: optional_number(daily.get("kwh_avg_7d")),
+                                        kwh_avg_30d: optional_number(daily.get("kwh_avg_30d")),
                                     };
                                     daily_summaries.push(daily_summary);
                                 }
@@ -290,4 +1283,1320 @@ impl VendingRecordRepository for MongoDbVendingRecordRepository {
             })
         }
     }
+    async fn get_vending_statistics(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        histogram_buckets: u32,
+    ) -> Result<VendingStatistics, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+
+        // Both metrics are accumulated in the same $group for every breakdown
+        let mut accumulators = distribution_accumulators("Amount", "amount");
+        accumulators.extend(distribution_accumulators("Kwh", "kwh"));
+
+        let mut overall_group = doc! { "_id": null };
+        overall_group.extend(accumulators.clone());
+        let mut station_group = doc! { "_id": "$safeVendingStation" };
+        station_group.extend(accumulators.clone());
+        let mut community_group = doc! { "_id": "$safeCommunity" };
+        community_group.extend(accumulators);
+
+        let pipeline = vec![
+            // Match documents within date range
+            doc! {
+                "$match": {
+                    "timestamp": {
+                        "$gte": start_bson,
+                        "$lte": end_bson
+                    }
+                }
+            },
+            // Keep only numeric values so strings and nulls do not distort min/max
+            doc! {
+                "$addFields": {
+                    "numAmount": { "$cond": [{ "$isNumber": "$amount" }, "$amount", "$$REMOVE"] },
+                    "numKwh": { "$cond": [{ "$isNumber": "$kwh" }, "$kwh", "$$REMOVE"] },
+                    "safeVendingStation": { "$ifNull": ["$vendingStation", "Unknown"] },
+                    "safeCommunity": { "$ifNull": ["$community", "Unknown"] }
+                }
+            },
+            doc! {
+                "$facet": {
+                    "overall": [{ "$group": overall_group }],
+                    "byStation": [{ "$group": station_group }, { "$sort": { "_id": 1 } }],
+                    "byCommunity": [{ "$group": community_group }, { "$sort": { "_id": 1 } }],
+                    "amountHistogram": [
+                        { "$match": { "numAmount": { "$exists": true } } },
+                        { "$bucketAuto": { "groupBy": "$numAmount", "buckets": histogram_buckets as i32 } }
+                    ],
+                    "kwhHistogram": [
+                        { "$match": { "numKwh": { "$exists": true } } },
+                        { "$bucketAuto": { "groupBy": "$numKwh", "buckets": histogram_buckets as i32 } }
+                    ]
+                }
+            },
+        ];
+
+        let mut cursor = self.collection.aggregate(self.restrict(pipeline)).await?;
+
+        use futures_util::stream::StreamExt;
+        let doc = match cursor.next().await {
+            Some(result) => result?,
+            None => Document::new(),
+        };
+
+        let empty = Vec::new();
+        let overall = match doc.get_array("overall").ok().and_then(|a| a.first()) {
+            Some(Bson::Document(overall)) => overall.clone(),
+            _ => Document::new(),
+        };
+
+        Ok(VendingStatistics {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            amount: MetricDistribution {
+                stats: parse_distribution(&overall, "amount"),
+                histogram: parse_histogram(doc.get_array("amountHistogram").unwrap_or(&empty)),
+            },
+            kwh: MetricDistribution {
+                stats: parse_distribution(&overall, "kwh"),
+                histogram: parse_histogram(doc.get_array("kwhHistogram").unwrap_or(&empty)),
+            },
+            vending_station_statistics: parse_group_statistics(
+                doc.get_array("byStation").unwrap_or(&empty),
+            ),
+            community_statistics: parse_group_statistics(
+                doc.get_array("byCommunity").unwrap_or(&empty),
+            ),
+        })
+    }
+
+    async fn get_leaderboard(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        dimension: LeaderboardDimension,
+        metric: LeaderboardMetric,
+        limit: u32,
+    ) -> Result<Leaderboard, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+        let sort_key = leaderboard_sort_key(metric);
+
+        let pipeline = vec![
+            // Match documents within date range
+            doc! {
+                "$match": {
+                    "timestamp": {
+                        "$gte": start_bson,
+                        "$lte": end_bson
+                    }
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "safeAmount": { "$ifNull": ["$amount", 0.0] },
+                    "safeKwh": { "$ifNull": ["$kwh", 0.0] },
+                    "safeKey": { "$ifNull": [leaderboard_field(dimension), "Unknown"] }
+                }
+            },
+            // Compute period totals alongside the ranking so shares need one round trip
+            doc! {
+                "$facet": {
+                    "totals": [{
+                        "$group": {
+                            "_id": null,
+                            "totalTransactions": { "$sum": 1 },
+                            "totalAmount": { "$sum": "$safeAmount" },
+                            "totalKwh": { "$sum": "$safeKwh" }
+                        }
+                    }],
+                    "ranking": [
+                        {
+                            "$group": {
+                                "_id": "$safeKey",
+                                "totalTransactions": { "$sum": 1 },
+                                "totalAmount": { "$sum": "$safeAmount" },
+                                "totalKwh": { "$sum": "$safeKwh" }
+                            }
+                        },
+                        { "$sort": { sort_key: -1, "_id": 1 } },
+                        { "$limit": limit as i64 }
+                    ]
+                }
+            },
+        ];
+
+        let mut cursor = self.collection.aggregate(self.restrict(pipeline)).await?;
+
+        use futures_util::stream::StreamExt;
+        let doc = match cursor.next().await {
+            Some(result) => result?,
+            None => Document::new(),
+        };
+
+        let totals = match doc.get_array("totals").ok().and_then(|a| a.first()) {
+            Some(Bson::Document(totals)) => totals.clone(),
+            _ => Document::new(),
+        };
+        let total_transactions = bson_number(totals.get("totalTransactions"));
+        let total_amount = bson_number(totals.get("totalAmount"));
+        let total_kwh = bson_number(totals.get("totalKwh"));
+        let metric_total = bson_number(totals.get(sort_key));
+
+        let mut entries = Vec::new();
+        if let Ok(ranking) = doc.get_array("ranking") {
+            for (index, entry) in ranking.iter().enumerate() {
+                if let Bson::Document(entry) = entry {
+                    let metric_value = bson_number(entry.get(sort_key));
+                    entries.push(LeaderboardEntry {
+                        rank: index as u32 + 1,
+                        name: entry.get_str("_id").unwrap_or("Unknown").to_string(),
+                        total_transactions: bson_number(entry.get("totalTransactions")) as u32,
+                        total_amount: bson_number(entry.get("totalAmount")),
+                        total_kwh: bson_number(entry.get("totalKwh")),
+                        share_percent: if metric_total != 0.0 {
+                            metric_value / metric_total * 100.0
+                        } else {
+                            0.0
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(Leaderboard {
+            dimension,
+            metric,
+            total_transactions: total_transactions as u32,
+            total_amount,
+            total_kwh,
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            entries,
+        })
+    }
+
+    async fn get_inactive_meters(
+        &self,
+        history_start: DateTime<Utc>,
+        inactive_since: DateTime<Utc>,
+        min_purchases: u32,
+    ) -> Result<Vec<InactiveMeter>, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(history_start.timestamp_millis());
+        let cutoff_bson = mongodb::bson::DateTime::from_millis(inactive_since.timestamp_millis());
+
+        let pipeline = vec![
+            // Only records that can be attributed to a meter
+            doc! {
+                "$match": {
+                    "timestamp": { "$gte": start_bson },
+                    "meterNumber": { "$type": "string", "$ne": "" }
+                }
+            },
+            // Sort so $last picks the customer details of the latest vend
+            doc! { "$sort": { "timestamp": 1 } },
+            doc! {
+                "$group": {
+                    "_id": "$meterNumber",
+                    "firstPurchase": { "$first": "$timestamp" },
+                    "lastPurchase": { "$last": "$timestamp" },
+                    "purchaseCount": { "$sum": 1_i64 },
+                    "customerName": { "$last": string_or_null("customerName") },
+                    "community": { "$last": string_or_null("community") },
+                    "vendingStation": { "$last": string_or_null("vendingStation") }
+                }
+            },
+            // Meters that bought regularly but have gone quiet
+            doc! {
+                "$match": {
+                    "purchaseCount": { "$gte": min_purchases as i64 },
+                    "lastPurchase": { "$lt": cutoff_bson }
+                }
+            },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .with_type::<MongoMeterActivity>()
+            .await?;
+
+        let now = Utc::now();
+        let mut meters = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            let activity = result?;
+            let first_purchase = activity.first_purchase.to_chrono();
+            let last_purchase = activity.last_purchase.to_chrono();
+
+            let span_days = (last_purchase - first_purchase).num_seconds() as f64 / 86_400.0;
+            let typical_interval_days = if activity.purchase_count > 1 {
+                span_days / (activity.purchase_count - 1) as f64
+            } else {
+                0.0
+            };
+            let days_since_last_purchase = (now - last_purchase).num_seconds() as f64 / 86_400.0;
+            let overdue_factor = if typical_interval_days > 0.0 {
+                days_since_last_purchase / typical_interval_days
+            } else {
+                0.0
+            };
+
+            meters.push(InactiveMeter {
+                meter_number: activity.meter_number,
+                customer_name: activity.customer_name,
+                community: activity.community,
+                vending_station: activity.vending_station,
+                first_purchase,
+                last_purchase,
+                purchase_count: activity.purchase_count as u32,
+                typical_interval_days,
+                days_since_last_purchase,
+                overdue_factor,
+            });
+        }
+
+        // Most overdue relative to their own buying rhythm first
+        meters.sort_by(|a, b| b.overdue_factor.total_cmp(&a.overdue_factor));
+
+        Ok(meters)
+    }
+
+    async fn get_meter_behaviour(
+        &self,
+        as_of: DateTime<Utc>,
+        window_days: u32,
+        meter_number: Option<&str>,
+    ) -> Result<Vec<MeterBehaviour>, AppError> {
+        let window = chrono::Duration::days(window_days as i64);
+        let recent_start = as_of - window;
+        let prior_start = recent_start - window;
+        let as_of_bson = mongodb::bson::DateTime::from_millis(as_of.timestamp_millis());
+        let recent_bson = mongodb::bson::DateTime::from_millis(recent_start.timestamp_millis());
+        let prior_bson = mongodb::bson::DateTime::from_millis(prior_start.timestamp_millis());
+
+        let mut filter = doc! {
+            "timestamp": { "$gte": prior_bson, "$lte": as_of_bson },
+            "meterNumber": { "$type": "string", "$ne": "" }
+        };
+        if let Some(meter_number) = meter_number {
+            filter.insert("meterNumber", meter_number);
+        }
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            // Attach the previous vend of the same meter to every vend
+            doc! {
+                "$setWindowFields": {
+                    "partitionBy": "$meterNumber",
+                    "sortBy": { "timestamp": 1 },
+                    "output": {
+                        "previousTimestamp": { "$shift": { "output": "$timestamp", "by": -1 } }
+                    }
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "intervalDays": {
+                        "$cond": [
+                            { "$eq": [{ "$type": "$previousTimestamp" }, "date"] },
+                            { "$divide": [{ "$subtract": ["$timestamp", "$previousTimestamp"] }, 86_400_000.0] },
+                            "$$REMOVE"
+                        ]
+                    },
+                    "safeAmount": { "$cond": [{ "$isNumber": "$amount" }, "$amount", 0.0] },
+                    "isRecent": { "$gte": ["$timestamp", recent_bson] }
+                }
+            },
+            // Sort so $last picks the customer details of the latest vend
+            doc! { "$sort": { "timestamp": 1 } },
+            doc! {
+                "$group": {
+                    "_id": "$meterNumber",
+                    "purchaseCount": { "$sum": 1_i64 },
+                    "averageTicket": { "$avg": "$amount" },
+                    "averageInterval": { "$avg": "$intervalDays" },
+                    "intervalStdDev": { "$stdDevPop": "$intervalDays" },
+                    "recentTransactions": { "$sum": { "$cond": ["$isRecent", 1_i64, 0_i64] } },
+                    "recentAmount": { "$sum": { "$cond": ["$isRecent", "$safeAmount", 0.0] } },
+                    "priorTransactions": { "$sum": { "$cond": ["$isRecent", 0_i64, 1_i64] } },
+                    "priorAmount": { "$sum": { "$cond": ["$isRecent", 0.0, "$safeAmount"] } },
+                    "customerName": { "$last": string_or_null("customerName") },
+                    "community": { "$last": string_or_null("community") },
+                    "vendingStation": { "$last": string_or_null("vendingStation") }
+                }
+            },
+            doc! { "$sort": { "_id": 1 } },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .with_type::<MongoMeterBehaviour>()
+            .await?;
+
+        let mut meters = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            meters.push(MeterBehaviour::from(result?));
+        }
+
+        Ok(meters)
+    }
+
+    async fn get_credit_runout(
+        &self,
+        history_start: DateTime<Utc>,
+        as_of: DateTime<Utc>,
+    ) -> Result<Vec<CreditRunout>, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(history_start.timestamp_millis());
+        let as_of_bson = mongodb::bson::DateTime::from_millis(as_of.timestamp_millis());
+
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": { "$gte": start_bson, "$lte": as_of_bson },
+                    "meterNumber": { "$type": "string", "$ne": "" }
+                }
+            },
+            // Attach the next vend of the same meter to every vend
+            doc! {
+                "$setWindowFields": {
+                    "partitionBy": "$meterNumber",
+                    "sortBy": { "timestamp": 1 },
+                    "output": {
+                        "nextTimestamp": { "$shift": { "output": "$timestamp", "by": 1 } },
+                        "nextRemainingCredit": { "$shift": { "output": "$remainingCredit", "by": 1 } }
+                    }
+                }
+            },
+            // Energy used until the next vend: credit after this vend minus credit left at the next one,
+            // or simply the kWh bought when the meter does not report its balance
+            doc! {
+                "$addFields": {
+                    "safeKwh": { "$cond": [{ "$isNumber": "$kwh" }, "$kwh", 0.0] },
+                    "intervalDays": {
+                        "$cond": [
+                            { "$eq": [{ "$type": "$nextTimestamp" }, "date"] },
+                            { "$divide": [{ "$subtract": ["$nextTimestamp", "$timestamp"] }, 86_400_000.0] },
+                            "$$REMOVE"
+                        ]
+                    }
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "consumedKwh": {
+                        "$cond": [
+                            { "$eq": [{ "$type": "$intervalDays" }, "missing"] },
+                            "$$REMOVE",
+                            {
+                                "$cond": [
+                                    {
+                                        "$and": [
+                                            { "$isNumber": "$remainingCredit" },
+                                            { "$isNumber": "$nextRemainingCredit" }
+                                        ]
+                                    },
+                                    {
+                                        "$max": [
+                                            0.0,
+                                            { "$subtract": [{ "$add": ["$remainingCredit", "$safeKwh"] }, "$nextRemainingCredit"] }
+                                        ]
+                                    },
+                                    "$safeKwh"
+                                ]
+                            }
+                        ]
+                    }
+                }
+            },
+            // Sort so $last picks the latest vend
+            doc! { "$sort": { "timestamp": 1 } },
+            doc! {
+                "$group": {
+                    "_id": "$meterNumber",
+                    "observedDays": { "$sum": "$intervalDays" },
+                    "consumedKwh": { "$sum": "$consumedKwh" },
+                    "lastPurchase": { "$last": "$timestamp" },
+                    "lastKwh": { "$last": number_or_null("kwh") },
+                    "lastRemainingCredit": { "$last": number_or_null("remainingCredit") },
+                    "customerName": { "$last": string_or_null("customerName") },
+                    "community": { "$last": string_or_null("community") },
+                    "vendingStation": { "$last": string_or_null("vendingStation") }
+                }
+            },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .with_type::<MongoMeterConsumption>()
+            .await?;
+
+        let mut meters = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            // Meters with a single vend have no consumption rate to extrapolate from
+            if let Some(runout) = result?.into_runout(as_of) {
+                meters.push(runout);
+            }
+        }
+
+        meters.sort_by_key(|meter| meter.estimated_runout);
+
+        Ok(meters)
+    }
+
+    async fn get_operator_shifts(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        user_id: Option<&str>,
+    ) -> Result<Vec<OperatorShift>, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+        let user_filter = match user_id {
+            Some(user_id) => Bson::String(user_id.to_string()),
+            None => Bson::Document(doc! { "$type": "string", "$ne": "" }),
+        };
+        let voided_path = format!("${}", VOIDED_FIELD);
+
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": { "$gte": start_bson, "$lte": end_bson },
+                    "userId": user_filter
+                }
+            },
+            // Voided vends are either flagged by the terminal (VOIDED_FIELD) or negative reversals.
+            // Flagged voids never took cash; reversals paid cash back, so they net off expected cash.
+            doc! {
+                "$addFields": {
+                    "date": {
+                        "$dateToString": {
+                            "format": "%Y-%m-%d",
+                            "date": "$timestamp",
+                            "timezone": timezone
+                        }
+                    },
+                    "safeAmount": { "$cond": [{ "$isNumber": "$amount" }, "$amount", 0.0] },
+                    "isFlaggedVoid": { "$eq": [&voided_path, true] },
+                    "isVoided": {
+                        "$or": [
+                            { "$eq": [&voided_path, true] },
+                            { "$and": [{ "$isNumber": "$amount" }, { "$lt": ["$amount", 0] }] }
+                        ]
+                    }
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": { "userId": "$userId", "date": "$date" },
+                    "vendingStations": { "$addToSet": { "$ifNull": ["$vendingStation", "Unknown"] } },
+                    "transactionCount": { "$sum": { "$cond": ["$isVoided", 0_i64, 1_i64] } },
+                    "totalAmount": { "$sum": { "$cond": ["$isVoided", 0.0, "$safeAmount"] } },
+                    "firstVend": { "$min": "$timestamp" },
+                    "lastVend": { "$max": "$timestamp" },
+                    "voidedCount": { "$sum": { "$cond": ["$isVoided", 1_i64, 0_i64] } },
+                    "voidedAmount": { "$sum": { "$cond": ["$isVoided", { "$abs": "$safeAmount" }, 0.0] } },
+                    "expectedCash": { "$sum": { "$cond": ["$isFlaggedVoid", 0.0, "$safeAmount"] } }
+                }
+            },
+            doc! { "$sort": { "_id.date": 1, "_id.userId": 1 } },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .with_type::<MongoOperatorShift>()
+            .await?;
+
+        let mut shifts = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            shifts.push(OperatorShift::from(result?));
+        }
+
+        Ok(shifts)
+    }
+
+    async fn get_meter_amount_baselines(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<MeterAmountBaseline>, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": { "$gte": start_bson, "$lte": end_bson },
+                    "meterNumber": { "$type": "string", "$ne": "" },
+                    "amount": { "$type": "number" }
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": "$meterNumber",
+                    "purchaseCount": { "$sum": 1_i64 },
+                    "meanAmount": { "$avg": "$amount" },
+                    "stdDevAmount": { "$stdDevPop": "$amount" }
+                }
+            },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .with_type::<MongoMeterAmountBaseline>()
+            .await?;
+
+        let mut baselines = Vec::new();
+
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            baselines.push(MeterAmountBaseline::from(result?));
+        }
+
+        Ok(baselines)
+    }
+
+    async fn get_integrity_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        window_seconds: u32,
+    ) -> Result<IntegrityReport, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+        let window_millis = window_seconds as i64 * 1000;
+
+        // Same meter and amount: start a new cluster whenever the gap to the previous vend
+        // exceeds the window, then number clusters with a running sum of those starts
+        let near_duplicate_stages = vec![
+            doc! {
+                "$match": {
+                    "meterNumber": { "$type": "string", "$ne": "" },
+                    "amount": { "$type": "number" }
+                }
+            },
+            doc! {
+                "$setWindowFields": {
+                    "partitionBy": { "meterNumber": "$meterNumber", "amount": "$amount" },
+                    "sortBy": { "timestamp": 1 },
+                    "output": {
+                        "previousTimestamp": { "$shift": { "output": "$timestamp", "by": -1 } }
+                    }
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "startsCluster": {
+                        "$cond": [
+                            {
+                                "$or": [
+                                    { "$ne": [{ "$type": "$previousTimestamp" }, "date"] },
+                                    { "$gt": [{ "$subtract": ["$timestamp", "$previousTimestamp"] }, window_millis] }
+                                ]
+                            },
+                            1,
+                            0
+                        ]
+                    }
+                }
+            },
+            doc! {
+                "$setWindowFields": {
+                    "partitionBy": { "meterNumber": "$meterNumber", "amount": "$amount" },
+                    "sortBy": { "timestamp": 1 },
+                    "output": {
+                        "cluster": {
+                            "$sum": "$startsCluster",
+                            "window": { "documents": ["unbounded", "current"] }
+                        }
+                    }
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": { "meterNumber": "$meterNumber", "amount": "$amount", "cluster": "$cluster" },
+                    "count": { "$sum": 1_i64 },
+                    "records": { "$push": duplicate_record_fields() }
+                }
+            },
+            doc! { "$match": { "count": { "$gt": 1 } } },
+            doc! {
+                "$project": {
+                    "_id": 0,
+                    "key": {
+                        "$concat": ["$_id.meterNumber", " / ", { "$toString": "$_id.amount" }]
+                    },
+                    "count": 1,
+                    "records": 1
+                }
+            },
+            doc! { "$sort": { "count": -1, "key": 1 } },
+        ];
+
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": { "$gte": start_bson, "$lte": end_bson }
+                }
+            },
+            doc! {
+                "$facet": {
+                    "duplicateTokens": exact_duplicate_stages("token"),
+                    "duplicateTransactionIds": exact_duplicate_stages("transactionId"),
+                    "nearDuplicates": near_duplicate_stages
+                }
+            },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .with_type::<MongoIntegrityFacets>()
+            .await?;
+
+        use futures_util::stream::StreamExt;
+        let facets = match cursor.next().await {
+            Some(result) => result?,
+            None => MongoIntegrityFacets {
+                duplicate_tokens: Vec::new(),
+                duplicate_transaction_ids: Vec::new(),
+                near_duplicates: Vec::new(),
+            },
+        };
+
+        Ok(IntegrityReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            near_duplicate_window_seconds: window_seconds,
+            duplicate_tokens: facets
+                .duplicate_tokens
+                .into_iter()
+                .map(DuplicateGroup::from)
+                .collect(),
+            duplicate_transaction_ids: facets
+                .duplicate_transaction_ids
+                .into_iter()
+                .map(DuplicateGroup::from)
+                .collect(),
+            near_duplicate_vends: facets
+                .near_duplicates
+                .into_iter()
+                .map(DuplicateGroup::from)
+                .collect(),
+        })
+    }
+
+    async fn get_transaction_sequence(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<TransactionSequenceEntry>, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "timestamp": { "$gte": start_bson, "$lte": end_bson },
+                    "vendingStation": { "$type": "string", "$ne": "" },
+                    "transactionId": { "$type": "string", "$ne": "" }
+                }
+            },
+            doc! {
+                "$project": {
+                    "_id": { "$toString": "$_id" },
+                    "vendingStation": 1,
+                    "transactionId": 1,
+                    "timestamp": 1
+                }
+            },
+            doc! { "$sort": { "vendingStation": 1, "timestamp": 1 } },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .with_type::<MongoTransactionSequenceEntry>()
+            .await?;
+
+        let mut entries = Vec::new();
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            entries.push(TransactionSequenceEntry::from(result?));
+        }
+
+        Ok(entries)
+    }
+
+    async fn get_heatmap(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        vending_station: Option<&str>,
+        community: Option<&str>,
+    ) -> Result<VendingHeatmap, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+
+        let mut filter = doc! {
+            "timestamp": { "$gte": start_bson, "$lte": end_bson }
+        };
+        if let Some(vending_station) = vending_station {
+            filter.insert("vendingStation", vending_station);
+        }
+        if let Some(community) = community {
+            filter.insert("community", community);
+        }
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! {
+                "$group": {
+                    "_id": {
+                        "day": { "$isoDayOfWeek": { "date": "$timestamp", "timezone": timezone } },
+                        "hour": { "$hour": { "date": "$timestamp", "timezone": timezone } }
+                    },
+                    "transactionCount": { "$sum": 1_i64 },
+                    "totalAmount": {
+                        "$sum": { "$cond": [{ "$isNumber": "$amount" }, "$amount", 0.0] }
+                    }
+                }
+            },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .with_type::<MongoHeatmapCell>()
+            .await?;
+
+        let mut transaction_counts = vec![vec![0_u32; 24]; 7];
+        let mut amounts = vec![vec![0.0; 24]; 7];
+        use futures_util::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            let cell = result?;
+            let (day, hour) = ((cell.key.day - 1) as usize, cell.key.hour as usize);
+            if day < 7 && hour < 24 {
+                transaction_counts[day][hour] = cell.transaction_count as u32;
+                amounts[day][hour] = cell.total_amount;
+            }
+        }
+
+        Ok(VendingHeatmap {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            timezone: timezone.to_string(),
+            vending_station: vending_station.map(str::to_string),
+            community: community.map(str::to_string),
+            days: HEATMAP_DAYS.iter().map(|day| day.to_string()).collect(),
+            transaction_counts,
+            amounts,
+        })
+    }
+
+    async fn get_kpis(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<KpiReport, AppError> {
+        let previous_start = start_date - (end_date - start_date);
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+        let previous_start_bson =
+            mongodb::bson::DateTime::from_millis(previous_start.timestamp_millis());
+        let period_months =
+            ((end_date - start_date).num_seconds() as f64 / 86_400.0 / DAYS_PER_MONTH)
+                .max(1.0 / DAYS_PER_MONTH);
+
+        // Sums shared by the per-community and overall groupings of the meter totals
+        let kpi_accumulators = doc! {
+            "revenue": { "$sum": "$currentAmount" },
+            "totalKwh": { "$sum": "$currentKwh" },
+            "previousRevenue": { "$sum": "$previousAmount" },
+            "activeCustomers": { "$sum": { "$cond": ["$isActive", 1_i64, 0_i64] } },
+            "newCustomers": { "$sum": { "$cond": ["$isNew", 1_i64, 0_i64] } }
+        };
+        let mut community_group = doc! { "_id": "$_id.community" };
+        community_group.extend(kpi_accumulators.clone());
+        let mut overall_group = doc! { "_id": null };
+        overall_group.extend(kpi_accumulators);
+
+        let pipeline = vec![
+            // The whole history up to end_date is needed to tell new customers from returning ones
+            doc! { "$match": { "timestamp": { "$lte": end_bson } } },
+            doc! {
+                "$addFields": {
+                    "safeAmount": { "$cond": [{ "$isNumber": "$amount" }, "$amount", 0.0] },
+                    "safeKwh": { "$cond": [{ "$isNumber": "$kwh" }, "$kwh", 0.0] },
+                    "inPeriod": { "$gte": ["$timestamp", start_bson] },
+                    "inPreviousPeriod": {
+                        "$and": [
+                            { "$gte": ["$timestamp", previous_start_bson] },
+                            { "$lt": ["$timestamp", start_bson] }
+                        ]
+                    }
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": {
+                        "meterNumber": "$meterNumber",
+                        "community": { "$ifNull": ["$community", "Unknown"] }
+                    },
+                    "firstPurchase": { "$min": "$timestamp" },
+                    "currentVends": { "$sum": { "$cond": ["$inPeriod", 1_i64, 0_i64] } },
+                    "currentAmount": { "$sum": { "$cond": ["$inPeriod", "$safeAmount", 0.0] } },
+                    "currentKwh": { "$sum": { "$cond": ["$inPeriod", "$safeKwh", 0.0] } },
+                    "previousAmount": {
+                        "$sum": { "$cond": ["$inPreviousPeriod", "$safeAmount", 0.0] }
+                    }
+                }
+            },
+            // A meter is new if its first vend in any community falls in the period
+            doc! {
+                "$setWindowFields": {
+                    "partitionBy": "$_id.meterNumber",
+                    "output": { "meterFirstPurchase": { "$min": "$firstPurchase" } }
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "isActive": {
+                        "$and": [
+                            { "$eq": [{ "$type": "$_id.meterNumber" }, "string"] },
+                            { "$ne": ["$_id.meterNumber", ""] },
+                            { "$gt": ["$currentVends", 0] }
+                        ]
+                    }
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "isNew": {
+                        "$and": ["$isActive", { "$gte": ["$meterFirstPurchase", start_bson] }]
+                    }
+                }
+            },
+            doc! {
+                "$facet": {
+                    "communities": [
+                        { "$group": community_group },
+                        { "$match": { "$or": [{ "revenue": { "$ne": 0 } }, { "previousRevenue": { "$ne": 0 } }, { "activeCustomers": { "$gt": 0 } }] } },
+                        { "$sort": { "_id": 1 } }
+                    ],
+                    // Meters that moved community are one customer overall
+                    "overall": [
+                        {
+                            "$group": {
+                                "_id": "$_id.meterNumber",
+                                "currentAmount": { "$sum": "$currentAmount" },
+                                "currentKwh": { "$sum": "$currentKwh" },
+                                "previousAmount": { "$sum": "$previousAmount" },
+                                "isActive": { "$max": "$isActive" },
+                                "isNew": { "$max": "$isNew" }
+                            }
+                        },
+                        { "$group": overall_group }
+                    ]
+                }
+            },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .await?;
+
+        use futures_util::stream::StreamExt;
+        let doc = match cursor.next().await {
+            Some(result) => result?,
+            None => Document::new(),
+        };
+
+        let overall = match doc
+            .get_array("overall")
+            .ok()
+            .and_then(|overall| overall.first())
+        {
+            Some(Bson::Document(totals)) => parse_kpi_set(totals, None, period_months),
+            _ => parse_kpi_set(&Document::new(), None, period_months),
+        };
+        let mut communities = Vec::new();
+        if let Ok(groups) = doc.get_array("communities") {
+            for group in groups {
+                if let Bson::Document(totals) = group {
+                    let community = totals.get_str("_id").unwrap_or("Unknown").to_string();
+                    communities.push(parse_kpi_set(totals, Some(community), period_months));
+                }
+            }
+        }
+
+        Ok(KpiReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            previous_period_start: previous_start.format("%Y-%m-%d").to_string(),
+            period_months,
+            overall,
+            communities,
+        })
+    }
+
+    async fn get_data_quality_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        now: DateTime<Utc>,
+        sample_size: u32,
+    ) -> Result<DataQualityReport, AppError> {
+        let start_bson = mongodb::bson::DateTime::from_millis(start_date.timestamp_millis());
+        let end_bson = mongodb::bson::DateTime::from_millis(end_date.timestamp_millis());
+        let now_bson = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
+        let checks = data_quality_checks(start_bson, end_bson, now_bson);
+
+        // One facet per check, plus the number of in-range documents
+        let mut facets = doc! {
+            "scanned": [
+                {
+                    "$match": {
+                        "timestamp": { "$gte": start_bson, "$lte": end_bson }
+                    }
+                },
+                { "$count": "count" }
+            ]
+        };
+        for (index, check) in checks.iter().enumerate() {
+            facets.insert(
+                format!("check{}", index),
+                vec![
+                    doc! { "$match": { "$expr": check.condition.clone() } },
+                    doc! {
+                        "$group": {
+                            "_id": null,
+                            "count": { "$sum": 1_i64 },
+                            "sampleIds": {
+                                "$firstN": { "input": { "$toString": "$_id" }, "n": sample_size as i64 }
+                            }
+                        }
+                    },
+                ],
+            );
+        }
+
+        // Timestamps of the wrong type or in the future fall outside any date range, so the
+        // scan covers those documents as well as the requested range
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "$or": [
+                        { "timestamp": { "$gte": start_bson, "$lte": end_bson } },
+                        { "timestamp": { "$gt": now_bson } },
+                        { "timestamp": { "$not": { "$type": "date" } } }
+                    ]
+                }
+            },
+            doc! { "$facet": facets },
+        ];
+
+        let mut cursor = self
+            .collection
+            .aggregate(self.restrict(pipeline))
+            .allow_disk_use(true)
+            .await?;
+
+        use futures_util::stream::StreamExt;
+        let doc = match cursor.next().await {
+            Some(result) => result?,
+            None => Document::new(),
+        };
+
+        let first_of = |key: &str| match doc.get_array(key).ok().and_then(|values| values.first()) {
+            Some(Bson::Document(result)) => Some(result.clone()),
+            _ => None,
+        };
+        let documents_scanned = first_of("scanned")
+            .map(|scanned| bson_number(scanned.get("count")) as u32)
+            .unwrap_or(0);
+
+        let mut issues = Vec::new();
+        for (index, check) in checks.into_iter().enumerate() {
+            let Some(result) = first_of(&format!("check{}", index)) else {
+                continue;
+            };
+            issues.push(DataQualityIssue {
+                problem: check.problem,
+                field: check.field.map(str::to_string),
+                document_count: bson_number(result.get("count")) as u32,
+                sample_ids: result
+                    .get_array("sampleIds")
+                    .map(|ids| {
+                        ids.iter()
+                            .filter_map(|id| id.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+        issues.sort_by_key(|issue| std::cmp::Reverse(issue.document_count));
+
+        Ok(DataQualityReport {
+            period_start: start_date.format("%Y-%m-%d").to_string(),
+            period_end: end_date.format("%Y-%m-%d").to_string(),
+            checked_at: now,
+            documents_scanned,
+            issues,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn full_day_window_covers_whole_days_only() {
+        let today = day("2025-03-01");
+        let refreshed_through = day("2025-02-27");
+
+        // Midnight to the last millisecond of a day takes in both end days
+        assert_eq!(
+            full_day_window(
+                at("2025-01-01T00:00:00Z"),
+                at("2025-01-31T23:59:59.999Z"),
+                refreshed_through,
+                today
+            ),
+            Some((day("2025-01-01"), day("2025-01-31")))
+        );
+        // Midnight at the end is the start of a day that has barely begun
+        assert_eq!(
+            full_day_window(
+                at("2025-01-01T00:00:00Z"),
+                at("2025-02-01T00:00:00Z"),
+                refreshed_through,
+                today
+            ),
+            Some((day("2025-01-01"), day("2025-01-31")))
+        );
+        // Partial first and last days are left to the raw records
+        assert_eq!(
+            full_day_window(
+                at("2025-01-01T00:00:00.001Z"),
+                at("2025-01-31T23:59:59.998Z"),
+                refreshed_through,
+                today
+            ),
+            Some((day("2025-01-02"), day("2025-01-30")))
+        );
+    }
+
+    #[test]
+    fn full_day_window_stops_at_the_watermark_and_yesterday() {
+        let start = at("2025-02-01T00:00:00Z");
+        let end = at("2025-03-31T23:59:59.999Z");
+
+        assert_eq!(
+            full_day_window(start, end, day("2025-02-10"), day("2025-03-15")),
+            Some((day("2025-02-01"), day("2025-02-10")))
+        );
+        assert_eq!(
+            full_day_window(start, end, day("2025-03-20"), day("2025-03-15")),
+            Some((day("2025-02-01"), day("2025-03-14")))
+        );
+        assert_eq!(
+            full_day_window(start, end, day("2025-01-31"), day("2025-03-15")),
+            None
+        );
+    }
+
+    #[test]
+    fn full_day_window_is_empty_within_a_single_partial_day() {
+        let refreshed_through = day("2025-02-27");
+        let today = day("2025-03-01");
+
+        assert_eq!(
+            full_day_window(
+                at("2025-01-05T06:00:00Z"),
+                at("2025-01-05T18:00:00Z"),
+                refreshed_through,
+                today
+            ),
+            None
+        );
+        assert_eq!(
+            full_day_window(
+                at("2025-01-05T06:00:00Z"),
+                at("2025-01-06T18:00:00Z"),
+                refreshed_through,
+                today
+            ),
+            None
+        );
+        assert_eq!(
+            full_day_window(
+                at("2025-01-05T00:00:00Z"),
+                at("2025-01-05T23:59:59.999Z"),
+                refreshed_through,
+                today
+            ),
+            Some((day("2025-01-05"), day("2025-01-05")))
+        );
+    }
+
+    fn legacy_document(id: &str) -> Document {
+        doc! {
+            "_id": id,
+            "timestamp": mongodb::bson::DateTime::from_millis(at("2025-01-05T10:00:00Z").timestamp_millis()),
+            "meterNumber": "M-1",
+            "address": "1 North Road",
+            "community": "North",
+            "customerName": "Customer M-1",
+            "token": "TOKEN-1",
+            "tariff": 0.5,
+            "amount": 20_i32,
+            "kwh": 40.0,
+            "userId": "u1",
+            "vendingStation": "Central",
+            "fixedCharge": 1_i64,
+            "transactionId": "7",
+            "remainingCredit": 5.0,
+        }
+    }
+
+    #[test]
+    fn salvage_keeps_fields_of_the_expected_type() {
+        let mut document = legacy_document("r1");
+        document.remove("meterNumber");
+        document.insert("customerName", 42_i32);
+        document.insert("amount", "20.00");
+        document.insert("transactionId", 7_i32);
+
+        let record = salvage_record(&document).unwrap();
+        assert_eq!(record.id, "r1");
+        assert_eq!(record.timestamp, at("2025-01-05T10:00:00Z"));
+        assert_eq!(record.meter_number, None);
+        assert_eq!(record.customer_name, None);
+        assert_eq!(record.amount, None);
+        assert_eq!(record.transaction_id, None);
+        assert_eq!(record.community.as_deref(), Some("North"));
+        assert_eq!(record.kwh, Some(40.0));
+        // Integer amounts are read as numbers like doubles
+        assert_eq!(record.fixed_charge, Some(1.0));
+    }
+
+    #[test]
+    fn salvage_needs_a_date_timestamp() {
+        let mut document = legacy_document("r1");
+        document.insert("timestamp", "2025-01-05T10:00:00Z");
+        assert!(salvage_record(&document).is_none());
+
+        document.remove("timestamp");
+        assert!(salvage_record(&document).is_none());
+
+        // An ObjectId is reported by its hex string
+        let mut document = legacy_document("r2");
+        let object_id = mongodb::bson::oid::ObjectId::new();
+        document.insert("_id", object_id);
+        assert_eq!(salvage_record(&document).unwrap().id, object_id.to_hex());
+    }
+
+    #[test]
+    fn decode_reports_partial_and_skipped_documents_by_id() {
+        let mut no_meter = legacy_document("no-meter");
+        no_meter.remove("meterNumber");
+        let mut numeric_meter = legacy_document("numeric-meter");
+        numeric_meter.insert("meterNumber", 1001_i32);
+        let mut string_amount = legacy_document("string-amount");
+        string_amount.insert("amount", "20.00");
+        let mut no_timestamp = legacy_document("no-timestamp");
+        no_timestamp.remove("timestamp");
+        let mut text_timestamp = legacy_document("text-timestamp");
+        text_timestamp.insert("timestamp", "yesterday");
+
+        let mut records = Vec::new();
+        let mut decode_report = DecodeReport::default();
+        for document in [
+            legacy_document("clean"),
+            no_meter,
+            numeric_meter,
+            string_amount,
+            no_timestamp,
+            text_timestamp,
+        ] {
+            decode_document(document, &mut records, &mut decode_report);
+        }
+
+        let ids: Vec<&str> = records.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["clean", "no-meter", "numeric-meter", "string-amount"]
+        );
+        // A missing descriptive field is not malformed, only one of the wrong type
+        assert_eq!(records[1].meter_number, None);
+        assert_eq!(records[2].meter_number, None);
+        assert_eq!(records[2].amount, Some(20.0));
+        assert_eq!(records[3].meter_number.as_deref(), Some("M-1"));
+        assert_eq!(records[3].amount, None);
+        assert_eq!(decode_report.partially_decoded, 2);
+        assert_eq!(
+            decode_report.partially_decoded_ids,
+            vec!["numeric-meter", "string-amount"]
+        );
+        assert_eq!(decode_report.skipped_documents, 2);
+        assert_eq!(
+            decode_report.skipped_ids,
+            vec!["no-timestamp", "text-timestamp"]
+        );
+    }
+
+    #[test]
+    fn decode_report_lists_a_bounded_number_of_ids() {
+        let mut records = Vec::new();
+        let mut decode_report = DecodeReport::default();
+        for index in 0..MAX_REPORTED_IDS + 5 {
+            let mut document = legacy_document(&format!("r{}", index));
+            document.remove("timestamp");
+            decode_document(document, &mut records, &mut decode_report);
+        }
+
+        assert!(records.is_empty());
+        assert_eq!(
+            decode_report.skipped_documents as usize,
+            MAX_REPORTED_IDS + 5
+        );
+        assert_eq!(decode_report.skipped_ids.len(), MAX_REPORTED_IDS);
+        assert_eq!(decode_report.skipped_ids[0], "r0");
+    }
 }
@@ -0,0 +1,706 @@
+use crate::error::AppError;
+use crate::model::{
+    AccessScope, CreditRunout, DailySummary, DataQualityReport, InactiveMeter, IntegrityReport,
+    KpiReport, Leaderboard, LeaderboardDimension, LeaderboardMetric, MeterAmountBaseline,
+    MeterBehaviour, OperatorShift, SummarySeriesOptions, TransactionSequenceEntry, VendingHeatmap,
+    VendingRecord, VendingRecordBatch, VendingStationSummary, VendingStatistics, VendingSummary,
+};
+use crate::repositories::{InMemoryVendingRecordRepository, VendingRecordRepository};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Vending record columns, with the timestamp as UTC milliseconds in both dialects
+#[derive(Debug, FromRow)]
+struct SqlVendingRecord {
+    pub id: String,
+    pub timestamp_ms: i64,
+    pub meter_number: Option<String>,
+    pub address: Option<String>,
+    pub community: Option<String>,
+    pub customer_name: Option<String>,
+    pub token: Option<String>,
+    pub tariff: Option<f64>,
+    pub amount: Option<f64>,
+    pub kwh: Option<f64>,
+    pub user_id: Option<String>,
+    pub vending_station: Option<String>,
+    pub fixed_charge: Option<f64>,
+    pub transaction_id: Option<String>,
+    pub remaining_credit: Option<f64>,
+}
+
+impl From<SqlVendingRecord> for VendingRecord {
+    fn from(sql_record: SqlVendingRecord) -> Self {
+        VendingRecord {
+            id: sql_record.id,
+            timestamp: DateTime::from_timestamp_millis(sql_record.timestamp_ms).unwrap_or_default(),
+            meter_number: sql_record.meter_number,
+            address: sql_record.address,
+            community: sql_record.community,
+            customer_name: sql_record.customer_name,
+            token: sql_record.token,
+            tariff: sql_record.tariff,
+            amount: sql_record.amount,
+            kwh: sql_record.kwh,
+            user_id: sql_record.user_id,
+            vending_station: sql_record.vending_station,
+            fixed_charge: sql_record.fixed_charge,
+            transaction_id: sql_record.transaction_id,
+            remaining_credit: sql_record.remaining_credit,
+        }
+    }
+}
+
+// Per-station daily totals with their running totals and moving averages
+#[derive(Debug, FromRow)]
+struct SqlDailySummary {
+    pub vending_station: String,
+    pub date: String,
+    pub total_transactions: i64,
+    pub total_amount: f64,
+    pub total_kwh: f64,
+    pub cumulative_amount: f64,
+    pub cumulative_kwh: f64,
+    pub amount_avg_7d: f64,
+    pub amount_avg_30d: f64,
+    pub kwh_avg_7d: f64,
+    pub kwh_avg_30d: f64,
+}
+
+// Records in [$1, $2] (either bound optional) plus, when $3 is set, records after $3, limited to
+// the stations ($4) and communities ($5) in scope when those JSON lists are set
+const POSTGRES_RECORDS_QUERY: &str = r#"
+SELECT id, (EXTRACT(EPOCH FROM timestamp) * 1000)::BIGINT AS timestamp_ms,
+       meter_number, address, community, customer_name, token, tariff, amount, kwh,
+       user_id, vending_station, fixed_charge, transaction_id, remaining_credit
+FROM vending_records
+WHERE ((($1::timestamptz IS NULL OR timestamp >= $1) AND ($2::timestamptz IS NULL OR timestamp <= $2))
+       OR timestamp > $3::timestamptz)
+  AND ($4::text IS NULL OR vending_station IN (SELECT jsonb_array_elements_text($4::jsonb)))
+  AND ($5::text IS NULL OR community IN (SELECT jsonb_array_elements_text($5::jsonb)))
+ORDER BY timestamp
+"#;
+
+const SQLITE_RECORDS_QUERY: &str = r#"
+SELECT id, timestamp_ms,
+       meter_number, address, community, customer_name, token, tariff, amount, kwh,
+       user_id, vending_station, fixed_charge, transaction_id, remaining_credit
+FROM vending_records
+WHERE (((?1 IS NULL OR timestamp_ms >= ?1) AND (?2 IS NULL OR timestamp_ms <= ?2))
+       OR timestamp_ms > ?3)
+  AND (?4 IS NULL OR vending_station IN (SELECT value FROM json_each(?4)))
+  AND (?5 IS NULL OR community IN (SELECT value FROM json_each(?5)))
+ORDER BY timestamp_ms
+"#;
+
+// Records since $1 of the meters that made at least $4 purchases in that time and none since $5,
+// with the scope lists ($2, $3) working as in the records query
+const POSTGRES_INACTIVE_RECORDS_QUERY: &str = r#"
+WITH history AS (
+    SELECT * FROM vending_records
+    WHERE timestamp >= $1
+      AND ($2::text IS NULL OR vending_station IN (SELECT jsonb_array_elements_text($2::jsonb)))
+      AND ($3::text IS NULL OR community IN (SELECT jsonb_array_elements_text($3::jsonb)))
+)
+SELECT id, (EXTRACT(EPOCH FROM timestamp) * 1000)::BIGINT AS timestamp_ms,
+       meter_number, address, community, customer_name, token, tariff, amount, kwh,
+       user_id, vending_station, fixed_charge, transaction_id, remaining_credit
+FROM history
+WHERE meter_number IN (
+    SELECT meter_number FROM history
+    WHERE meter_number <> ''
+    GROUP BY meter_number
+    HAVING COUNT(*) >= $4 AND MAX(timestamp) < $5
+)
+ORDER BY timestamp
+"#;
+
+const SQLITE_INACTIVE_RECORDS_QUERY: &str = r#"
+WITH history AS (
+    SELECT * FROM vending_records
+    WHERE timestamp_ms >= ?1
+      AND (?2 IS NULL OR vending_station IN (SELECT value FROM json_each(?2)))
+      AND (?3 IS NULL OR community IN (SELECT value FROM json_each(?3)))
+)
+SELECT id, timestamp_ms,
+       meter_number, address, community, customer_name, token, tariff, amount, kwh,
+       user_id, vending_station, fixed_charge, transaction_id, remaining_credit
+FROM history
+WHERE meter_number IN (
+    SELECT meter_number FROM history
+    WHERE meter_number <> ''
+    GROUP BY meter_number
+    HAVING COUNT(*) >= ?4 AND MAX(timestamp_ms) < ?5
+)
+ORDER BY timestamp_ms
+"#;
+
+// Meters that vended in [$2, $3] and also before $1, with the scope lists ($4, $5) working as in
+// the records query
+const POSTGRES_RETURNING_METERS_QUERY: &str = r#"
+SELECT DISTINCT meter_number
+FROM vending_records
+WHERE timestamp < $1
+  AND ($4::text IS NULL OR vending_station IN (SELECT jsonb_array_elements_text($4::jsonb)))
+  AND ($5::text IS NULL OR community IN (SELECT jsonb_array_elements_text($5::jsonb)))
+  AND meter_number IN (
+      SELECT meter_number FROM vending_records
+      WHERE timestamp >= $2 AND timestamp <= $3
+        AND ($4::text IS NULL OR vending_station IN (SELECT jsonb_array_elements_text($4::jsonb)))
+        AND ($5::text IS NULL OR community IN (SELECT jsonb_array_elements_text($5::jsonb)))
+  )
+"#;
+
+const SQLITE_RETURNING_METERS_QUERY: &str = r#"
+SELECT DISTINCT meter_number
+FROM vending_records
+WHERE timestamp_ms < ?1
+  AND (?4 IS NULL OR vending_station IN (SELECT value FROM json_each(?4)))
+  AND (?5 IS NULL OR community IN (SELECT value FROM json_each(?5)))
+  AND meter_number IN (
+      SELECT meter_number FROM vending_records
+      WHERE timestamp_ms >= ?2 AND timestamp_ms <= ?3
+        AND (?4 IS NULL OR vending_station IN (SELECT value FROM json_each(?4)))
+        AND (?5 IS NULL OR community IN (SELECT value FROM json_each(?5)))
+  )
+"#;
+
+// Daily totals per station and UTC date. Moving averages cover calendar days, so days without
+// vends count as zero, and are shortened at the start of the period ($3). The scope lists ($4, $5)
+// work as in the records query.
+const POSTGRES_SUMMARY_QUERY: &str = r#"
+WITH daily AS (
+    SELECT COALESCE(vending_station, 'Unknown') AS vending_station,
+           (timestamp AT TIME ZONE 'UTC')::date AS day,
+           COUNT(*) AS total_transactions,
+           COALESCE(SUM(amount), 0)::DOUBLE PRECISION AS total_amount,
+           COALESCE(SUM(kwh), 0)::DOUBLE PRECISION AS total_kwh
+    FROM vending_records
+    WHERE timestamp >= $1 AND timestamp <= $2
+      AND ($4::text IS NULL OR vending_station IN (SELECT jsonb_array_elements_text($4::jsonb)))
+      AND ($5::text IS NULL OR community IN (SELECT jsonb_array_elements_text($5::jsonb)))
+    GROUP BY 1, 2
+)
+SELECT vending_station,
+       to_char(day, 'YYYY-MM-DD') AS date,
+       total_transactions,
+       total_amount,
+       total_kwh,
+       SUM(total_amount) OVER station_days AS cumulative_amount,
+       SUM(total_kwh) OVER station_days AS cumulative_kwh,
+       SUM(total_amount) OVER last_7_days / LEAST(7, day - $3::date + 1) AS amount_avg_7d,
+       SUM(total_amount) OVER last_30_days / LEAST(30, day - $3::date + 1) AS amount_avg_30d,
+       SUM(total_kwh) OVER last_7_days / LEAST(7, day - $3::date + 1) AS kwh_avg_7d,
+       SUM(total_kwh) OVER last_30_days / LEAST(30, day - $3::date + 1) AS kwh_avg_30d
+FROM daily
+WINDOW station_days AS (PARTITION BY vending_station ORDER BY day ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW),
+       last_7_days AS (PARTITION BY vending_station ORDER BY day RANGE BETWEEN INTERVAL '6 days' PRECEDING AND CURRENT ROW),
+       last_30_days AS (PARTITION BY vending_station ORDER BY day RANGE BETWEEN INTERVAL '29 days' PRECEDING AND CURRENT ROW)
+ORDER BY vending_station, day
+"#;
+
+// Same as the PostgreSQL summary, with days numbered since the Unix epoch
+const SQLITE_SUMMARY_QUERY: &str = r#"
+WITH daily AS (
+    SELECT COALESCE(vending_station, 'Unknown') AS vending_station,
+           timestamp_ms / 86400000 AS day,
+           COUNT(*) AS total_transactions,
+           CAST(COALESCE(SUM(amount), 0) AS REAL) AS total_amount,
+           CAST(COALESCE(SUM(kwh), 0) AS REAL) AS total_kwh
+    FROM vending_records
+    WHERE timestamp_ms >= ?1 AND timestamp_ms <= ?2
+      AND (?4 IS NULL OR vending_station IN (SELECT value FROM json_each(?4)))
+      AND (?5 IS NULL OR community IN (SELECT value FROM json_each(?5)))
+    GROUP BY 1, 2
+)
+SELECT vending_station,
+       strftime('%Y-%m-%d', day * 86400, 'unixepoch') AS date,
+       total_transactions,
+       total_amount,
+       total_kwh,
+       SUM(total_amount) OVER station_days AS cumulative_amount,
+       SUM(total_kwh) OVER station_days AS cumulative_kwh,
+       SUM(total_amount) OVER last_7_days / MIN(7, day - ?3 + 1) AS amount_avg_7d,
+       SUM(total_amount) OVER last_30_days / MIN(30, day - ?3 + 1) AS amount_avg_30d,
+       SUM(total_kwh) OVER last_7_days / MIN(7, day - ?3 + 1) AS kwh_avg_7d,
+       SUM(total_kwh) OVER last_30_days / MIN(30, day - ?3 + 1) AS kwh_avg_30d
+FROM daily
+WINDOW station_days AS (PARTITION BY vending_station ORDER BY day ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW),
+       last_7_days AS (PARTITION BY vending_station ORDER BY day RANGE BETWEEN 6 PRECEDING AND CURRENT ROW),
+       last_30_days AS (PARTITION BY vending_station ORDER BY day RANGE BETWEEN 29 PRECEDING AND CURRENT ROW)
+ORDER BY vending_station, day
+"#;
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+// Scope lists are bound as JSON arrays so one parameter holds any number of values
+fn scope_list(values: &Option<Vec<String>>) -> Option<String> {
+    values
+        .as_ref()
+        .map(|values| serde_json::Value::from(values.clone()).to_string())
+}
+
+#[derive(Clone)]
+enum SqlPool {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+/// Vending records stored in PostgreSQL or SQLite. The summary is aggregated in SQL; the
+/// other reports load the records they need and aggregate them like the in-memory backend,
+/// with the KPIs and inactive meters narrowing that load in SQL first.
+///
+/// Every method except the summary therefore holds all of the caller's rows in the requested
+/// range in memory at once, so memory use grows with the range and the size of the table. Keep
+/// this backend to modest data sets or bound the ranges that clients can request.
+#[derive(Clone)]
+pub struct SqlVendingRecordRepository {
+    pool: SqlPool,
+    scope: AccessScope,
+}
+
+impl SqlVendingRecordRepository {
+    /// Connect to PostgreSQL and apply any pending schema migrations
+    pub async fn connect_postgres(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let pool = PgPoolOptions::new().connect(url).await?;
+        sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+        Ok(Self {
+            pool: SqlPool::Postgres(pool),
+            scope: AccessScope::default(),
+        })
+    }
+
+    /// Open (or create) a SQLite database and apply any pending schema migrations
+    pub async fn connect_sqlite(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+        Ok(Self {
+            pool: SqlPool::Sqlite(pool),
+            scope: AccessScope::default(),
+        })
+    }
+
+    // Records with start <= timestamp <= end (either bound optional), plus any after `after`
+    async fn load_records(
+        &self,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        after: Option<DateTime<Utc>>,
+    ) -> Result<InMemoryVendingRecordRepository, AppError> {
+        let stations = scope_list(&self.scope.vending_stations);
+        let communities = scope_list(&self.scope.communities);
+        let rows: Vec<SqlVendingRecord> = match &self.pool {
+            SqlPool::Postgres(pool) => {
+                sqlx::query_as(POSTGRES_RECORDS_QUERY)
+                    .bind(start)
+                    .bind(end)
+                    .bind(after)
+                    .bind(stations)
+                    .bind(communities)
+                    .fetch_all(pool)
+                    .await?
+            }
+            SqlPool::Sqlite(pool) => {
+                sqlx::query_as(SQLITE_RECORDS_QUERY)
+                    .bind(start.map(|start| start.timestamp_millis()))
+                    .bind(end.map(|end| end.timestamp_millis()))
+                    .bind(after.map(|after| after.timestamp_millis()))
+                    .bind(stations)
+                    .bind(communities)
+                    .fetch_all(pool)
+                    .await?
+            }
+        };
+        Ok(InMemoryVendingRecordRepository::new(
+            rows.into_iter().map(VendingRecord::from).collect(),
+        ))
+    }
+
+    // Test fixtures are written straight to the table
+    #[cfg(test)]
+    pub async fn insert_records(&self, records: &[VendingRecord]) -> Result<(), AppError> {
+        for record in records {
+            match &self.pool {
+                SqlPool::Postgres(pool) => {
+                    sqlx::query(
+                        "INSERT INTO vending_records (id, timestamp, meter_number, address, \
+                         community, customer_name, token, tariff, amount, kwh, user_id, \
+                         vending_station, fixed_charge, transaction_id, remaining_credit) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+                    )
+                    .bind(&record.id)
+                    .bind(record.timestamp)
+                    .bind(&record.meter_number)
+                    .bind(&record.address)
+                    .bind(&record.community)
+                    .bind(&record.customer_name)
+                    .bind(&record.token)
+                    .bind(record.tariff)
+                    .bind(record.amount)
+                    .bind(record.kwh)
+                    .bind(&record.user_id)
+                    .bind(&record.vending_station)
+                    .bind(record.fixed_charge)
+                    .bind(&record.transaction_id)
+                    .bind(record.remaining_credit)
+                    .execute(pool)
+                    .await?;
+                }
+                SqlPool::Sqlite(pool) => {
+                    sqlx::query(
+                        "INSERT INTO vending_records (id, timestamp_ms, meter_number, address, \
+                         community, customer_name, token, tariff, amount, kwh, user_id, \
+                         vending_station, fixed_charge, transaction_id, remaining_credit) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    )
+                    .bind(&record.id)
+                    .bind(record.timestamp.timestamp_millis())
+                    .bind(&record.meter_number)
+                    .bind(&record.address)
+                    .bind(&record.community)
+                    .bind(&record.customer_name)
+                    .bind(&record.token)
+                    .bind(record.tariff)
+                    .bind(record.amount)
+                    .bind(record.kwh)
+                    .bind(&record.user_id)
+                    .bind(&record.vending_station)
+                    .bind(record.fixed_charge)
+                    .bind(&record.transaction_id)
+                    .bind(record.remaining_credit)
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<InMemoryVendingRecordRepository, AppError> {
+        self.load_records(Some(start), Some(end), None).await
+    }
+}
+
+// Fold the per-station daily rows, ordered by station and date, into the summary
+fn summary_from_rows(
+    rows: Vec<SqlDailySummary>,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    series: SummarySeriesOptions,
+) -> VendingSummary {
+    let period_start = start_date.format("%Y-%m-%d").to_string();
+    let period_end = end_date.format("%Y-%m-%d").to_string();
+    let mut summary = VendingSummary {
+        period_start: period_start.clone(),
+        period_end: period_end.clone(),
+        ..VendingSummary::default()
+    };
+
+    for row in rows {
+        let daily = DailySummary {
+            date: row.date,
+            total_transactions: row.total_transactions as u32,
+            total_amount: row.total_amount,
+            total_kwh: row.total_kwh,
+            cumulative_amount: series.cumulative.then_some(row.cumulative_amount),
+            cumulative_kwh: series.cumulative.then_some(row.cumulative_kwh),
+            amount_avg_7d: series.moving_averages.then_some(row.amount_avg_7d),
+            amount_avg_30d: series.moving_averages.then_some(row.amount_avg_30d),
+            kwh_avg_7d: series.moving_averages.then_some(row.kwh_avg_7d),
+            kwh_avg_30d: series.moving_averages.then_some(row.kwh_avg_30d),
+        };
+
+        let stations = &mut summary.vending_station_summaries;
+        if stations
+            .last()
+            .is_none_or(|station| station.vending_station != row.vending_station)
+        {
+            stations.push(VendingStationSummary {
+                vending_station: row.vending_station,
+                period_start: period_start.clone(),
+                period_end: period_end.clone(),
+                ..VendingStationSummary::default()
+            });
+        }
+        let station = stations
+            .last_mut()
+            .expect("a station summary was just added");
+        station.total_transactions += daily.total_transactions;
+        station.total_amount += daily.total_amount;
+        station.total_kwh += daily.total_kwh;
+        summary.total_transactions += daily.total_transactions;
+        summary.total_amount += daily.total_amount;
+        summary.total_kwh += daily.total_kwh;
+        station.daily_summaries.push(daily);
+    }
+
+    summary
+}
+
+#[async_trait]
+impl VendingRecordRepository for SqlVendingRecordRepository {
+    fn with_scope(&self, scope: AccessScope) -> Arc<dyn VendingRecordRepository> {
+        Arc::new(Self {
+            scope,
+            ..self.clone()
+        })
+    }
+
+    async fn get_vending_records(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<VendingRecordBatch, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_vending_records(start_date, end_date)
+            .await
+    }
+
+    async fn get_vending_summary(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        series: SummarySeriesOptions,
+    ) -> Result<VendingSummary, AppError> {
+        let stations = scope_list(&self.scope.vending_stations);
+        let communities = scope_list(&self.scope.communities);
+        let rows: Vec<SqlDailySummary> = match &self.pool {
+            SqlPool::Postgres(pool) => {
+                sqlx::query_as(POSTGRES_SUMMARY_QUERY)
+                    .bind(start_date)
+                    .bind(end_date)
+                    .bind(start_date.date_naive())
+                    .bind(stations)
+                    .bind(communities)
+                    .fetch_all(pool)
+                    .await?
+            }
+            SqlPool::Sqlite(pool) => {
+                sqlx::query_as(SQLITE_SUMMARY_QUERY)
+                    .bind(start_date.timestamp_millis())
+                    .bind(end_date.timestamp_millis())
+                    .bind(start_date.timestamp_millis().div_euclid(MILLIS_PER_DAY))
+                    .bind(stations)
+                    .bind(communities)
+                    .fetch_all(pool)
+                    .await?
+            }
+        };
+
+        Ok(summary_from_rows(rows, start_date, end_date, series))
+    }
+
+    async fn get_vending_statistics(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        histogram_buckets: u32,
+    ) -> Result<VendingStatistics, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_vending_statistics(start_date, end_date, histogram_buckets)
+            .await
+    }
+
+    async fn get_leaderboard(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        dimension: LeaderboardDimension,
+        metric: LeaderboardMetric,
+        limit: u32,
+    ) -> Result<Leaderboard, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_leaderboard(start_date, end_date, dimension, metric, limit)
+            .await
+    }
+
+    async fn get_inactive_meters(
+        &self,
+        history_start: DateTime<Utc>,
+        inactive_since: DateTime<Utc>,
+        min_purchases: u32,
+    ) -> Result<Vec<InactiveMeter>, AppError> {
+        // Only the records of meters that have gone quiet are loaded
+        let stations = scope_list(&self.scope.vending_stations);
+        let communities = scope_list(&self.scope.communities);
+        let rows: Vec<SqlVendingRecord> = match &self.pool {
+            SqlPool::Postgres(pool) => {
+                sqlx::query_as(POSTGRES_INACTIVE_RECORDS_QUERY)
+                    .bind(history_start)
+                    .bind(stations)
+                    .bind(communities)
+                    .bind(min_purchases as i64)
+                    .bind(inactive_since)
+                    .fetch_all(pool)
+                    .await?
+            }
+            SqlPool::Sqlite(pool) => {
+                sqlx::query_as(SQLITE_INACTIVE_RECORDS_QUERY)
+                    .bind(history_start.timestamp_millis())
+                    .bind(stations)
+                    .bind(communities)
+                    .bind(min_purchases as i64)
+                    .bind(inactive_since.timestamp_millis())
+                    .fetch_all(pool)
+                    .await?
+            }
+        };
+        InMemoryVendingRecordRepository::new(rows.into_iter().map(VendingRecord::from).collect())
+            .get_inactive_meters(history_start, inactive_since, min_purchases)
+            .await
+    }
+
+    async fn get_meter_behaviour(
+        &self,
+        as_of: DateTime<Utc>,
+        window_days: u32,
+        meter_number: Option<&str>,
+    ) -> Result<Vec<MeterBehaviour>, AppError> {
+        let prior_start = as_of - chrono::Duration::days(2 * window_days as i64);
+        self.load_between(prior_start, as_of)
+            .await?
+            .get_meter_behaviour(as_of, window_days, meter_number)
+            .await
+    }
+
+    async fn get_credit_runout(
+        &self,
+        history_start: DateTime<Utc>,
+        as_of: DateTime<Utc>,
+    ) -> Result<Vec<CreditRunout>, AppError> {
+        self.load_between(history_start, as_of)
+            .await?
+            .get_credit_runout(history_start, as_of)
+            .await
+    }
+
+    async fn get_operator_shifts(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        user_id: Option<&str>,
+    ) -> Result<Vec<OperatorShift>, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_operator_shifts(start_date, end_date, timezone, user_id)
+            .await
+    }
+
+    async fn get_meter_amount_baselines(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<MeterAmountBaseline>, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_meter_amount_baselines(start_date, end_date)
+            .await
+    }
+
+    async fn get_integrity_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        window_seconds: u32,
+    ) -> Result<IntegrityReport, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_integrity_report(start_date, end_date, window_seconds)
+            .await
+    }
+
+    async fn get_transaction_sequence(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<TransactionSequenceEntry>, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_transaction_sequence(start_date, end_date)
+            .await
+    }
+
+    async fn get_heatmap(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        vending_station: Option<&str>,
+        community: Option<&str>,
+    ) -> Result<VendingHeatmap, AppError> {
+        self.load_between(start_date, end_date)
+            .await?
+            .get_heatmap(start_date, end_date, timezone, vending_station, community)
+            .await
+    }
+
+    async fn get_kpis(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<KpiReport, AppError> {
+        // Records from the previous period on, plus which meters active in the period vended
+        // earlier still, so they don't count as new customers
+        let previous_start = start_date - (end_date - start_date);
+        let stations = scope_list(&self.scope.vending_stations);
+        let communities = scope_list(&self.scope.communities);
+        let returning_meters: Vec<String> = match &self.pool {
+            SqlPool::Postgres(pool) => {
+                sqlx::query_scalar(POSTGRES_RETURNING_METERS_QUERY)
+                    .bind(previous_start)
+                    .bind(start_date)
+                    .bind(end_date)
+                    .bind(stations)
+                    .bind(communities)
+                    .fetch_all(pool)
+                    .await?
+            }
+            SqlPool::Sqlite(pool) => {
+                sqlx::query_scalar(SQLITE_RETURNING_METERS_QUERY)
+                    .bind(previous_start.timestamp_millis())
+                    .bind(start_date.timestamp_millis())
+                    .bind(end_date.timestamp_millis())
+                    .bind(stations)
+                    .bind(communities)
+                    .fetch_all(pool)
+                    .await?
+            }
+        };
+        Ok(self
+            .load_between(previous_start, end_date)
+            .await?
+            .kpi_report(
+                start_date,
+                end_date,
+                &returning_meters.into_iter().collect(),
+            ))
+    }
+
+    async fn get_data_quality_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        now: DateTime<Utc>,
+        sample_size: u32,
+    ) -> Result<DataQualityReport, AppError> {
+        // Future timestamps are reported wherever they fall
+        self.load_records(Some(start_date), Some(end_date), Some(now))
+            .await?
+            .get_data_quality_report(start_date, end_date, now, sample_size)
+            .await
+    }
+}
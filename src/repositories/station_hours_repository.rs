@@ -0,0 +1,14 @@
+use crate::error::AppError;
+use crate::model::StationOpeningHours;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait StationHoursRepository: Send + Sync {
+    //Get the opening hours of every station that has them configured
+    async fn get_opening_hours(&self) -> Result<Vec<StationOpeningHours>, AppError>;
+    //Set a station's opening hours, replacing any earlier ones
+    async fn save_opening_hours(
+        &self,
+        opening_hours: StationOpeningHours,
+    ) -> Result<StationOpeningHours, AppError>;
+}
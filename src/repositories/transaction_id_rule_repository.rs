@@ -0,0 +1,11 @@
+use crate::error::AppError;
+use crate::model::TransactionIdRule;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait TransactionIdRuleRepository: Send + Sync {
+    //Get the transaction ID parsing rule of every station that has one configured
+    async fn get_rules(&self) -> Result<Vec<TransactionIdRule>, AppError>;
+    //Set a station's transaction ID parsing rule, replacing any earlier one
+    async fn save_rule(&self, rule: TransactionIdRule) -> Result<TransactionIdRule, AppError>;
+}
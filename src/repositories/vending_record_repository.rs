@@ -1,19 +1,117 @@
-use crate::model::{VendingRecord, VendingSummary};
+use crate::error::AppError;
+use crate::model::{
+    AccessScope, CreditRunout, DataQualityReport, InactiveMeter, IntegrityReport, KpiReport,
+    Leaderboard, LeaderboardDimension, LeaderboardMetric, MeterAmountBaseline, MeterBehaviour,
+    OperatorShift, SummarySeriesOptions, TransactionSequenceEntry, VendingHeatmap,
+    VendingRecordBatch, VendingStatistics, VendingSummary,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use std::error::Error;
+use std::sync::Arc;
 
 #[async_trait]
 pub trait VendingRecordRepository: Send + Sync {
-    //Get all vending records by date range
+    //The same repository with every query restricted to the given stations and communities
+    fn with_scope(&self, scope: AccessScope) -> Arc<dyn VendingRecordRepository>;
+    //Get all vending records by date range, skipping or partially decoding malformed documents
     async fn get_vending_records(
         &self,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
-    ) -> Result<Vec<VendingRecord>, Box<dyn Error>>;
+    ) -> Result<VendingRecordBatch, AppError>;
+    //Get per-station daily totals by date range, optionally with running totals and moving averages
     async fn get_vending_summary(
         &self,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
-    ) -> Result<VendingSummary, Box<dyn Error>>;
+        series: SummarySeriesOptions,
+    ) -> Result<VendingSummary, AppError>;
+    //Get amount/kWh distribution statistics by date range
+    async fn get_vending_statistics(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        histogram_buckets: u32,
+    ) -> Result<VendingStatistics, AppError>;
+    //Get the top N meters, customers, communities or stations by date range
+    async fn get_leaderboard(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        dimension: LeaderboardDimension,
+        metric: LeaderboardMetric,
+        limit: u32,
+    ) -> Result<Leaderboard, AppError>;
+    //Get meters with at least min_purchases since history_start whose last vend is before inactive_since
+    async fn get_inactive_meters(
+        &self,
+        history_start: DateTime<Utc>,
+        inactive_since: DateTime<Utc>,
+        min_purchases: u32,
+    ) -> Result<Vec<InactiveMeter>, AppError>;
+    //Get per-meter purchase behaviour, comparing the window_days before as_of with the window_days before that
+    async fn get_meter_behaviour(
+        &self,
+        as_of: DateTime<Utc>,
+        window_days: u32,
+        meter_number: Option<&str>,
+    ) -> Result<Vec<MeterBehaviour>, AppError>;
+    //Estimate when each meter bought from since history_start runs out of credit
+    async fn get_credit_runout(
+        &self,
+        history_start: DateTime<Utc>,
+        as_of: DateTime<Utc>,
+    ) -> Result<Vec<CreditRunout>, AppError>;
+    //Get per-operator daily cash-up totals by date range, with days bucketed in the given timezone.
+    //Only the given operator when user_id is set. A vend is voided when the terminal set
+    //`voided: true` on it or its amount is negative.
+    async fn get_operator_shifts(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        user_id: Option<&str>,
+    ) -> Result<Vec<OperatorShift>, AppError>;
+    //Get each meter's mean and spread of purchase amounts by date range
+    async fn get_meter_amount_baselines(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<MeterAmountBaseline>, AppError>;
+    //Find duplicate tokens, duplicate transaction IDs and same meter/amount vends within window_seconds
+    async fn get_integrity_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        window_seconds: u32,
+    ) -> Result<IntegrityReport, AppError>;
+    //Get every vend's station, transaction ID and timestamp by date range
+    async fn get_transaction_sequence(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<Vec<TransactionSequenceEntry>, AppError>;
+    //Get transaction count and amount per local day of week and hour, optionally for one station or community
+    async fn get_heatmap(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        timezone: &str,
+        vending_station: Option<&str>,
+        community: Option<&str>,
+    ) -> Result<VendingHeatmap, AppError>;
+    //Get revenue, energy and customer KPIs per community and overall, compared with the equally long previous period
+    async fn get_kpis(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Result<KpiReport, AppError>;
+    //Count documents per data quality problem by date range, with sample ids; future timestamps are checked against now
+    async fn get_data_quality_report(
+        &self,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        now: DateTime<Utc>,
+        sample_size: u32,
+    ) -> Result<DataQualityReport, AppError>;
 }